@@ -1,4 +1,7 @@
-use crate::{Event, EventPayload, ProfilingData, Timestamp};
+use crate::call_tree::integer_parent;
+use crate::histogram::QueryHistograms;
+use crate::parallel::{map_partitions, partition_by_thread, AnalysisOptions, ThreadEvents};
+use crate::{Counter, Event, EventPayload, ProfilingData, Timestamp};
 use measureme::rustc::*;
 use rustc_hash::{FxHashMap, FxHashSet};
 use serde::{Deserialize, Serialize};
@@ -112,200 +115,738 @@ impl ProfilingData {
     ///
     /// In this case when we encounter `e2`, the stack is `[e1, e3, e4]`, and both
     /// `e4` and `e3` need to be popped in the same step.
-    pub fn perform_analysis(self) -> AnalysisResults {
-        struct PerThreadState<'a> {
-            stack: Vec<Event<'a>>,
-            start: SystemTime,
-            end: SystemTime,
-        }
+    pub fn perform_analysis(&self) -> AnalysisResults {
+        self.perform_analysis_with_options(&AnalysisOptions::default())
+    }
+
+    /// Like [`ProfilingData::perform_analysis`], but allows for controlling how
+    /// many threads are used. With more than one thread, the events of each
+    /// profiled thread are decoded and analyzed on one of the workers and the
+    /// partial results are merged afterwards. The merged results are exactly
+    /// the same as the results of a sequential run.
+    pub fn perform_analysis_with_options(&self, options: &AnalysisOptions) -> AnalysisResults {
+        let recording_overhead = if options.correct_overhead {
+            self.metadata().recording_overhead.map(Duration::from_nanos)
+        } else {
+            None
+        };
+
+        // Only used with more than one thread, see below.
+        let mut last_cache_hit_count_events = FxHashMap::<String, usize>::default();
+
+        let state = if options.num_threads <= 1 {
+            let mut state = AnalysisState::new(
+                options,
+                recording_overhead,
+                CacheHitCountEvents::SeenSoFar(Default::default()),
+            );
+
+            for (event_index, current_event) in self.iter_full().enumerate().rev() {
+                state.process_event(event_index, current_event);
+            }
+
+            state
+        } else {
+            let partitions = partition_by_thread(self, options.num_threads);
+
+            // The sequential version finds out about `QUERY_CACHE_HIT_COUNT_EVENT_KIND`
+            // events as it walks backwards over all events. The workers only see
+            // the events of their threads, so they need to know about the events
+            // of all threads up front, which they collect in a first pass.
+            let partial_last_indices = map_partitions(&partitions, |threads| {
+                self.last_cache_hit_count_events(threads)
+            });
+            for partial in partial_last_indices {
+                for (label, event_index) in partial {
+                    let last_index = last_cache_hit_count_events.entry(label).or_default();
+                    *last_index = (*last_index).max(event_index);
+                }
+            }
+
+            let partial_states = map_partitions(&partitions, |threads| {
+                let mut state = AnalysisState::new(
+                    options,
+                    recording_overhead,
+                    CacheHitCountEvents::LastIndex(&last_cache_hit_count_events),
+                );
+
+                for thread in threads {
+                    for &event_index in thread.event_indices.iter().rev() {
+                        state.process_event(event_index, self.decode_full_event(event_index));
+                    }
+                }
+
+                state
+            });
 
-        let mut query_data = FxHashMap::<String, QueryData>::default();
-        let mut artifact_sizes = BTreeMap::<Cow<'_, str>, ArtifactSize>::default();
-        let mut threads = FxHashMap::<_, PerThreadState<'_>>::default();
-
-        let mut record_event_data = |label: &Cow<'_, str>, f: &dyn Fn(&mut QueryData)| {
-            if let Some(data) = query_data.get_mut(&label[..]) {
-                f(data);
-            } else {
-                let mut data = QueryData::new(label.clone().into_owned());
-                f(&mut data);
-                query_data.insert(label.clone().into_owned(), data);
+            let mut partial_states = partial_states.into_iter();
+            let mut state = partial_states.next().unwrap();
+            for partial_state in partial_states {
+                state.merge(partial_state);
             }
+
+            state
         };
 
-        // Remember if we found a `QUERY_CACHE_HIT_COUNT_EVENT_KIND` event at the end of the event
-        // log for a given query. If yes, we need to avoid incrementing the query cache counts
-        // if we encounter `QUERY_CACHE_HIT_EVENT_KIND`, to avoid double counting.
-        let mut query_cache_hit_counts_found: FxHashSet<String> = Default::default();
-
-        for current_event in self.iter_full().rev() {
-            match current_event.payload {
-                EventPayload::Timestamp(Timestamp::Instant(_)) => {
-                    if &current_event.event_kind[..] == QUERY_CACHE_HIT_EVENT_KIND {
-                        record_event_data(&current_event.label, &|data| {
-                            if !query_cache_hit_counts_found.contains(current_event.label.as_ref()) {
-                                data.number_of_cache_hits += 1;
-                            }
-                        });
+        state.into_results(self.metadata().counter.clone())
+    }
+
+    /// The index of the last `QUERY_CACHE_HIT_COUNT_EVENT_KIND` event for each
+    /// label among the events of the given threads.
+    fn last_cache_hit_count_events(&self, threads: &[ThreadEvents]) -> FxHashMap<String, usize> {
+        let mut last_indices = FxHashMap::default();
+
+        for thread in threads {
+            for &event_index in &thread.event_indices {
+                let payload = self.decode_lightweight_event(event_index).payload;
+                if !payload.is_integer() {
+                    continue;
+                }
+
+                let event = self.decode_full_event(event_index);
+                if event.event_kind == QUERY_CACHE_HIT_COUNT_EVENT_KIND {
+                    // Events are visited in order, so the last one wins.
+                    last_indices.insert(event.label.into_owned(), event_index);
+                }
+            }
+        }
+
+        last_indices
+    }
+}
+
+/// Keeps track of which `QUERY_CACHE_HIT_COUNT_EVENT_KIND` events come after
+/// a given event, so that we don't count cache hits twice.
+enum CacheHitCountEvents<'m> {
+    /// The labels of all such events encountered so far. Only correct if
+    /// *all* events are processed in reverse order.
+    SeenSoFar(FxHashSet<String>),
+    /// The index of the last such event for each label.
+    LastIndex(&'m FxHashMap<String, usize>),
+}
+
+impl CacheHitCountEvents<'_> {
+    fn found_after(&self, label: &str, event_index: usize) -> bool {
+        match self {
+            CacheHitCountEvents::SeenSoFar(labels) => labels.contains(label),
+            CacheHitCountEvents::LastIndex(last_indices) => last_indices
+                .get(label)
+                .is_some_and(|&last_index| last_index > event_index),
+        }
+    }
+
+    fn record(&mut self, label: &str) {
+        if let CacheHitCountEvents::SeenSoFar(labels) = self {
+            labels.insert(label.to_owned());
+        }
+    }
+}
+
+struct PerThreadState<'a> {
+    stack: Vec<Event<'a>>,
+    /// The direct children of each event on `stack` processed so far.
+    children: Vec<Children>,
+    start: SystemTime,
+    end: SystemTime,
+}
+
+/// The state of `perform_analysis` for some subset of the profiled threads.
+/// All events of these threads must be fed into `process_event` in reverse
+/// order.
+struct AnalysisState<'a, 'm> {
+    query_data: QueryDataMaps,
+    artifact_sizes: BTreeMap<Cow<'a, str>, ArtifactSize>,
+    /// The histograms by event kind and label, if requested via
+    /// `AnalysisOptions::histograms`.
+    histograms: Option<FxHashMap<String, FxHashMap<String, QueryHistograms>>>,
+    threads: FxHashMap<u32, PerThreadState<'a>>,
+    /// The values of integer events other than artifact sizes and cache hit
+    /// counts, by event kind and label, together with their event index.
+    integer_samples: FxHashMap<(String, String), Vec<(usize, IntegerSample)>>,
+    /// Integer events waiting for the event preceding them on their thread,
+    /// see `attach_pending_integers`.
+    pending_integers: FxHashMap<u32, Vec<(usize, Event<'a>)>>,
+    /// The cost of recording an interval event, which is subtracted from the
+    /// self-time of its parent, if requested via
+    /// `AnalysisOptions::correct_overhead`.
+    recording_overhead: Option<Duration>,
+    // Remember if we found a `QUERY_CACHE_HIT_COUNT_EVENT_KIND` event at the end of the event
+    // log for a given query. If yes, we need to avoid incrementing the query cache counts
+    // if we encounter `QUERY_CACHE_HIT_EVENT_KIND`, to avoid double counting.
+    query_cache_hit_counts_found: CacheHitCountEvents<'m>,
+}
+
+/// The query data collected so far, by event kind and label and, if requested
+/// via `AnalysisOptions::by_args`, also by event kind, label and arguments.
+#[derive(Default)]
+struct QueryDataMaps {
+    by_label: FxHashMap<String, FxHashMap<String, QueryData>>,
+    by_args: Option<FxHashMap<ArgsKey, QueryData>>,
+}
+
+/// The event kind, label and arguments of an event.
+type ArgsKey = (String, String, Vec<String>);
+
+impl QueryDataMaps {
+    fn record(&mut self, event: &Event<'_>, f: &dyn Fn(&mut QueryData)) {
+        let event_kind = query_data_event_kind(&event.event_kind);
+
+        let by_label = match self.by_label.get_mut(event_kind) {
+            Some(by_label) => by_label,
+            None => self.by_label.entry(event_kind.to_owned()).or_default(),
+        };
+
+        if let Some(data) = by_label.get_mut(&event.label[..]) {
+            f(data);
+        } else {
+            let mut data = QueryData::with_event_kind(event_kind, event.label.to_string());
+            f(&mut data);
+            by_label.insert(event.label.to_string(), data);
+        }
+
+        if let Some(by_args) = &mut self.by_args {
+            let key = (
+                event_kind.to_owned(),
+                event.label.to_string(),
+                event
+                    .additional_data
+                    .iter()
+                    .map(|arg| arg.to_string())
+                    .collect(),
+            );
+
+            f(by_args
+                .entry(key)
+                .or_insert_with_key(|(event_kind, label, _)| {
+                    QueryData::with_event_kind(event_kind, label.clone())
+                }));
+        }
+    }
+}
+
+/// The number and summed up duration of the direct children of an interval
+/// event.
+#[derive(Clone, Copy, Default)]
+struct Children {
+    count: u32,
+    time: Duration,
+}
+
+/// Records what can only be known once all direct children of an interval
+/// event have been processed: the correction of its self-time for the cost of
+/// recording its children, if requested, and its histograms.
+fn finish_interval(
+    query_data: &mut QueryDataMaps,
+    histograms: Option<&mut FxHashMap<String, FxHashMap<String, QueryHistograms>>>,
+    recording_overhead: Option<Duration>,
+    event: &Event<'_>,
+    children: Children,
+) {
+    let self_time = event.duration().unwrap().saturating_sub(children.time);
+
+    // Recording each child took time that the self-time of this invocation
+    // still includes. We can't remove more than the invocation's self-time,
+    // e.g. if the overhead was overestimated.
+    let overhead = recording_overhead
+        .map_or(Duration::ZERO, |overhead| overhead * children.count)
+        .min(self_time);
+
+    if overhead > Duration::ZERO {
+        query_data.record(event, &|data| data.self_time -= overhead);
+    }
+
+    if let Some(histograms) = histograms {
+        record_histograms(histograms, event, self_time - overhead);
+    }
+}
+
+/// Records the self-time and time of an interval event.
+fn record_histograms(
+    histograms: &mut FxHashMap<String, FxHashMap<String, QueryHistograms>>,
+    event: &Event<'_>,
+    self_time: Duration,
+) {
+    // Hashing happens within the query, so it isn't an invocation of its own.
+    if event.event_kind == INCREMENTAL_RESULT_HASHING_EVENT_KIND {
+        return;
+    }
+
+    let event_kind = query_data_event_kind(&event.event_kind);
+    let by_label = histograms.entry(event_kind.to_owned()).or_default();
+
+    if !by_label.contains_key(&event.label[..]) {
+        let query_histograms = QueryHistograms {
+            label: event.label.to_string(),
+            event_kind: event_kind.to_owned(),
+            ..Default::default()
+        };
+        by_label.insert(event.label.to_string(), query_histograms);
+    }
+
+    let query_histograms = by_label.get_mut(&event.label[..]).unwrap();
+    let duration = event.duration().unwrap();
+    query_histograms.self_time.record(self_time);
+    query_histograms.time.record(duration);
+}
+
+/// Returns the event kind under which the data of an event is recorded. Events
+/// that are part of executing a query, like `QueryBlocked` or
+/// `IncrementalLoadResult`, share the label of the query and are recorded as
+/// part of it.
+pub(crate) fn query_data_event_kind(event_kind: &str) -> &str {
+    match event_kind {
+        QUERY_BLOCKED_EVENT_KIND
+        | QUERY_CACHE_HIT_EVENT_KIND
+        | QUERY_CACHE_HIT_COUNT_EVENT_KIND
+        | INCREMENTAL_LOAD_RESULT_EVENT_KIND
+        | INCREMENTAL_RESULT_HASHING_EVENT_KIND => QUERY_EVENT_KIND,
+        _ => event_kind,
+    }
+}
+
+impl<'a, 'm> AnalysisState<'a, 'm> {
+    fn new(
+        options: &AnalysisOptions,
+        recording_overhead: Option<Duration>,
+        query_cache_hit_counts_found: CacheHitCountEvents<'m>,
+    ) -> Self {
+        AnalysisState {
+            query_data: QueryDataMaps {
+                by_label: Default::default(),
+                by_args: options.by_args.then(Default::default),
+            },
+            artifact_sizes: Default::default(),
+            histograms: options.histograms.then(Default::default),
+            threads: Default::default(),
+            integer_samples: Default::default(),
+            pending_integers: Default::default(),
+            recording_overhead,
+            query_cache_hit_counts_found,
+        }
+    }
+
+    fn process_event(&mut self, event_index: usize, current_event: Event<'a>) {
+        if let EventPayload::Timestamp(timestamp) = &current_event.payload {
+            self.attach_pending_integers(current_event.thread_id, Some(timestamp.end()));
+        }
+
+        let query_data = &mut self.query_data;
+
+        match current_event.payload {
+            EventPayload::Timestamp(Timestamp::Instant(_)) => {
+                if &current_event.event_kind[..] == QUERY_CACHE_HIT_EVENT_KIND {
+                    let count_found = self
+                        .query_cache_hit_counts_found
+                        .found_after(&current_event.label, event_index);
+
+                    query_data.record(&current_event, &|data| {
+                        if !count_found {
+                            data.number_of_cache_hits += 1;
+                        }
+                    });
+                }
+            }
+            EventPayload::Timestamp(Timestamp::Interval { start, end }) => {
+                // This is an interval event
+                let thread = self
+                    .threads
+                    .entry(current_event.thread_id)
+                    .or_insert_with(|| PerThreadState {
+                        stack: Vec::new(),
+                        children: Vec::new(),
+                        start,
+                        end,
+                    });
+
+                // Pop all events from the stack that are not parents of the
+                // current event.
+                while let Some(current_top) = thread.stack.last().cloned() {
+                    if current_top.contains(&current_event) {
+                        break;
                     }
+
+                    thread.stack.pop();
+                    finish_interval(
+                        query_data,
+                        self.histograms.as_mut(),
+                        self.recording_overhead,
+                        &current_top,
+                        thread.children.pop().unwrap(),
+                    );
                 }
-                EventPayload::Timestamp(Timestamp::Interval { start, end }) => {
-                    // This is an interval event
-                    let thread =
-                        threads
-                            .entry(current_event.thread_id)
-                            .or_insert_with(|| PerThreadState {
-                                stack: Vec::new(),
-                                start,
-                                end,
-                            });
-
-                    // Pop all events from the stack that are not parents of the
-                    // current event.
-                    while let Some(current_top) = thread.stack.last().cloned() {
-                        if current_top.contains(&current_event) {
-                            break;
+
+                let current_event_duration = current_event.duration().unwrap();
+
+                if let Some(children) = thread.children.last_mut() {
+                    children.count += 1;
+                    children.time += current_event_duration;
+                }
+
+                // If there is something on the stack, subtract the current
+                // interval from it.
+                if let Some(current_top) = thread.stack.last() {
+                    query_data.record(current_top, &|data| {
+                        match &current_top.event_kind[..] {
+                            QUERY_EVENT_KIND | GENERIC_ACTIVITY_EVENT_KIND => {
+                                data.self_time -= current_event_duration;
+                            }
+                            INCREMENTAL_RESULT_HASHING_EVENT_KIND => {
+                                // We are within hashing something. If we now encounter something
+                                // within that event (like the nested "intern-the-dep-node" event)
+                                // then we don't want to attribute that to the hashing time.
+                                data.self_time -= current_event_duration;
+                                data.incremental_hashing_time -= current_event_duration;
+                            }
+                            INCREMENTAL_LOAD_RESULT_EVENT_KIND => {
+                                data.self_time -= current_event_duration;
+                                data.incremental_load_time -= current_event_duration;
+                            }
+                            _ => {
+                                // Data sources other than rustc will use their own event kinds so
+                                // just treat this like a GENERIC_ACTIVITY except that we don't
+                                // track cache misses since those may not apply to all data sources.
+                                data.self_time -= current_event_duration;
+                            }
                         }
+                    });
+                }
 
-                        thread.stack.pop();
+                // Update counters for the current event
+                match &current_event.event_kind[..] {
+                    QUERY_EVENT_KIND | GENERIC_ACTIVITY_EVENT_KIND => {
+                        query_data.record(&current_event, &|data| {
+                            data.self_time += current_event_duration;
+                            data.time += current_event_duration;
+                            data.number_of_cache_misses += 1;
+                            data.invocation_count += 1;
+                        });
                     }
 
-                    let current_event_duration = current_event.duration().unwrap();
-
-                    // If there is something on the stack, subtract the current
-                    // interval from it.
-                    if let Some(current_top) = thread.stack.last() {
-                        record_event_data(
-                            &current_top.label,
-                            &|data| match &current_top.event_kind[..] {
-                                QUERY_EVENT_KIND | GENERIC_ACTIVITY_EVENT_KIND => {
-                                    data.self_time -= current_event_duration;
-                                }
-                                INCREMENTAL_RESULT_HASHING_EVENT_KIND => {
-                                    // We are within hashing something. If we now encounter something
-                                    // within that event (like the nested "intern-the-dep-node" event)
-                                    // then we don't want to attribute that to the hashing time.
-                                    data.self_time -= current_event_duration;
-                                    data.incremental_hashing_time -= current_event_duration;
-                                }
-                                INCREMENTAL_LOAD_RESULT_EVENT_KIND => {
-                                    data.self_time -= current_event_duration;
-                                    data.incremental_load_time -= current_event_duration;
-                                }
-                                _ => {
-                                    // Data sources other than rustc will use their own event kinds so
-                                    // just treat this like a GENERIC_ACTIVITY except that we don't
-                                    // track cache misses since those may not apply to all data sources.
-                                    data.self_time -= current_event_duration;
-                                }
-                            },
-                        );
+                    QUERY_BLOCKED_EVENT_KIND => {
+                        query_data.record(&current_event, &|data| {
+                            data.self_time += current_event_duration;
+                            data.time += current_event_duration;
+                            data.blocked_time += current_event_duration;
+                            // We don't increment invocation_count here, because the query
+                            // was actually a cache hit, just a blocked one.
+                            // Rustc also records a cache hit when this happens.
+                        });
                     }
 
-                    // Update counters for the current event
-                    match &current_event.event_kind[..] {
-                        QUERY_EVENT_KIND | GENERIC_ACTIVITY_EVENT_KIND => {
-                            record_event_data(&current_event.label, &|data| {
-                                data.self_time += current_event_duration;
-                                data.time += current_event_duration;
-                                data.number_of_cache_misses += 1;
-                                data.invocation_count += 1;
-                            });
-                        }
+                    INCREMENTAL_LOAD_RESULT_EVENT_KIND => {
+                        query_data.record(&current_event, &|data| {
+                            data.self_time += current_event_duration;
+                            data.time += current_event_duration;
+                            data.incremental_load_time += current_event_duration;
+                            data.incremental_load_count += 1;
+                        });
+                    }
 
-                        QUERY_BLOCKED_EVENT_KIND => {
-                            record_event_data(&current_event.label, &|data| {
-                                data.self_time += current_event_duration;
-                                data.time += current_event_duration;
-                                data.blocked_time += current_event_duration;
-                                // We don't increment invocation_count here, because the query
-                                // was actually a cache hit, just a blocked one.
-                                // Rustc also records a cache hit when this happens.
-                            });
-                        }
+                    INCREMENTAL_RESULT_HASHING_EVENT_KIND => {
+                        query_data.record(&current_event, &|data| {
+                            // Don't add to data.time since this event happens
+                            // within the query itself which is already contributing
+                            // to data.time
+                            data.self_time += current_event_duration;
+                            data.incremental_hashing_time += current_event_duration;
+                        });
+                    }
 
-                        INCREMENTAL_LOAD_RESULT_EVENT_KIND => {
-                            record_event_data(&current_event.label, &|data| {
-                                data.self_time += current_event_duration;
-                                data.time += current_event_duration;
-                                data.incremental_load_time += current_event_duration;
-                            });
-                        }
+                    _ => {
+                        // Data sources other than rustc will use their own event kinds so just
+                        // treat this like a GENERIC_ACTIVITY except that we don't track cache
+                        // misses since those may not apply to all data sources.
+                        query_data.record(&current_event, &|data| {
+                            data.self_time += current_event_duration;
+                            data.time += current_event_duration;
+                            data.invocation_count += 1;
+                        });
+                    }
+                };
 
-                        INCREMENTAL_RESULT_HASHING_EVENT_KIND => {
-                            record_event_data(&current_event.label, &|data| {
-                                // Don't add to data.time since this event happens
-                                // within the query itself which is already contributing
-                                // to data.time
-                                data.self_time += current_event_duration;
-                                data.incremental_hashing_time += current_event_duration;
-                            });
-                        }
+                // Update the start and end times for thread
+                thread.start = std::cmp::min(thread.start, start);
+                thread.end = std::cmp::max(thread.end, end);
 
-                        _ => {
-                            // Data sources other than rustc will use their own event kinds so just
-                            // treat this like a GENERIC_ACTIVITY except that we don't track cache
-                            // misses since those may not apply to all data sources.
-                            record_event_data(&current_event.label, &|data| {
-                                data.self_time += current_event_duration;
-                                data.time += current_event_duration;
-                                data.invocation_count += 1;
-                            });
-                        }
-                    };
+                // Bring the stack up-to-date
+                thread.stack.push(current_event);
+                thread.children.push(Children::default());
+            }
+            EventPayload::Integer(value) => {
+                match current_event.event_kind.as_ref() {
+                    ARTIFACT_SIZE_EVENT_KIND => {
+                        // Dedup artifact size events according to their label
+                        self.artifact_sizes
+                            .entry(current_event.label.clone())
+                            .or_insert_with(|| ArtifactSize::new(current_event.label.into_owned()))
+                            .add_value(value);
+                    }
+                    // Aggregated query cache hit counts
+                    QUERY_CACHE_HIT_COUNT_EVENT_KIND => {
+                        query_data.record(&current_event, &|data| {
+                            // rustc produces aggregated cache hits per **query invocation**,
+                            // so a query + specific instances of arguments.
+                            // We need to deduplicate the aggregated counts here to sum them up
+                            // for individual queries, according to the event label.
+                            data.number_of_cache_hits += value as usize;
+                        });
+                        self.query_cache_hit_counts_found
+                            .record(&current_event.label);
+                    }
+                    _ => {
+                        self.pending_integers
+                            .entry(current_event.thread_id)
+                            .or_default()
+                            .push((event_index, current_event));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Records the pending integer events of a thread, at the end of the
+    /// interval they were recorded in, see `integer_parent`.
+    fn attach_pending_integers(&mut self, thread_id: u32, preceding_event_end: Option<SystemTime>) {
+        if self.pending_integers.is_empty() {
+            return;
+        }
 
-                    // Update the start and end times for thread
-                    thread.start = std::cmp::min(thread.start, start);
-                    thread.end = std::cmp::max(thread.end, end);
+        let Some(pending_integers) = self.pending_integers.remove(&thread_id) else {
+            return;
+        };
 
-                    // Bring the stack up-to-date
-                    thread.stack.push(current_event)
+        let time = self.threads.get(&thread_id).and_then(|thread| {
+            let timestamp = |event: &Event<'_>| event.payload.timestamp().unwrap();
+            let position = integer_parent(
+                &thread.stack,
+                |event| timestamp(event).start(),
+                preceding_event_end,
+            )?;
+            Some(timestamp(&thread.stack[position]).end())
+        });
+
+        for (event_index, event) in pending_integers {
+            let sample = IntegerSample {
+                time,
+                value: event.payload.integer().unwrap(),
+            };
+
+            self.integer_samples
+                .entry((event.event_kind.into_owned(), event.label.into_owned()))
+                .or_default()
+                .push((event_index, sample));
+        }
+    }
+
+    /// Merges the state of another, disjoint set of threads into this one.
+    fn merge(&mut self, other: AnalysisState<'a, 'm>) {
+        for (event_kind, other_by_label) in other.query_data.by_label {
+            let by_label = self.query_data.by_label.entry(event_kind).or_default();
+
+            for (label, data) in other_by_label {
+                match by_label.get_mut(&label) {
+                    Some(existing) => existing.add(data),
+                    None => {
+                        by_label.insert(label, data);
+                    }
                 }
-                EventPayload::Integer(value) => {
-                    match current_event.event_kind.as_ref() {
-                        ARTIFACT_SIZE_EVENT_KIND => {
-                            // Dedup artifact size events according to their label
-                            artifact_sizes
-                                .entry(current_event.label.clone())
-                                .or_insert_with(|| ArtifactSize::new(current_event.label.into_owned()))
-                                .add_value(value);
-                        }
-                        // Aggregated query cache hit counts
-                        QUERY_CACHE_HIT_COUNT_EVENT_KIND => {
-                            record_event_data(&current_event.label, &|data| {
-                                // rustc produces aggregated cache hits per **query invocation**,
-                                // so a query + specific instances of arguments.
-                                // We need to deduplicate the aggregated counts here to sum them up
-                                // for individual queries, according to the event label.
-                                data.number_of_cache_hits += value as usize;
-                            });
-                            query_cache_hit_counts_found.insert(current_event.label.into_owned());
+            }
+        }
+
+        if let (Some(by_args), Some(other_by_args)) =
+            (&mut self.query_data.by_args, other.query_data.by_args)
+        {
+            for (key, data) in other_by_args {
+                match by_args.get_mut(&key) {
+                    Some(existing) => existing.add(data),
+                    None => {
+                        by_args.insert(key, data);
+                    }
+                }
+            }
+        }
+
+        if let (Some(histograms), Some(other_histograms)) = (&mut self.histograms, other.histograms)
+        {
+            for (event_kind, other_by_label) in other_histograms {
+                let by_label = histograms.entry(event_kind).or_default();
+
+                for (label, other_histograms) in other_by_label {
+                    match by_label.get_mut(&label) {
+                        Some(existing) => existing.add(&other_histograms),
+                        None => {
+                            by_label.insert(label, other_histograms);
                         }
-                        _ => {}
                     }
                 }
             }
         }
 
-        let total_time = threads
+        for (key, samples) in other.integer_samples {
+            self.integer_samples.entry(key).or_default().extend(samples);
+        }
+
+        for (label, artifact_size) in other.artifact_sizes {
+            self.artifact_sizes
+                .entry(label)
+                .or_insert_with(|| ArtifactSize::new(artifact_size.label.clone()))
+                .add_value(artifact_size.value);
+        }
+
+        for (thread_id, thread) in other.threads {
+            let previous = self.threads.insert(thread_id, thread);
+            assert!(
+                previous.is_none(),
+                "thread {} was analyzed twice",
+                thread_id
+            );
+        }
+    }
+
+    fn into_results(mut self, counter: Counter) -> AnalysisResults {
+        let thread_ids: Vec<_> = self.pending_integers.keys().copied().collect();
+        for thread_id in thread_ids {
+            self.attach_pending_integers(thread_id, None);
+        }
+
+        // Events that are still on a stack have not been finished yet.
+        for thread in self.threads.values() {
+            for (event, &children) in thread.stack.iter().zip(&thread.children) {
+                finish_interval(
+                    &mut self.query_data,
+                    self.histograms.as_mut(),
+                    self.recording_overhead,
+                    event,
+                    children,
+                );
+            }
+        }
+
+        let total_time = self
+            .threads
             .values()
             .map(|t| t.end.duration_since(t.start).unwrap())
             .sum();
 
+        let mut query_data: Vec<_> = self
+            .query_data
+            .by_label
+            .into_values()
+            .flat_map(|by_label| by_label.into_values())
+            .collect();
+        // Sort the results so that they don't depend on the order in which the
+        // events were processed.
+        query_data.sort_by(|a, b| (&a.label, &a.event_kind).cmp(&(&b.label, &b.event_kind)));
+
+        let mut query_data_by_args: Vec<_> = self
+            .query_data
+            .by_args
+            .unwrap_or_default()
+            .into_iter()
+            .map(|((_, _, args), data)| QueryArgsData { args, data })
+            .collect();
+        query_data_by_args.sort_by(|a, b| {
+            (&a.data.label, &a.data.event_kind, &a.args).cmp(&(
+                &b.data.label,
+                &b.data.event_kind,
+                &b.args,
+            ))
+        });
+
+        let mut histograms: Vec<_> = self
+            .histograms
+            .unwrap_or_default()
+            .into_values()
+            .flat_map(|by_label| by_label.into_values())
+            .collect();
+        histograms.sort_by(|a, b| (&a.label, &a.event_kind).cmp(&(&b.label, &b.event_kind)));
+
+        let mut integer_metrics: Vec<_> = self
+            .integer_samples
+            .into_iter()
+            .map(|((event_kind, label), samples)| IntegerMetric::new(event_kind, label, samples))
+            .collect();
+        integer_metrics.sort_by(|a, b| (&a.label, &a.event_kind).cmp(&(&b.label, &b.event_kind)));
+
         AnalysisResults {
-            query_data: query_data.drain().map(|(_, value)| value).collect(),
-            artifact_sizes: artifact_sizes.into_values().collect(),
+            event_kinds: EventKindData::from_query_data(&query_data),
+            integer_metrics,
+            recording_overhead: self.recording_overhead,
+            query_data,
+            query_data_by_args,
+            histograms,
+            artifact_sizes: self.artifact_sizes.into_values().collect(),
             total_time,
+            counter,
         }
     }
 }
 
 /// A collection data for an entire rustc invocation
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
 pub struct AnalysisResults {
+    /// The query data by label and event kind, sorted by label and event kind.
     pub query_data: Vec<QueryData>,
+    /// The query data rolled up by event kind, sorted by event kind.
+    #[serde(default)]
+    pub event_kinds: Vec<EventKindData>,
+    /// The query data for each combination of label and arguments (e.g. the
+    /// query keys recorded with `-Z self-profile-events=query-keys`), sorted
+    /// by label and arguments. Only collected if `AnalysisOptions::by_args`
+    /// is set.
+    #[serde(default)]
+    pub query_data_by_args: Vec<QueryArgsData>,
+    /// The distributions of the self-time and time of the events of each
+    /// label and event kind, sorted by label and event kind. Only collected
+    /// if `AnalysisOptions::histograms` is set.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub histograms: Vec<QueryHistograms>,
     pub artifact_sizes: Vec<ArtifactSize>,
+    /// The values of integer events other than artifact sizes and cache hit
+    /// counts, e.g. custom metrics recorded with `record_integer_event`,
+    /// sorted by label and event kind.
+    #[serde(default)]
+    pub integer_metrics: Vec<IntegerMetric>,
+    /// The cost of recording an interval event that was subtracted from the
+    /// self-time of its parent. Only set if `AnalysisOptions::correct_overhead`
+    /// is set and the profile contains a measurement of it.
+    #[serde(default)]
+    pub recording_overhead: Option<Duration>,
     pub total_time: Duration,
+    /// The counter the profile was recorded with. All durations above are
+    /// really deltas of this counter, see `Counter::format_duration`.
+    #[serde(default)]
+    pub counter: Counter,
+}
+
+impl AnalysisResults {
+    /// Returns the `n` argument combinations of the given event kind and label
+    /// with the highest self-time, in descending order. Requires the analysis
+    /// to have been run with `AnalysisOptions::by_args`.
+    pub fn top_args_by_self_time(
+        &self,
+        event_kind: &str,
+        label: &str,
+        n: usize,
+    ) -> Vec<&QueryArgsData> {
+        let start = self.query_data_by_args.partition_point(|args_data| {
+            (
+                args_data.data.label.as_str(),
+                args_data.data.event_kind.as_str(),
+            ) < (label, event_kind)
+        });
+
+        let mut args_data: Vec<_> = self.query_data_by_args[start..]
+            .iter()
+            .take_while(|args_data| {
+                args_data.data.label == label && args_data.data.event_kind == event_kind
+            })
+            .collect();
+
+        args_data.sort_by_key(|args_data| std::cmp::Reverse(args_data.data.self_time));
+        args_data.truncate(n);
+        args_data
+    }
 }
 
 // These are currently only needed for testing
@@ -324,9 +865,14 @@ impl AnalysisResults {
 }
 
 /// Data related to profiling a specific rustc query
-#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
 pub struct QueryData {
     pub label: String,
+    /// The kind of the events, e.g. `Query` or `GenericActivity`. Events that
+    /// are part of executing a query, like `QueryBlocked`, are counted as
+    /// `Query`.
+    #[serde(default)]
+    pub event_kind: String,
     pub time: Duration,
     pub self_time: Duration,
     pub number_of_cache_misses: usize,
@@ -337,6 +883,9 @@ pub struct QueryData {
     pub invocation_count: usize,
     pub blocked_time: Duration,
     pub incremental_load_time: Duration,
+    /// How many times the result was loaded from the incremental cache.
+    #[serde(default)]
+    pub incremental_load_count: usize,
     pub incremental_hashing_time: Duration,
 }
 
@@ -347,9 +896,119 @@ impl QueryData {
             ..Self::default()
         }
     }
+
+    pub fn with_event_kind(event_kind: &str, label: String) -> QueryData {
+        QueryData {
+            label,
+            event_kind: event_kind.to_owned(),
+            ..Self::default()
+        }
+    }
+
+    /// Adds up the data of two partial results for the same label.
+    pub(crate) fn add(&mut self, other: QueryData) {
+        let QueryData {
+            label: _,
+            event_kind: _,
+            time,
+            self_time,
+            number_of_cache_misses,
+            number_of_cache_hits,
+            invocation_count,
+            blocked_time,
+            incremental_load_time,
+            incremental_load_count,
+            incremental_hashing_time,
+        } = other;
+
+        self.time += time;
+        self.self_time += self_time;
+        self.number_of_cache_misses += number_of_cache_misses;
+        self.number_of_cache_hits += number_of_cache_hits;
+        self.invocation_count += invocation_count;
+        self.blocked_time += blocked_time;
+        self.incremental_load_time += incremental_load_time;
+        self.incremental_load_count += incremental_load_count;
+        self.incremental_hashing_time += incremental_hashing_time;
+    }
+
+    /// Divides all data by `n`, e.g. to turn a sum over `n` profiles into a
+    /// mean. Counts are rounded to the nearest integer.
+    pub(crate) fn divide(&mut self, n: usize) {
+        let QueryData {
+            label: _,
+            event_kind: _,
+            time,
+            self_time,
+            number_of_cache_misses,
+            number_of_cache_hits,
+            invocation_count,
+            blocked_time,
+            incremental_load_time,
+            incremental_load_count,
+            incremental_hashing_time,
+        } = self;
+
+        let divide_count = |count: &mut usize| *count = (*count + n / 2) / n;
+
+        *time /= n as u32;
+        *self_time /= n as u32;
+        divide_count(number_of_cache_misses);
+        divide_count(number_of_cache_hits);
+        divide_count(invocation_count);
+        *blocked_time /= n as u32;
+        *incremental_load_time /= n as u32;
+        divide_count(incremental_load_count);
+        *incremental_hashing_time /= n as u32;
+    }
+}
+
+/// The query data of all items of one event kind.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct EventKindData {
+    pub event_kind: String,
+    /// The sum of the times of all items. Items that (indirectly) call items
+    /// of the same kind are counted several times.
+    pub time: Duration,
+    pub self_time: Duration,
+    pub invocation_count: usize,
+}
+
+impl EventKindData {
+    /// Rolls up the given query data by event kind.
+    pub(crate) fn from_query_data(query_data: &[QueryData]) -> Vec<EventKindData> {
+        let mut event_kinds = BTreeMap::<&str, EventKindData>::new();
+
+        for data in query_data {
+            let event_kind_data =
+                event_kinds
+                    .entry(&data.event_kind)
+                    .or_insert_with(|| EventKindData {
+                        event_kind: data.event_kind.clone(),
+                        time: Duration::ZERO,
+                        self_time: Duration::ZERO,
+                        invocation_count: 0,
+                    });
+
+            event_kind_data.time += data.time;
+            event_kind_data.self_time += data.self_time;
+            event_kind_data.invocation_count += data.invocation_count;
+        }
+
+        event_kinds.into_values().collect()
+    }
+}
+
+/// The query data for invocations of a query or activity with specific
+/// arguments.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct QueryArgsData {
+    pub args: Vec<String>,
+    #[serde(flatten)]
+    pub data: QueryData,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct ArtifactSize {
     pub label: String,
     pub value: u64,
@@ -365,6 +1024,54 @@ impl ArtifactSize {
     }
 }
 
+/// The values of all integer events of one event kind and label.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct IntegerMetric {
+    pub event_kind: String,
+    pub label: String,
+    pub count: usize,
+    pub sum: u64,
+    pub min: u64,
+    pub max: u64,
+    /// The value that was recorded last.
+    pub last: u64,
+    /// All values in the order they were recorded.
+    pub series: Vec<IntegerSample>,
+}
+
+/// A single value of an [`IntegerMetric`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct IntegerSample {
+    /// The end of the innermost interval event in progress when the value was
+    /// recorded, if there was one.
+    pub time: Option<SystemTime>,
+    pub value: u64,
+}
+
+impl IntegerMetric {
+    fn new(
+        event_kind: String,
+        label: String,
+        mut samples: Vec<(usize, IntegerSample)>,
+    ) -> IntegerMetric {
+        samples.sort_by_key(|&(event_index, _)| event_index);
+        let series: Vec<_> = samples.into_iter().map(|(_, sample)| sample).collect();
+
+        let values = || series.iter().map(|sample| sample.value);
+
+        IntegerMetric {
+            event_kind,
+            label,
+            count: series.len(),
+            sum: values().fold(0, u64::saturating_add),
+            min: values().min().unwrap_or(0),
+            max: values().max().unwrap_or(0),
+            last: series.last().map_or(0, |sample| sample.value),
+            series,
+        }
+    }
+}
+
 #[rustfmt::skip]
 #[cfg(test)]
 mod tests {
@@ -641,6 +1348,46 @@ mod tests {
         assert_eq!(results.query_data_by_label("q1").time, Duration::from_nanos(230));
     }
 
+    #[test]
+    fn event_kinds() {
+        let mut b = ProfilingDataBuilder::new();
+
+        b.interval(GENERIC_ACTIVITY_EVENT_KIND, "a", 0, 100, 200, |b| {
+            b.interval(QUERY_EVENT_KIND, "a", 0, 110, 130, |_| {});
+            b.interval(QUERY_EVENT_KIND, "b", 0, 140, 150, |_| {});
+        });
+        b.interval(QUERY_BLOCKED_EVENT_KIND, "a", 1, 100, 150, |_| {});
+
+        let results = b.into_profiling_data().perform_analysis();
+
+        let query_data: Vec<_> = results
+            .query_data
+            .iter()
+            .map(|data| (&data.label[..], &data.event_kind[..], data.self_time))
+            .collect();
+
+        assert_eq!(query_data, vec![
+            ("a", GENERIC_ACTIVITY_EVENT_KIND, Duration::from_nanos(70)),
+            ("a", QUERY_EVENT_KIND, Duration::from_nanos(70)),
+            ("b", QUERY_EVENT_KIND, Duration::from_nanos(10)),
+        ]);
+
+        assert_eq!(results.event_kinds, vec![
+            EventKindData {
+                event_kind: GENERIC_ACTIVITY_EVENT_KIND.to_string(),
+                time: Duration::from_nanos(100),
+                self_time: Duration::from_nanos(70),
+                invocation_count: 1,
+            },
+            EventKindData {
+                event_kind: QUERY_EVENT_KIND.to_string(),
+                time: Duration::from_nanos(80),
+                self_time: Duration::from_nanos(80),
+                invocation_count: 2,
+            },
+        ]);
+    }
+
     #[test]
     fn query_incr_loading_time() {
         // T1: <---------------q1 (loading)----->
@@ -660,6 +1407,7 @@ mod tests {
 
         assert_eq!(results.query_data_by_label("q1").self_time, Duration::from_nanos(230));
         assert_eq!(results.query_data_by_label("q1").incremental_load_time, Duration::from_nanos(230));
+        assert_eq!(results.query_data_by_label("q1").incremental_load_count, 3);
         assert_eq!(results.query_data_by_label("q1").time, Duration::from_nanos(230));
     }
 
@@ -680,4 +1428,222 @@ mod tests {
         assert_eq!(results.artifact_size_by_label("artifact2").value, 50);
         assert_eq!(results.artifact_size_by_label("artifact2").label, "artifact2");
     }
+
+    #[test]
+    fn integer_metrics() {
+        let mut b = ProfilingDataBuilder::new();
+
+        b.integer("Allocations", "allocated_bytes", 0, 10);
+        b.interval(GENERIC_ACTIVITY_EVENT_KIND, "a", 0, 100, 400, |b| {
+            b.interval(QUERY_EVENT_KIND, "q1", 0, 110, 200, |_| {});
+            b.integer("Allocations", "allocated_bytes", 0, 30);
+            b.integer("Allocations", "allocated_bytes", 0, 20);
+            b.integer("Allocations", "live_bytes", 0, 5);
+        });
+        b.integer(ARTIFACT_SIZE_EVENT_KIND, "artifact", 0, 50);
+        b.integer(QUERY_CACHE_HIT_COUNT_EVENT_KIND, "q1", 0, 2);
+
+        let results = b.into_profiling_data().perform_analysis();
+
+        assert_eq!(results.integer_metrics.len(), 2);
+
+        let allocated = &results.integer_metrics[0];
+        assert_eq!((&allocated.event_kind[..], &allocated.label[..]), ("Allocations", "allocated_bytes"));
+        assert_eq!(allocated.count, 3);
+        assert_eq!(allocated.sum, 60);
+        assert_eq!((allocated.min, allocated.max, allocated.last), (10, 30, 20));
+
+        let start = SystemTime::UNIX_EPOCH;
+        let at = |nanos| Some(start + Duration::from_nanos(nanos));
+        let series: Vec<_> = allocated.series.iter().map(|sample| (sample.time, sample.value)).collect();
+        assert_eq!(series, vec![(None, 10), (at(400), 30), (at(400), 20)]);
+
+        let live = &results.integer_metrics[1];
+        assert_eq!(live.label, "live_bytes");
+        assert_eq!((live.count, live.sum, live.last), (1, 5, 5));
+    }
+
+    fn multi_threaded_profile() -> ProfilingData {
+        let mut b = ProfilingDataBuilder::new();
+
+        for thread_id in 0..5 {
+            let offset = thread_id as u64 * 7;
+
+            b.interval(GENERIC_ACTIVITY_EVENT_KIND, "a", thread_id, offset + 100, offset + 400, |b| {
+                b.interval(QUERY_EVENT_KIND, "q1", thread_id, offset + 110, offset + 200, |b| {
+                    b.instant(QUERY_CACHE_HIT_EVENT_KIND, "q2", thread_id, offset + 120);
+                    b.interval(INCREMENTAL_LOAD_RESULT_EVENT_KIND, "q2", thread_id, offset + 130, offset + 150, |_| {});
+                    b.interval(INCREMENTAL_RESULT_HASHING_EVENT_KIND, "q1", thread_id, offset + 160, offset + 190, |b| {
+                        b.interval(QUERY_EVENT_KIND, "q3", thread_id, offset + 170, offset + 180, |_| {});
+                    });
+                });
+                b.interval(QUERY_BLOCKED_EVENT_KIND, "q1", thread_id, offset + 210, offset + 250, |_| {});
+                b.instant(QUERY_CACHE_HIT_EVENT_KIND, "q3", thread_id, offset + 260);
+                b.integer(ARTIFACT_SIZE_EVENT_KIND, "artifact", thread_id, 10 + thread_id as u64);
+                b.integer("Allocations", "allocated_bytes", thread_id, 1000 + thread_id as u64);
+            });
+
+            // Aggregated cache hit counts for `q3` are recorded after the
+            // events of the first few threads, so only the cache hits after
+            // them should be counted.
+            if thread_id == 2 {
+                b.integer(QUERY_CACHE_HIT_COUNT_EVENT_KIND, "q3", 4, 100);
+            }
+        }
+
+        b.integer(QUERY_CACHE_HIT_COUNT_EVENT_KIND, "q2", 1, 50);
+        b.interval("CustomKind", "custom", 7, 0, 1000, |_| {});
+
+        b.into_profiling_data()
+    }
+
+    #[test]
+    fn parallel_analysis_matches_sequential() {
+        let sequential = multi_threaded_profile()
+            .perform_analysis_with_options(&AnalysisOptions { num_threads: 1, by_args: true, histograms: true, correct_overhead: false });
+
+        assert_eq!(sequential.query_data_by_label("q2").number_of_cache_hits, 50);
+        assert_eq!(sequential.query_data_by_label("q3").number_of_cache_hits, 102);
+        assert_eq!(sequential.artifact_size_by_label("artifact").value, 60);
+
+        for num_threads in 2..8 {
+            let parallel = multi_threaded_profile()
+                .perform_analysis_with_options(&AnalysisOptions { num_threads, by_args: true, histograms: true, correct_overhead: false });
+
+            assert_eq!(parallel, sequential);
+        }
+    }
+
+    fn nested_queries(mut b: ProfilingDataBuilder) -> ProfilingData {
+        b.interval(QUERY_EVENT_KIND, "q1", 0, 100, 200, |b| {
+            b.interval(QUERY_EVENT_KIND, "q2", 0, 110, 120, |_| {});
+            b.interval(QUERY_EVENT_KIND, "q2", 0, 130, 170, |b| {
+                b.interval(QUERY_EVENT_KIND, "q3", 0, 140, 142, |_| {});
+            });
+        });
+
+        b.into_profiling_data()
+    }
+
+    #[test]
+    fn recording_overhead() {
+        let options = AnalysisOptions { num_threads: 1, by_args: false, histograms: true, correct_overhead: true };
+
+        let data = nested_queries(ProfilingDataBuilder::with_recording_overhead(5));
+        let results = data.perform_analysis_with_options(&options);
+
+        assert_eq!(results.recording_overhead, Some(Duration::from_nanos(5)));
+        assert_eq!(results.query_data_by_label("q1").self_time, Duration::from_nanos(40));
+        assert_eq!(results.query_data_by_label("q2").self_time, Duration::from_nanos(43));
+        assert_eq!(results.query_data_by_label("q2").time, Duration::from_nanos(50));
+        assert_eq!(results.query_data_by_label("q3").self_time, Duration::from_nanos(2));
+        assert_eq!(results.histograms[1].self_time.max, Duration::from_nanos(33));
+
+        // Without a measurement in the profile, nothing is corrected.
+        let data = nested_queries(ProfilingDataBuilder::new());
+        let results = data.perform_analysis_with_options(&options);
+
+        assert_eq!(results.recording_overhead, None);
+        assert_eq!(results.query_data_by_label("q1").self_time, Duration::from_nanos(50));
+    }
+
+    #[test]
+    fn recording_overhead_per_invocation() {
+        let mut b = ProfilingDataBuilder::with_recording_overhead(5);
+
+        // The overhead of recording three children exceeds the self-time of
+        // this invocation, so its self-time only drops to zero.
+        b.interval(QUERY_EVENT_KIND, "q1", 0, 100, 130, |b| {
+            b.interval(QUERY_EVENT_KIND, "q2", 0, 101, 110, |_| {});
+            b.interval(QUERY_EVENT_KIND, "q2", 0, 111, 120, |_| {});
+            b.interval(QUERY_EVENT_KIND, "q2", 0, 121, 129, |_| {});
+        });
+        b.interval(QUERY_EVENT_KIND, "q1", 1, 100, 200, |b| {
+            b.interval(QUERY_EVENT_KIND, "q2", 1, 110, 120, |_| {});
+        });
+        let data = b.into_profiling_data();
+
+        let options = |num_threads| AnalysisOptions { num_threads, by_args: true, histograms: true, correct_overhead: true };
+        let sequential = data.perform_analysis_with_options(&options(1));
+
+        assert_eq!(sequential.query_data_by_label("q1").self_time, Duration::from_nanos(85));
+        assert_eq!(sequential.query_data_by_label("q2").self_time, Duration::from_nanos(36));
+        assert_eq!(sequential.histograms[0].self_time.percentile(0.0), Some(Duration::ZERO));
+        assert_eq!(sequential.histograms[0].self_time.max, Duration::from_nanos(85));
+
+        for num_threads in 2..4 {
+            assert_eq!(data.perform_analysis_with_options(&options(num_threads)), sequential);
+        }
+    }
+
+    #[test]
+    fn histograms() {
+        let mut b = ProfilingDataBuilder::new();
+
+        b.interval(QUERY_EVENT_KIND, "q1", 0, 100, 200, |b| {
+            b.interval(QUERY_EVENT_KIND, "q2", 0, 110, 120, |_| {});
+            b.interval(QUERY_EVENT_KIND, "q2", 0, 130, 170, |b| {
+                b.interval(INCREMENTAL_RESULT_HASHING_EVENT_KIND, "q2", 0, 150, 160, |_| {});
+            });
+        });
+        b.interval(QUERY_EVENT_KIND, "q2", 1, 100, 130, |_| {});
+
+        let options = AnalysisOptions { num_threads: 1, by_args: false, histograms: true, correct_overhead: false };
+        let results = b.into_profiling_data().perform_analysis_with_options(&options);
+
+        assert_eq!(results.histograms.len(), 2);
+
+        let q1 = &results.histograms[0];
+        assert_eq!((&q1.label[..], &q1.event_kind[..]), ("q1", QUERY_EVENT_KIND));
+        assert_eq!(q1.self_time.count, 1);
+        assert_eq!(q1.self_time.max, Duration::from_nanos(50));
+        assert_eq!(q1.time.max, Duration::from_nanos(100));
+
+        let q2 = &results.histograms[1];
+        assert_eq!(q2.self_time.count, 3);
+        assert_eq!(q2.self_time.percentile(0.0), Some(Duration::from_nanos(10)));
+        assert_eq!(q2.self_time.percentile(50.0), Some(Duration::from_nanos(30)));
+        assert_eq!(q2.time.percentile(100.0), Some(Duration::from_nanos(40)));
+
+        // Histograms are only collected on request.
+        let results = multi_threaded_profile().perform_analysis();
+        assert!(results.histograms.is_empty());
+    }
+
+    #[test]
+    fn query_data_by_args() {
+        let mut b = ProfilingDataBuilder::new();
+
+        b.interval(QUERY_EVENT_KIND, "typeck\u{1e}a", 0, 100, 200, |b| {
+            b.interval(QUERY_EVENT_KIND, "typeck\u{1e}b", 0, 110, 130, |_| {});
+            b.instant(QUERY_CACHE_HIT_EVENT_KIND, "typeck\u{1e}b", 0, 140);
+            b.interval(QUERY_EVENT_KIND, "mir_built", 0, 150, 160, |_| {});
+        });
+        b.interval(QUERY_EVENT_KIND, "typeck\u{1e}b", 1, 100, 150, |_| {});
+        b.interval(QUERY_EVENT_KIND, "typeck\u{1e}c\u{1e}d", 1, 150, 155, |_| {});
+
+        let options = AnalysisOptions { num_threads: 1, by_args: true, histograms: true, correct_overhead: false };
+        let results = b.into_profiling_data().perform_analysis_with_options(&options);
+
+        let typeck = results.query_data_by_label("typeck");
+        assert_eq!(typeck.self_time, Duration::from_nanos(145));
+        assert_eq!(typeck.invocation_count, 4);
+
+        let top: Vec<_> = results
+            .top_args_by_self_time(QUERY_EVENT_KIND, "typeck", 10)
+            .into_iter()
+            .map(|args_data| (&args_data.args[..], args_data.data.self_time, args_data.data.invocation_count, args_data.data.number_of_cache_hits))
+            .collect();
+
+        assert_eq!(top, vec![
+            (&["a".to_string()][..], Duration::from_nanos(70), 1, 0),
+            (&["b".to_string()][..], Duration::from_nanos(70), 2, 1),
+            (&["c".to_string(), "d".to_string()][..], Duration::from_nanos(5), 1, 0),
+        ]);
+
+        assert_eq!(results.top_args_by_self_time(QUERY_EVENT_KIND, "typeck", 1).len(), 1);
+        assert_eq!(results.top_args_by_self_time(QUERY_EVENT_KIND, "mir_built", 10)[0].args, Vec::<String>::new());
+        assert!(results.top_args_by_self_time(QUERY_EVENT_KIND, "unknown", 10).is_empty());
+        assert_eq!(results.query_data_by_args.len(), 4);
+    }
 }
@@ -0,0 +1,456 @@
+use crate::{EventPayload, LightweightEvent, ProfilingData};
+use rustc_hash::FxHashMap;
+use std::ops::Index;
+use std::time::{Duration, SystemTime};
+
+/// The interval events of a profile arranged as one tree per thread, as
+/// returned by [`ProfilingData::call_tree`].
+#[derive(Clone, Debug)]
+pub struct CallTree {
+    threads: Vec<ThreadCallTree>,
+}
+
+/// The call tree of a single thread. Nodes are stored in an arena and refer
+/// to each other through [`NodeId`]s, which can be resolved by indexing the
+/// `ThreadCallTree`.
+#[derive(Clone, Debug)]
+pub struct ThreadCallTree {
+    pub thread_id: u32,
+    nodes: Vec<CallTreeNode>,
+    roots: Vec<NodeId>,
+    /// Instant events that are not enclosed by any interval event.
+    pub instants: Vec<LightweightEvent>,
+    /// Integer events that cannot be attributed to any interval event.
+    pub integers: Vec<LightweightEvent>,
+}
+
+/// Identifies a node within its [`ThreadCallTree`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NodeId(usize);
+
+/// An interval event together with the events nested within it.
+#[derive(Clone, Debug)]
+pub struct CallTreeNode {
+    pub event: LightweightEvent,
+    pub parent: Option<NodeId>,
+    /// The number of enclosing interval events.
+    pub depth: usize,
+    /// The directly nested interval events, sorted by start time.
+    pub children: Vec<NodeId>,
+    /// The instant events directly enclosed by this event, sorted by time.
+    pub instants: Vec<LightweightEvent>,
+    /// The integer events recorded while this event was the innermost one in
+    /// progress, in the order they were recorded. Since integer events have
+    /// no timestamp, this is inferred from their position in the event stream.
+    pub integers: Vec<LightweightEvent>,
+    /// The duration of the event.
+    pub inclusive_time: Duration,
+    /// The duration of the event minus the durations of its children.
+    pub exclusive_time: Duration,
+}
+
+impl CallTreeNode {
+    pub fn start(&self) -> SystemTime {
+        self.event.start().unwrap()
+    }
+
+    pub fn end(&self) -> SystemTime {
+        self.event.end().unwrap()
+    }
+}
+
+impl CallTree {
+    /// The trees of all threads, sorted by thread id.
+    pub fn threads(&self) -> &[ThreadCallTree] {
+        &self.threads
+    }
+
+    pub fn thread(&self, thread_id: u32) -> Option<&ThreadCallTree> {
+        self.threads
+            .binary_search_by_key(&thread_id, |thread| thread.thread_id)
+            .ok()
+            .map(|index| &self.threads[index])
+    }
+}
+
+impl ThreadCallTree {
+    fn new(thread_id: u32) -> ThreadCallTree {
+        ThreadCallTree {
+            thread_id,
+            nodes: Vec::new(),
+            roots: Vec::new(),
+            instants: Vec::new(),
+            integers: Vec::new(),
+        }
+    }
+
+    /// The outermost interval events of the thread, sorted by start time.
+    pub fn roots(&self) -> &[NodeId] {
+        &self.roots
+    }
+
+    /// All nodes of the tree, in no particular order.
+    pub fn nodes(&self) -> impl Iterator<Item = (NodeId, &CallTreeNode)> + '_ {
+        self.nodes
+            .iter()
+            .enumerate()
+            .map(|(index, node)| (NodeId(index), node))
+    }
+
+    /// Visits all nodes depth-first, parents before their children and
+    /// siblings in the order they started.
+    pub fn preorder(&self) -> impl Iterator<Item = NodeId> + '_ {
+        let mut stack: Vec<NodeId> = self.roots.iter().rev().copied().collect();
+
+        std::iter::from_fn(move || {
+            let id = stack.pop()?;
+            stack.extend(self[id].children.iter().rev().copied());
+            Some(id)
+        })
+    }
+
+    /// The parts of the node's interval that are not covered by any of its
+    /// children, in chronological order. Their durations add up to the
+    /// node's exclusive time.
+    pub fn exclusive_intervals(&self, id: NodeId) -> Vec<(SystemTime, SystemTime)> {
+        let node = &self[id];
+        let mut intervals = Vec::new();
+
+        let mut start = node.start();
+        for &child in &node.children {
+            let child = &self[child];
+            if child.start() > start {
+                intervals.push((start, child.start()));
+            }
+            start = start.max(child.end());
+        }
+        if node.end() > start {
+            intervals.push((start, node.end()));
+        }
+
+        intervals
+    }
+
+    fn add_node(&mut self, event: LightweightEvent, parent: Option<NodeId>) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        let inclusive_time = event.duration().unwrap();
+
+        let depth = match parent {
+            Some(parent) => {
+                let parent = &mut self.nodes[parent.0];
+                parent.children.push(id);
+                parent.exclusive_time = parent.exclusive_time.saturating_sub(inclusive_time);
+                parent.depth + 1
+            }
+            None => {
+                self.roots.push(id);
+                0
+            }
+        };
+
+        self.nodes.push(CallTreeNode {
+            event,
+            parent,
+            depth,
+            children: Vec::new(),
+            instants: Vec::new(),
+            integers: Vec::new(),
+            inclusive_time,
+            exclusive_time: inclusive_time,
+        });
+
+        id
+    }
+
+    /// Events are added in reverse order, so all lists end up reversed.
+    fn reverse_lists(&mut self) {
+        self.roots.reverse();
+        self.instants.reverse();
+        self.integers.reverse();
+
+        for node in &mut self.nodes {
+            node.children.reverse();
+            node.instants.reverse();
+            node.integers.reverse();
+        }
+    }
+}
+
+impl Index<NodeId> for ThreadCallTree {
+    type Output = CallTreeNode;
+
+    fn index(&self, id: NodeId) -> &CallTreeNode {
+        &self.nodes[id.0]
+    }
+}
+
+/// The state of `ProfilingData::call_tree` for a single thread.
+struct PerThreadState {
+    tree: ThreadCallTree,
+    stack: Vec<NodeId>,
+    /// Integer events waiting for the next event with a timestamp to be
+    /// processed, see `attach_pending_integers`.
+    pending_integers: Vec<LightweightEvent>,
+}
+
+/// Integer events don't have a timestamp, but we know that they were
+/// recorded after the event preceding them in the event stream ended. An
+/// interval that started before that point in time and ended after the
+/// integer event was recorded was therefore in progress at that time.
+///
+/// `stack` holds the intervals of a thread that end after the integer events,
+/// outermost first, and `start` returns their start. Returns the position of
+/// the innermost interval the integer events were recorded in, if any. Without
+/// a preceding event, i.e. for integer events at the start of the stream, we
+/// can't tell.
+pub(crate) fn integer_parent<T>(
+    stack: &[T],
+    start: impl Fn(&T) -> SystemTime,
+    preceding_event_end: Option<SystemTime>,
+) -> Option<usize> {
+    let preceding_event_end = preceding_event_end?;
+    stack
+        .iter()
+        .rposition(|interval| start(interval) <= preceding_event_end)
+}
+
+impl PerThreadState {
+    /// Attaches the pending integer events to their parent, see
+    /// `integer_parent`.
+    fn attach_pending_integers(&mut self, preceding_event_end: Option<SystemTime>) {
+        if self.pending_integers.is_empty() {
+            return;
+        }
+
+        let tree = &self.tree;
+        let parent = integer_parent(&self.stack, |&id| tree[id].start(), preceding_event_end)
+            .map(|position| self.stack[position]);
+
+        let integers = match parent {
+            Some(parent) => &mut self.tree.nodes[parent.0].integers,
+            None => &mut self.tree.integers,
+        };
+
+        integers.append(&mut self.pending_integers);
+    }
+}
+
+impl ProfilingData {
+    /// Arranges the interval events of each thread in a tree according to
+    /// how they are nested. Instant events are attached to the innermost
+    /// interval enclosing them. Integer events are attached to the innermost
+    /// interval that is known to have been in progress when they were
+    /// recorded, judging by their position in the event stream.
+    pub fn call_tree(&self) -> CallTree {
+        let mut threads = FxHashMap::<u32, PerThreadState>::default();
+
+        // Events are stored in the order they end, so iterating in reverse
+        // gives us every parent before its children.
+        for event in self.iter().rev() {
+            let thread = threads
+                .entry(event.thread_id)
+                .or_insert_with(|| PerThreadState {
+                    tree: ThreadCallTree::new(event.thread_id),
+                    stack: Vec::new(),
+                    pending_integers: Vec::new(),
+                });
+
+            let timestamp = match event.payload {
+                EventPayload::Timestamp(timestamp) => timestamp,
+                EventPayload::Integer(_) => {
+                    thread.pending_integers.push(event);
+                    continue;
+                }
+            };
+
+            thread.attach_pending_integers(Some(timestamp.end()));
+
+            // Pop all events that don't contain the current one.
+            while let Some(&top) = thread.stack.last() {
+                if thread.tree[top].event.contains(&event) {
+                    break;
+                }
+                thread.stack.pop();
+            }
+
+            let parent = thread.stack.last().copied();
+
+            if timestamp.is_instant() {
+                match parent {
+                    Some(parent) => thread.tree.nodes[parent.0].instants.push(event),
+                    None => thread.tree.instants.push(event),
+                }
+            } else {
+                let id = thread.tree.add_node(event, parent);
+                thread.stack.push(id);
+            }
+        }
+
+        let mut threads: Vec<_> = threads
+            .into_values()
+            .map(|mut thread| {
+                thread.attach_pending_integers(None);
+                thread.tree.reverse_lists();
+                thread.tree
+            })
+            .collect();
+        threads.sort_by_key(|thread| thread.thread_id);
+
+        CallTree { threads }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ProfilingDataBuilder;
+    use std::time::Duration;
+
+    #[test]
+    fn tree_structure() {
+        let mut b = ProfilingDataBuilder::new();
+
+        // Event indices are given in the comments.
+        b.interval("Query", "e1", 0, 100, 200, |b| {
+            b.interval("Query", "e2", 0, 110, 150, |b| {
+                b.instant("QueryCacheHit", "e3", 0, 120); // 0
+                b.interval("Query", "e4", 0, 130, 140, |_| {}); // 1
+            }); // 2
+            b.integer("ArtifactSize", "e5", 0, 1234); // 3
+            b.interval("Query", "e6", 0, 160, 190, |_| {}); // 4
+            b.instant("QueryCacheHit", "e7", 0, 195); // 5
+        }); // 6
+        b.instant("QueryCacheHit", "e8", 0, 300); // 7
+        b.interval("Query", "e9", 1, 100, 150, |_| {}); // 8
+
+        let data = b.into_profiling_data();
+        let call_tree = data.call_tree();
+
+        assert_eq!(call_tree.threads().len(), 2);
+        assert!(call_tree.thread(2).is_none());
+
+        let thread = call_tree.thread(0).unwrap();
+        let event_index = |id| thread[id].event.event_index;
+
+        let preorder: Vec<_> = thread.preorder().map(event_index).collect();
+        assert_eq!(preorder, vec![6, 2, 1, 4]);
+
+        let e1 = &thread[thread.roots()[0]];
+        assert_eq!(thread.roots().len(), 1);
+        assert_eq!(e1.depth, 0);
+        assert_eq!(e1.parent, None);
+        assert_eq!(
+            e1.children
+                .iter()
+                .copied()
+                .map(event_index)
+                .collect::<Vec<_>>(),
+            vec![2, 4]
+        );
+        assert_eq!(e1.inclusive_time, Duration::from_nanos(100));
+        assert_eq!(e1.exclusive_time, Duration::from_nanos(30));
+        assert_eq!(
+            e1.instants
+                .iter()
+                .map(|e| e.event_index)
+                .collect::<Vec<_>>(),
+            vec![5]
+        );
+        assert_eq!(
+            e1.integers
+                .iter()
+                .map(|e| e.event_index)
+                .collect::<Vec<_>>(),
+            vec![3]
+        );
+
+        let e2 = &thread[e1.children[0]];
+        assert_eq!(e2.depth, 1);
+        assert_eq!(e2.parent, Some(thread.roots()[0]));
+        assert_eq!(e2.inclusive_time, Duration::from_nanos(40));
+        assert_eq!(e2.exclusive_time, Duration::from_nanos(30));
+        assert_eq!(
+            e2.instants
+                .iter()
+                .map(|e| e.event_index)
+                .collect::<Vec<_>>(),
+            vec![0]
+        );
+
+        let e4 = &thread[e2.children[0]];
+        assert_eq!(e4.depth, 2);
+        assert!(e4.children.is_empty());
+        assert_eq!(e4.exclusive_time, e4.inclusive_time);
+
+        assert_eq!(
+            thread
+                .instants
+                .iter()
+                .map(|e| e.event_index)
+                .collect::<Vec<_>>(),
+            vec![7]
+        );
+        assert!(thread.integers.is_empty());
+
+        let thread = call_tree.thread(1).unwrap();
+        assert_eq!(thread.nodes().count(), 1);
+        assert_eq!(thread[thread.roots()[0]].event.event_index, 8);
+    }
+
+    #[test]
+    fn sibling_roots_and_same_timestamps() {
+        let mut b = ProfilingDataBuilder::new();
+
+        b.interval("Query", "e1", 0, 100, 200, |b| {
+            b.interval("Query", "e2", 0, 100, 200, |b| {
+                b.interval("Query", "e3", 0, 100, 150, |_| {}); // 0
+            }); // 1
+        }); // 2
+        b.interval("Query", "e4", 0, 200, 300, |_| {}); // 3
+
+        let data = b.into_profiling_data();
+        let call_tree = data.call_tree();
+        let thread = call_tree.thread(0).unwrap();
+
+        let depths: Vec<_> = thread
+            .preorder()
+            .map(|id| (thread[id].event.event_index, thread[id].depth))
+            .collect();
+        assert_eq!(depths, vec![(2, 0), (1, 1), (0, 2), (3, 0)]);
+
+        assert_eq!(thread[thread.roots()[0]].exclusive_time, Duration::ZERO);
+    }
+
+    #[test]
+    fn integer_events() {
+        let mut b = ProfilingDataBuilder::new();
+
+        b.interval("Query", "e1", 0, 100, 200, |b| {
+            b.interval("Query", "e2", 0, 110, 120, |b| {
+                b.integer("ArtifactSize", "i1", 0, 1); // 0
+            }); // 1
+            b.integer("ArtifactSize", "i2", 0, 2); // 2
+            b.interval("Query", "e3", 1, 130, 140, |_| {}); // 3
+            b.integer("ArtifactSize", "i3", 0, 3); // 4
+            b.interval("Query", "e4", 0, 150, 160, |_| {}); // 5
+        }); // 6
+        b.integer("ArtifactSize", "i4", 0, 4); // 7
+
+        let data = b.into_profiling_data();
+        let call_tree = data.call_tree();
+        let thread = call_tree.thread(0).unwrap();
+        let integers = |events: &[crate::LightweightEvent]| {
+            events.iter().map(|e| e.event_index).collect::<Vec<_>>()
+        };
+
+        let e1 = &thread[thread.roots()[0]];
+        let e2 = &thread[e1.children[0]];
+        let e4 = &thread[e1.children[1]];
+
+        // `i1` is the first event on the thread, so we cannot tell whether it
+        // was recorded within `e2`.
+        assert_eq!(integers(&thread.integers), vec![0, 7]);
+        assert_eq!(integers(&e1.integers), vec![2, 4]);
+        assert!(e2.integers.is_empty());
+        assert!(e4.integers.is_empty());
+    }
+}
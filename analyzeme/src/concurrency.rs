@@ -0,0 +1,260 @@
+use crate::ProfilingData;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime};
+
+/// How many threads were busy over the course of a profile, as computed by
+/// [`ProfilingData::concurrency`]. A thread counts as busy while it is inside
+/// a top-level interval event.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Concurrency {
+    /// The number of threads that recorded at least one interval event.
+    pub num_threads: usize,
+    /// The time from the start of the first to the end of the last interval.
+    pub wall_time: Duration,
+    /// The number of busy threads over time, in chronological order. Adjacent
+    /// steps always have a different number of busy threads and together
+    /// cover `wall_time` without gaps.
+    pub timeline: Vec<ConcurrencyStep>,
+}
+
+/// A stretch of time during which the number of busy threads did not change.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConcurrencyStep {
+    pub start: SystemTime,
+    pub end: SystemTime,
+    pub busy_threads: usize,
+}
+
+/// A stretch of time during which at most one thread was busy.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SerialStretch {
+    pub start: SystemTime,
+    pub end: SystemTime,
+}
+
+impl ConcurrencyStep {
+    pub fn duration(&self) -> Duration {
+        self.end.duration_since(self.start).unwrap_or_default()
+    }
+}
+
+impl SerialStretch {
+    pub fn duration(&self) -> Duration {
+        self.end.duration_since(self.start).unwrap_or_default()
+    }
+}
+
+impl Concurrency {
+    /// The average number of busy threads over the wall time.
+    pub fn average_parallelism(&self) -> f64 {
+        if self.wall_time == Duration::ZERO {
+            return 0.0;
+        }
+
+        let busy_time: f64 = self
+            .timeline
+            .iter()
+            .map(|step| step.duration().as_secs_f64() * step.busy_threads as f64)
+            .sum();
+
+        busy_time / self.wall_time.as_secs_f64()
+    }
+
+    /// The fraction of the available thread time (`wall_time * num_threads`)
+    /// during which threads were idle.
+    pub fn idle_fraction(&self) -> f64 {
+        if self.num_threads == 0 {
+            return 0.0;
+        }
+
+        1.0 - self.average_parallelism() / self.num_threads as f64
+    }
+
+    /// The total time during which exactly `n` threads were busy, indexed by
+    /// `n`.
+    pub fn time_by_busy_threads(&self) -> Vec<Duration> {
+        let mut times = vec![Duration::ZERO; self.num_threads + 1];
+
+        for step in &self.timeline {
+            times[step.busy_threads] += step.duration();
+        }
+
+        times
+    }
+
+    /// The longest stretches of time in which at most one thread was busy,
+    /// sorted by descending duration.
+    pub fn longest_serial_stretches(&self, n: usize) -> Vec<SerialStretch> {
+        let mut stretches: Vec<SerialStretch> = Vec::new();
+
+        for step in self.timeline.iter().filter(|step| step.busy_threads <= 1) {
+            match stretches.last_mut() {
+                Some(last) if last.end == step.start => last.end = step.end,
+                _ => stretches.push(SerialStretch {
+                    start: step.start,
+                    end: step.end,
+                }),
+            }
+        }
+
+        stretches.sort_by(|a, b| b.duration().cmp(&a.duration()).then(a.start.cmp(&b.start)));
+        stretches.truncate(n);
+        stretches
+    }
+}
+
+impl ProfilingData {
+    /// Computes how many threads were busy, i.e. inside a top-level interval
+    /// event, at each point in time.
+    pub fn concurrency(&self) -> Concurrency {
+        let call_tree = self.call_tree();
+
+        // +1 when a thread becomes busy, -1 when it becomes idle again.
+        let mut changes: Vec<(SystemTime, isize)> = Vec::new();
+        let mut num_threads = 0;
+
+        for thread in call_tree.threads() {
+            let mut busy: Option<(SystemTime, SystemTime)> = None;
+
+            for &root in thread.roots() {
+                let (start, end) = (thread[root].start(), thread[root].end());
+
+                busy = match busy {
+                    // Top-level events that touch or overlap are merged so
+                    // that the thread does not briefly count as idle.
+                    Some((busy_start, busy_end)) if start <= busy_end => {
+                        Some((busy_start, busy_end.max(end)))
+                    }
+                    Some((busy_start, busy_end)) => {
+                        changes.push((busy_start, 1));
+                        changes.push((busy_end, -1));
+                        Some((start, end))
+                    }
+                    None => Some((start, end)),
+                };
+            }
+
+            if let Some((busy_start, busy_end)) = busy {
+                changes.push((busy_start, 1));
+                changes.push((busy_end, -1));
+                num_threads += 1;
+            }
+        }
+
+        changes.sort();
+
+        let mut timeline: Vec<ConcurrencyStep> = Vec::new();
+        let mut busy_threads = 0isize;
+        let mut changes = changes.into_iter().peekable();
+
+        while let Some((time, delta)) = changes.next() {
+            busy_threads += delta;
+
+            // Apply all changes happening at the same time at once.
+            while let Some(&(_, delta)) = changes.peek().filter(|&&(t, _)| t == time) {
+                busy_threads += delta;
+                changes.next();
+            }
+
+            let next = match changes.peek() {
+                Some(&(next, _)) => next,
+                None => break,
+            };
+
+            match timeline.last_mut() {
+                Some(last) if last.busy_threads == busy_threads as usize => last.end = next,
+                _ => timeline.push(ConcurrencyStep {
+                    start: time,
+                    end: next,
+                    busy_threads: busy_threads as usize,
+                }),
+            }
+        }
+
+        let wall_time = match (timeline.first(), timeline.last()) {
+            (Some(first), Some(last)) => last.end.duration_since(first.start).unwrap(),
+            _ => Duration::ZERO,
+        };
+
+        Concurrency {
+            num_threads,
+            wall_time,
+            timeline,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ProfilingDataBuilder;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    fn nanos(t: SystemTime) -> u64 {
+        t.duration_since(UNIX_EPOCH).unwrap().as_nanos() as u64
+    }
+
+    #[test]
+    fn two_threads() {
+        let mut b = ProfilingDataBuilder::new();
+
+        b.interval("Query", "a", 0, 0, 40, |b| {
+            b.interval("Query", "b", 0, 10, 20, |_| {});
+        });
+        b.interval("Query", "c", 0, 40, 60, |_| {});
+        b.interval("Query", "d", 1, 20, 50, |_| {});
+        b.interval("Query", "e", 0, 80, 100, |_| {});
+
+        let concurrency = b.into_profiling_data().concurrency();
+
+        assert_eq!(concurrency.num_threads, 2);
+        assert_eq!(concurrency.wall_time, Duration::from_nanos(100));
+
+        let timeline: Vec<_> = concurrency
+            .timeline
+            .iter()
+            .map(|step| (nanos(step.start), nanos(step.end), step.busy_threads))
+            .collect();
+        assert_eq!(
+            timeline,
+            vec![
+                (0, 20, 1),
+                (20, 50, 2),
+                (50, 60, 1),
+                (60, 80, 0),
+                (80, 100, 1),
+            ]
+        );
+
+        // 110ns of busy thread time over 100ns of wall time.
+        assert!((concurrency.average_parallelism() - 1.1).abs() < 1e-9);
+        assert!((concurrency.idle_fraction() - 0.45).abs() < 1e-9);
+
+        assert_eq!(
+            concurrency.time_by_busy_threads(),
+            vec![
+                Duration::from_nanos(20),
+                Duration::from_nanos(50),
+                Duration::from_nanos(30),
+            ]
+        );
+
+        let stretches: Vec<_> = concurrency
+            .longest_serial_stretches(5)
+            .iter()
+            .map(|s| (nanos(s.start), nanos(s.end)))
+            .collect();
+        assert_eq!(stretches, vec![(50, 100), (0, 20)]);
+    }
+
+    #[test]
+    fn empty_profile() {
+        let concurrency = ProfilingDataBuilder::new()
+            .into_profiling_data()
+            .concurrency();
+
+        assert_eq!(concurrency.num_threads, 0);
+        assert!(concurrency.timeline.is_empty());
+        assert_eq!(concurrency.average_parallelism(), 0.0);
+        assert_eq!(concurrency.idle_fraction(), 0.0);
+    }
+}
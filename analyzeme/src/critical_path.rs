@@ -0,0 +1,448 @@
+use crate::{Event, ProfilingData};
+use measureme::rustc::{QUERY_BLOCKED_EVENT_KIND, QUERY_EVENT_KIND};
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime};
+
+/// The label and event kind of the parts of the critical path in which the
+/// thread on it did not execute any recorded event.
+pub const IDLE: &str = "<idle>";
+
+/// The chain of events that determined the wall-clock duration of a profile,
+/// as computed by [`ProfilingData::critical_path`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CriticalPath {
+    /// The time from the start of the first event to the end of the last one.
+    pub wall_time: Duration,
+    /// The pieces of the critical path in chronological order. Together they
+    /// cover `wall_time` without gaps.
+    pub segments: Vec<CriticalPathSegment>,
+}
+
+/// A part of the critical path during which the innermost event on the given
+/// thread was the same.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CriticalPathSegment {
+    pub thread_id: u32,
+    pub event_kind: String,
+    pub label: String,
+    pub start: SystemTime,
+    pub end: SystemTime,
+}
+
+/// The total time an event label contributes to the critical path.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CriticalPathItem {
+    pub event_kind: String,
+    pub label: String,
+    pub time: Duration,
+}
+
+impl CriticalPathSegment {
+    pub fn duration(&self) -> Duration {
+        self.end.duration_since(self.start).unwrap_or_default()
+    }
+}
+
+impl CriticalPath {
+    /// Sums up the time on the critical path by event kind and label, sorted
+    /// by descending time.
+    pub fn time_by_label(&self) -> Vec<CriticalPathItem> {
+        let mut items = FxHashMap::<(&str, &str), Duration>::default();
+
+        for segment in &self.segments {
+            *items
+                .entry((&segment.event_kind, &segment.label))
+                .or_default() += segment.duration();
+        }
+
+        let mut items: Vec<_> = items
+            .into_iter()
+            .map(|((event_kind, label), time)| CriticalPathItem {
+                event_kind: event_kind.to_owned(),
+                label: label.to_owned(),
+                time,
+            })
+            .collect();
+
+        items.sort_by(|a, b| {
+            b.time
+                .cmp(&a.time)
+                .then_with(|| (&a.label, &a.event_kind).cmp(&(&b.label, &b.event_kind)))
+        });
+        items
+    }
+}
+
+struct EventInfo {
+    event_kind: String,
+    label: String,
+    args: Vec<String>,
+    start: SystemTime,
+}
+
+/// A piece of a thread's timeline in which the given event was the innermost
+/// one in progress.
+struct Segment {
+    start: SystemTime,
+    end: SystemTime,
+    event: usize,
+}
+
+struct Timeline {
+    thread_id: u32,
+    /// Sorted by start time and non-overlapping.
+    segments: Vec<Segment>,
+}
+
+impl Timeline {
+    /// The last segment starting before `t`.
+    fn segment_before(&self, t: SystemTime) -> Option<&Segment> {
+        let index = self.segments.partition_point(|segment| segment.start < t);
+        index.checked_sub(1).map(|index| &self.segments[index])
+    }
+
+    /// The last segment ending at or before `t`.
+    fn segment_ending_before(&self, t: SystemTime) -> Option<&Segment> {
+        let index = self.segments.partition_point(|segment| segment.end <= t);
+        index.checked_sub(1).map(|index| &self.segments[index])
+    }
+}
+
+type QueryKey = (String, Vec<String>);
+
+fn query_key(event: &Event<'_>) -> QueryKey {
+    (
+        event.label.to_string(),
+        event
+            .additional_data
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect(),
+    )
+}
+
+impl ProfilingData {
+    /// Computes the critical path through the profile, i.e. the chain of
+    /// events that the end of the last event transitively had to wait for.
+    ///
+    /// The path is traced backwards in time, starting at the end of the last
+    /// event. Within a thread it follows the innermost event in progress.
+    /// When it reaches a `QueryBlocked` interval, it continues on the thread
+    /// that executed the query being waited for, identified by its label and
+    /// arguments. When the thread on the path was idle, the path continues on
+    /// the thread whose last event ended most recently before, or on a thread
+    /// that was busy at that point.
+    pub fn critical_path(&self) -> CriticalPath {
+        let call_tree = self.call_tree();
+
+        let mut events = Vec::new();
+        let mut timelines = Vec::new();
+        // The end times of all query executions, for resolving `QueryBlocked`
+        // events.
+        let mut queries = FxHashMap::<QueryKey, Vec<(SystemTime, usize)>>::default();
+
+        for thread in call_tree.threads() {
+            let mut segments = Vec::new();
+
+            for id in thread.preorder() {
+                let node = &thread[id];
+                let event = self.to_full_event(&node.event);
+                let event_index = events.len();
+
+                if event.event_kind == QUERY_EVENT_KIND {
+                    queries
+                        .entry(query_key(&event))
+                        .or_default()
+                        .push((node.end(), timelines.len()));
+                }
+
+                events.push(EventInfo {
+                    event_kind: event.event_kind.to_string(),
+                    label: event.label.to_string(),
+                    args: event
+                        .additional_data
+                        .iter()
+                        .map(|arg| arg.to_string())
+                        .collect(),
+                    start: node.start(),
+                });
+
+                // The parts of the event not covered by its children.
+                for (start, end) in thread.exclusive_intervals(id) {
+                    segments.push(Segment {
+                        start,
+                        end,
+                        event: event_index,
+                    });
+                }
+            }
+
+            segments.sort_by_key(|segment| segment.start);
+            timelines.push(Timeline {
+                thread_id: thread.thread_id,
+                segments,
+            });
+        }
+
+        for ends in queries.values_mut() {
+            ends.sort();
+        }
+
+        let global_start = timelines
+            .iter()
+            .filter_map(|timeline| timeline.segments.first())
+            .map(|segment| segment.start)
+            .min();
+
+        // Start on the thread whose last event ended last.
+        let last = timelines
+            .iter()
+            .enumerate()
+            .filter_map(|(index, timeline)| Some((timeline.segments.last()?.end, index)))
+            .max_by(|(a_end, a_index), (b_end, b_index)| {
+                a_end.cmp(b_end).then_with(|| b_index.cmp(a_index))
+            });
+
+        let (global_start, (global_end, mut current)) = match (global_start, last) {
+            (Some(global_start), Some(last)) => (global_start, last),
+            _ => {
+                return CriticalPath {
+                    wall_time: Duration::ZERO,
+                    segments: Vec::new(),
+                }
+            }
+        };
+
+        let mut t = global_end;
+        let mut path: Vec<CriticalPathSegment> = Vec::new();
+        // Jumping between threads does not necessarily move us back in time,
+        // so we need to make sure that we cannot jump back and forth forever.
+        let mut jumps_without_progress = 0;
+
+        let mut push = |timeline: &Timeline, event_kind: &str, label: &str, start, end| {
+            if let Some(last) = path.last_mut() {
+                if last.thread_id == timeline.thread_id
+                    && last.event_kind == event_kind
+                    && last.label == label
+                    && last.start == end
+                {
+                    last.start = start;
+                    return;
+                }
+            }
+
+            path.push(CriticalPathSegment {
+                thread_id: timeline.thread_id,
+                event_kind: event_kind.to_owned(),
+                label: label.to_owned(),
+                start,
+                end,
+            });
+        };
+
+        while t > global_start {
+            let timeline = &timelines[current];
+            let segment = timeline.segment_before(t);
+
+            if let Some(segment) = segment.filter(|segment| segment.end >= t) {
+                let event = &events[segment.event];
+
+                if event.event_kind == QUERY_BLOCKED_EVENT_KIND
+                    && jumps_without_progress < timelines.len()
+                {
+                    let key = (event.label.clone(), event.args.clone());
+                    let unblocker = queries.get(&key).and_then(|ends| {
+                        let candidates = &ends[..ends.partition_point(|&(end, _)| end <= t)];
+                        candidates
+                            .iter()
+                            .rev()
+                            .take_while(|&&(end, _)| end >= event.start)
+                            .find(|&&(_, thread)| thread != current)
+                    });
+
+                    if let Some(&(end, thread)) = unblocker {
+                        if end < t {
+                            // The time between the query finishing and this
+                            // thread continuing.
+                            push(timeline, &event.event_kind, &event.label, end, t);
+                            jumps_without_progress = 0;
+                        } else {
+                            jumps_without_progress += 1;
+                        }
+
+                        current = thread;
+                        t = end;
+                        continue;
+                    }
+                }
+
+                push(timeline, &event.event_kind, &event.label, segment.start, t);
+                t = segment.start;
+                jumps_without_progress = 0;
+                continue;
+            }
+
+            // The thread was idle right before `t`. Continue on the thread
+            // that finished something most recently, or else one that was
+            // busy at `t`.
+            let idle_start = segment.map_or(global_start, |segment| segment.end);
+
+            let most_recent_end = timelines
+                .iter()
+                .enumerate()
+                .filter(|&(index, _)| index != current)
+                .filter_map(|(index, timeline)| {
+                    Some((timeline.segment_ending_before(t)?.end, index))
+                })
+                .filter(|&(end, _)| end > idle_start)
+                .max_by(|(a_end, a_index), (b_end, b_index)| {
+                    a_end.cmp(b_end).then_with(|| b_index.cmp(a_index))
+                });
+
+            let busy = || {
+                timelines.iter().position(|timeline| {
+                    timeline
+                        .segment_before(t)
+                        .is_some_and(|segment| segment.end >= t)
+                })
+            };
+
+            if let Some((end, index)) = most_recent_end {
+                if end < t {
+                    push(timeline, IDLE, IDLE, end, t);
+                }
+                current = index;
+                t = end;
+            } else if let Some(index) = busy() {
+                current = index;
+            } else {
+                push(timeline, IDLE, IDLE, idle_start, t);
+                t = idle_start;
+            }
+            jumps_without_progress = 0;
+        }
+
+        path.reverse();
+
+        CriticalPath {
+            wall_time: global_end.duration_since(global_start).unwrap(),
+            segments: path,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IDLE;
+    use crate::ProfilingDataBuilder;
+    use measureme::rustc::{QUERY_BLOCKED_EVENT_KIND, QUERY_EVENT_KIND};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn segments(path: &super::CriticalPath) -> Vec<(u32, &str, &str, u64, u64)> {
+        let nanos =
+            |t: std::time::SystemTime| t.duration_since(UNIX_EPOCH).unwrap().as_nanos() as u64;
+
+        path.segments
+            .iter()
+            .map(|s| {
+                (
+                    s.thread_id,
+                    &s.event_kind[..],
+                    &s.label[..],
+                    nanos(s.start),
+                    nanos(s.end),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn single_thread() {
+        let mut b = ProfilingDataBuilder::new();
+
+        b.interval(QUERY_EVENT_KIND, "a", 0, 100, 200, |b| {
+            b.interval(QUERY_EVENT_KIND, "b", 0, 120, 150, |_| {});
+        });
+        b.interval(QUERY_EVENT_KIND, "c", 0, 250, 300, |_| {});
+
+        let path = b.into_profiling_data().critical_path();
+
+        assert_eq!(path.wall_time, Duration::from_nanos(200));
+        assert_eq!(
+            segments(&path),
+            vec![
+                (0, QUERY_EVENT_KIND, "a", 100, 120),
+                (0, QUERY_EVENT_KIND, "b", 120, 150),
+                (0, QUERY_EVENT_KIND, "a", 150, 200),
+                (0, IDLE, IDLE, 200, 250),
+                (0, QUERY_EVENT_KIND, "c", 250, 300),
+            ]
+        );
+
+        let items = path.time_by_label();
+        assert_eq!(items[0].label, "a");
+        assert_eq!(items[0].time, Duration::from_nanos(70));
+    }
+
+    #[test]
+    fn blocked_query() {
+        let mut b = ProfilingDataBuilder::new();
+
+        // Thread 0 waits for thread 1 to finish executing `q`.
+        b.interval(QUERY_EVENT_KIND, "q", 1, 10, 70, |_| {});
+        b.interval(QUERY_EVENT_KIND, "a", 0, 0, 100, |b| {
+            b.interval(QUERY_BLOCKED_EVENT_KIND, "q", 0, 20, 80, |_| {});
+        });
+        b.interval(QUERY_EVENT_KIND, "x", 1, 70, 90, |_| {});
+
+        let path = b.into_profiling_data().critical_path();
+
+        assert_eq!(
+            segments(&path),
+            vec![
+                (0, QUERY_EVENT_KIND, "a", 0, 10),
+                (1, QUERY_EVENT_KIND, "q", 10, 70),
+                (0, QUERY_BLOCKED_EVENT_KIND, "q", 70, 80),
+                (0, QUERY_EVENT_KIND, "a", 80, 100),
+            ]
+        );
+
+        let total: Duration = path.segments.iter().map(|s| s.duration()).sum();
+        assert_eq!(total, path.wall_time);
+    }
+
+    #[test]
+    fn joining_threads() {
+        let mut b = ProfilingDataBuilder::new();
+
+        // Thread 0 spawns two workers and waits for them without recording
+        // anything.
+        b.interval(QUERY_EVENT_KIND, "spawn", 0, 0, 10, |_| {});
+        b.interval(QUERY_EVENT_KIND, "w1", 1, 10, 50, |_| {});
+        b.interval(QUERY_EVENT_KIND, "w2", 2, 10, 80, |_| {});
+        b.interval(QUERY_EVENT_KIND, "join", 0, 85, 100, |_| {});
+
+        let path = b.into_profiling_data().critical_path();
+
+        assert_eq!(
+            segments(&path),
+            vec![
+                (0, QUERY_EVENT_KIND, "spawn", 0, 10),
+                (2, QUERY_EVENT_KIND, "w2", 10, 80),
+                (0, IDLE, IDLE, 80, 85),
+                (0, QUERY_EVENT_KIND, "join", 85, 100),
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_profile() {
+        let path = ProfilingDataBuilder::new()
+            .into_profiling_data()
+            .critical_path();
+
+        assert_eq!(path.wall_time, Duration::ZERO);
+        assert!(path.segments.is_empty());
+    }
+}
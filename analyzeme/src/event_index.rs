@@ -0,0 +1,307 @@
+use crate::{EventPayload, LightweightEvent, ProfilingData, Timestamp};
+use rustc_hash::FxHashMap;
+use std::ops::Range;
+use std::time::{Duration, SystemTime};
+
+/// An index over all timestamped events of a profile, grouped by thread and
+/// sorted by end time. It allows for finding the events in a given time range
+/// with a binary search instead of scanning the whole event stream.
+///
+/// The index is built on first use by [`ProfilingData::events_in_range`] or
+/// [`ProfilingData::events_overlapping`] and then kept for the lifetime of the
+/// `ProfilingData`.
+#[derive(Debug)]
+pub(crate) struct EventIndex {
+    threads: FxHashMap<u32, ThreadIndex>,
+}
+
+#[derive(Debug, Default)]
+struct ThreadIndex {
+    /// Sorted by `timestamp.end()`.
+    entries: Vec<IndexEntry>,
+    /// The longest duration of any event in `entries`. An event ending at `t`
+    /// cannot have started before `t - max_duration`, which bounds how far we
+    /// need to scan after a binary search by end time.
+    max_duration: Duration,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct IndexEntry {
+    timestamp: Timestamp,
+    event_index: usize,
+}
+
+impl EventIndex {
+    pub(crate) fn new(data: &ProfilingData) -> EventIndex {
+        let mut threads = FxHashMap::<u32, ThreadIndex>::default();
+
+        for event in data.iter() {
+            if let EventPayload::Timestamp(timestamp) = event.payload {
+                let thread = threads.entry(event.thread_id).or_default();
+
+                if let Some(duration) = timestamp.duration() {
+                    thread.max_duration = thread.max_duration.max(duration);
+                }
+
+                thread.entries.push(IndexEntry {
+                    timestamp,
+                    event_index: event.event_index,
+                });
+            }
+        }
+
+        for thread in threads.values_mut() {
+            // Events are mostly recorded in the order they end, so this is
+            // usually cheap.
+            thread
+                .entries
+                .sort_by_key(|entry| (entry.timestamp.end(), entry.event_index));
+        }
+
+        EventIndex { threads }
+    }
+}
+
+impl ThreadIndex {
+    /// Returns the entries that end at or after `t` and might have started at
+    /// or before `latest_start`. The caller still has to check the start time
+    /// of each entry.
+    fn candidates(&self, t: SystemTime, latest_start: SystemTime) -> &[IndexEntry] {
+        let first = self
+            .entries
+            .partition_point(|entry| entry.timestamp.end() < t);
+
+        // Anything ending after `latest_start + max_duration` must have started
+        // after `latest_start`.
+        let last = match latest_start.checked_add(self.max_duration) {
+            Some(limit) => {
+                first
+                    + self.entries[first..].partition_point(|entry| entry.timestamp.end() <= limit)
+            }
+            None => self.entries.len(),
+        };
+
+        &self.entries[first..last]
+    }
+}
+
+impl IndexEntry {
+    fn to_lightweight_event(self, thread_id: u32) -> LightweightEvent {
+        LightweightEvent {
+            event_index: self.event_index,
+            thread_id,
+            payload: EventPayload::Timestamp(self.timestamp),
+        }
+    }
+}
+
+/// Sorts events by start time, with enclosing intervals coming before the
+/// intervals they contain.
+fn sort_by_start(events: &mut [LightweightEvent]) {
+    events.sort_by(|a, b| {
+        let (a, b) = (
+            a.payload.timestamp().unwrap(),
+            b.payload.timestamp().unwrap(),
+        );
+        a.start()
+            .cmp(&b.start())
+            .then_with(|| b.end().cmp(&a.end()))
+    });
+}
+
+impl ProfilingData {
+    /// Returns all interval and instant events on the given thread that
+    /// overlap with `range`, i.e. intervals that end after `range.start` and
+    /// start before `range.end`, and instants within `range`. The events are
+    /// sorted by start time, with enclosing intervals coming before the
+    /// intervals they contain.
+    pub fn events_in_range(
+        &self,
+        thread_id: u32,
+        range: Range<SystemTime>,
+    ) -> Vec<LightweightEvent> {
+        let thread = match self.event_index().threads.get(&thread_id) {
+            Some(thread) => thread,
+            None => return Vec::new(),
+        };
+
+        let mut events: Vec<_> = thread
+            .candidates(range.start, range.end)
+            .iter()
+            .filter(|entry| match entry.timestamp {
+                Timestamp::Interval { start, end } => start < range.end && end > range.start,
+                Timestamp::Instant(t) => range.contains(&t),
+            })
+            .map(|entry| entry.to_lightweight_event(thread_id))
+            .collect();
+
+        sort_by_start(&mut events);
+        events
+    }
+
+    /// Returns the interval events of all threads that were in progress at
+    /// time `t`, i.e. that started at or before `t` and end after it. The
+    /// events are sorted by thread id and then by start time, so the events of
+    /// each thread form the stack of active events at `t`.
+    pub fn events_overlapping(&self, t: SystemTime) -> Vec<LightweightEvent> {
+        let index = self.event_index();
+
+        let mut thread_ids: Vec<_> = index.threads.keys().copied().collect();
+        thread_ids.sort_unstable();
+
+        let mut result = Vec::new();
+
+        for thread_id in thread_ids {
+            let mut events: Vec<_> = index.threads[&thread_id]
+                .candidates(t, t)
+                .iter()
+                .filter(|entry| match entry.timestamp {
+                    Timestamp::Interval { .. } => entry.timestamp.contains(t),
+                    Timestamp::Instant(_) => false,
+                })
+                .map(|entry| entry.to_lightweight_event(thread_id))
+                .collect();
+
+            sort_by_start(&mut events);
+            result.extend(events);
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ProfilingDataBuilder;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    fn t(nanos: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_nanos(nanos)
+    }
+
+    fn event_indices(events: &[crate::LightweightEvent]) -> Vec<usize> {
+        events.iter().map(|e| e.event_index).collect()
+    }
+
+    fn sample_profile() -> crate::ProfilingData {
+        let mut b = ProfilingDataBuilder::new();
+
+        // Event indices are given in the comments.
+        b.interval("Query", "e1", 0, 100, 200, |b| {
+            b.interval("Query", "e2", 0, 110, 120, |_| {}); // 0
+            b.instant("QueryCacheHit", "e3", 0, 150); // 1
+            b.integer("ArtifactSize", "e4", 0, 1234); // 2
+            b.interval("Query", "e5", 0, 160, 190, |b| {
+                b.interval("Query", "e6", 0, 170, 180, |_| {}); // 3
+            }); // 4
+        }); // 5
+        b.interval("Query", "e7", 1, 50, 1000, |_| {}); // 6
+        b.interval("Query", "e8", 0, 300, 400, |_| {}); // 7
+
+        b.into_profiling_data()
+    }
+
+    #[test]
+    fn events_in_range() {
+        let profile = sample_profile();
+
+        assert_eq!(
+            event_indices(&profile.events_in_range(0, t(0)..t(50))),
+            Vec::<usize>::new()
+        );
+        assert_eq!(
+            event_indices(&profile.events_in_range(0, t(0)..t(101))),
+            vec![5]
+        );
+        assert_eq!(
+            event_indices(&profile.events_in_range(0, t(115)..t(175))),
+            vec![5, 0, 1, 4, 3]
+        );
+        assert_eq!(
+            event_indices(&profile.events_in_range(0, t(150)..t(151))),
+            vec![5, 1]
+        );
+        // Intervals ending exactly at the start of the range are not included.
+        assert_eq!(
+            event_indices(&profile.events_in_range(0, t(200)..t(350))),
+            vec![7]
+        );
+        assert_eq!(
+            event_indices(&profile.events_in_range(0, t(0)..t(10_000))),
+            vec![5, 0, 1, 4, 3, 7]
+        );
+        assert_eq!(
+            event_indices(&profile.events_in_range(1, t(0)..t(60))),
+            vec![6]
+        );
+        assert_eq!(
+            event_indices(&profile.events_in_range(2, t(0)..t(60))),
+            Vec::<usize>::new()
+        );
+    }
+
+    #[test]
+    fn events_overlapping() {
+        let profile = sample_profile();
+
+        assert_eq!(
+            event_indices(&profile.events_overlapping(t(10))),
+            Vec::<usize>::new()
+        );
+        assert_eq!(event_indices(&profile.events_overlapping(t(60))), vec![6]);
+        assert_eq!(
+            event_indices(&profile.events_overlapping(t(175))),
+            vec![5, 4, 3, 6]
+        );
+        // Instants are never in progress.
+        assert_eq!(
+            event_indices(&profile.events_overlapping(t(150))),
+            vec![5, 6]
+        );
+        // Intervals include their start but not their end.
+        assert_eq!(
+            event_indices(&profile.events_overlapping(t(300))),
+            vec![7, 6]
+        );
+        assert_eq!(event_indices(&profile.events_overlapping(t(400))), vec![6]);
+    }
+
+    #[test]
+    fn matches_linear_scan() {
+        let mut b = ProfilingDataBuilder::new();
+
+        // A bunch of long and short intervals on a few threads.
+        for i in 0..200u64 {
+            let thread_id = (i % 3) as u32;
+            let start = i * 37 % 1000;
+            let end = start + (i * 101 % 250);
+            b.interval("Query", "e", thread_id, start, end, |_| {});
+        }
+
+        let profile = b.into_profiling_data();
+
+        for thread_id in 0..3 {
+            for start in (0..1300).step_by(97) {
+                for len in [0, 1, 10, 300] {
+                    let range = t(start)..t(start + len);
+
+                    let mut expected: Vec<_> = profile
+                        .iter()
+                        .filter(|e| e.thread_id == thread_id)
+                        .filter(|e| {
+                            let ts = e.payload.timestamp().unwrap();
+                            ts.start() < range.end && ts.end() > range.start
+                        })
+                        .map(|e| e.event_index)
+                        .collect();
+                    expected.sort_unstable();
+
+                    let mut actual = event_indices(&profile.events_in_range(thread_id, range));
+                    actual.sort_unstable();
+
+                    assert_eq!(actual, expected);
+                }
+            }
+        }
+    }
+}
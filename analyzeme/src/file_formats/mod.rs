@@ -1,4 +1,5 @@
-use decodeme::{event::Event, lightweight_event::LightweightEvent, Metadata};
+use crate::ThreadOrigin;
+use decodeme::{event::Event, lightweight_event::LightweightEvent, validation::Problem, Metadata};
 use std::fmt::Debug;
 
 pub mod v8;
@@ -12,4 +13,22 @@ pub trait EventDecoder: Debug + Send + Sync {
     fn metadata(&self) -> Metadata;
     fn decode_full_event<'a>(&'a self, event_index: usize) -> Event<'a>;
     fn decode_lightweight_event<'a>(&'a self, event_index: usize) -> LightweightEvent;
+
+    /// The id of the thread the given event was recorded on. Decoders that
+    /// can find it without decoding the payload should override this.
+    fn thread_id(&self, event_index: usize) -> u32 {
+        self.decode_lightweight_event(event_index).thread_id
+    }
+
+    /// Checks that the strings of all events can be decoded. Decoders for
+    /// older file formats don't support this and never report any problems.
+    fn check_strings(&self) -> Vec<Problem> {
+        Vec::new()
+    }
+
+    /// Maps thread ids to the process and thread they were recorded on, for
+    /// decoders that renumber threads.
+    fn thread_origin(&self, _thread_id: u32) -> Option<ThreadOrigin> {
+        None
+    }
 }
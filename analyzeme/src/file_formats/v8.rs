@@ -31,6 +31,9 @@ fn v8_metadata_as_current(old: &OldMetadata) -> Metadata {
         start_time: old.start_time,
         process_id: old.process_id,
         cmd: old.cmd.clone(),
+        // The old decoder does not expose the counter description.
+        counter: Default::default(),
+        recording_overhead: None,
     }
 }
 
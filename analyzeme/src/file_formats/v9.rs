@@ -2,7 +2,7 @@
 
 use crate::{Event, LightweightEvent};
 pub use decodeme::EventDecoder;
-use decodeme::Metadata;
+use decodeme::{validation::Problem, Metadata};
 
 pub const FILE_FORMAT: u32 = decodeme::CURRENT_FILE_FORMAT_VERSION;
 
@@ -22,4 +22,12 @@ impl super::EventDecoder for EventDecoder {
     fn decode_lightweight_event(&self, event_index: usize) -> LightweightEvent {
         self.decode_lightweight_event(event_index)
     }
+
+    fn thread_id(&self, event_index: usize) -> u32 {
+        self.thread_id(event_index)
+    }
+
+    fn check_strings(&self) -> Vec<Problem> {
+        self.check_strings()
+    }
 }
@@ -0,0 +1,309 @@
+use crate::{LightweightEvent, ProfilingData};
+use regex::Regex;
+use rustc_hash::FxHashSet;
+use std::time::{Duration, SystemTime};
+
+/// A predicate on events, used to create a filtered view of a profile with
+/// [`ProfilingData::filter`]. An event is selected if it satisfies all of
+/// the configured conditions.
+#[derive(Clone, Debug, Default)]
+pub struct EventFilter {
+    event_kinds: Option<FxHashSet<String>>,
+    label: Option<Regex>,
+    thread_ids: Option<FxHashSet<u32>>,
+    from: Option<Duration>,
+    to: Option<Duration>,
+    min_duration: Option<Duration>,
+}
+
+impl EventFilter {
+    /// Creates a filter that selects every event.
+    pub fn new() -> EventFilter {
+        EventFilter::default()
+    }
+
+    /// Only selects events of one of the given kinds, e.g. `Query`.
+    pub fn event_kinds<S: Into<String>>(mut self, kinds: impl IntoIterator<Item = S>) -> Self {
+        self.event_kinds = Some(kinds.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Only selects events whose label matches the given regular expression.
+    pub fn label(mut self, regex: Regex) -> Self {
+        self.label = Some(regex);
+        self
+    }
+
+    /// Only selects events recorded on one of the given threads.
+    pub fn thread_ids(mut self, thread_ids: impl IntoIterator<Item = u32>) -> Self {
+        self.thread_ids = Some(thread_ids.into_iter().collect());
+        self
+    }
+
+    /// Only selects events that end at or after the given offset from the
+    /// start of the profile. Integer events, which have no timestamp, are
+    /// never selected.
+    pub fn from(mut self, offset: Duration) -> Self {
+        self.from = Some(offset);
+        self
+    }
+
+    /// Only selects events that start at or before the given offset from the
+    /// start of the profile. Integer events, which have no timestamp, are
+    /// never selected.
+    pub fn to(mut self, offset: Duration) -> Self {
+        self.to = Some(offset);
+        self
+    }
+
+    /// Only selects interval events that last at least the given duration.
+    pub fn min_duration(mut self, duration: Duration) -> Self {
+        self.min_duration = Some(duration);
+        self
+    }
+
+    /// Whether the filter selects every event.
+    pub fn is_empty(&self) -> bool {
+        let EventFilter {
+            event_kinds,
+            label,
+            thread_ids,
+            from,
+            to,
+            min_duration,
+        } = self;
+
+        event_kinds.is_none()
+            && label.is_none()
+            && thread_ids.is_none()
+            && from.is_none()
+            && to.is_none()
+            && min_duration.is_none()
+    }
+
+    /// Checks whether the given event of `data` is selected.
+    pub fn matches(&self, data: &ProfilingData, event: &LightweightEvent) -> bool {
+        if let Some(thread_ids) = &self.thread_ids {
+            if !thread_ids.contains(&event.thread_id) {
+                return false;
+            }
+        }
+
+        if let Some(min_duration) = self.min_duration {
+            match event.duration() {
+                Some(duration) if duration >= min_duration => {}
+                _ => return false,
+            }
+        }
+
+        if self.from.is_some() || self.to.is_some() {
+            let (start, end) = match (event.start(), event.end()) {
+                (Some(start), Some(end)) => (start, end),
+                _ => return false,
+            };
+            let offset = |t: SystemTime| {
+                t.duration_since(data.metadata().start_time)
+                    .unwrap_or_default()
+            };
+
+            if self.from.is_some_and(|from| offset(end) < from)
+                || self.to.is_some_and(|to| offset(start) > to)
+            {
+                return false;
+            }
+        }
+
+        if self.event_kinds.is_some() || self.label.is_some() {
+            let full_event = data.to_full_event(event);
+
+            if let Some(event_kinds) = &self.event_kinds {
+                if !event_kinds.contains(&full_event.event_kind[..]) {
+                    return false;
+                }
+            }
+
+            if let Some(label) = &self.label {
+                if !label.is_match(&full_event.label) {
+                    return false;
+                }
+            }
+        }
+
+        true
+    }
+}
+
+impl ProfilingData {
+    /// Returns a view of the profile that only contains the events selected
+    /// by `filter`. Everything that works on a whole profile, e.g.
+    /// `perform_analysis` or `collapse_stacks`, also works on such a view, but
+    /// only sees the selected events. For example, the self-time of an event
+    /// in a view includes the time of nested events that were filtered out.
+    /// Filters can be applied to views again to combine them.
+    pub fn filter(self, filter: &EventFilter) -> ProfilingData {
+        if filter.is_empty() {
+            return self;
+        }
+
+        let selected: Vec<usize> = self
+            .iter()
+            .filter(|event| filter.matches(&self, event))
+            .map(|event| event.event_index)
+            .collect();
+
+        self.select(&selected)
+    }
+}
+
+// The command line options for filtering a profile and rewriting its labels
+// that all tools share. This is not a doc comment because clap would use it as
+// the help text of the tool.
+#[cfg(feature = "clap")]
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct FilterArgs {
+    /// Only include events of this kind (e.g. `Query`), can be given multiple times
+    #[arg(long = "kind")]
+    pub kinds: Vec<String>,
+
+    /// Only include events whose label matches this regular expression
+    #[arg(long = "label")]
+    pub label: Option<Regex>,
+
+    /// Only include events on this thread, can be given multiple times
+    #[arg(short = 't', long = "thread", alias = "thread-id")]
+    pub threads: Vec<u32>,
+
+    /// Only include events ending after this point (in microseconds since the start of the profile)
+    #[arg(long = "from")]
+    pub from: Option<u64>,
+
+    /// Only include events starting before this point (in microseconds since the start of the profile)
+    #[arg(long = "to")]
+    pub to: Option<u64>,
+
+    /// Only include interval events with at least this duration (in microseconds)
+    #[arg(long = "min-duration", alias = "minimum-duration")]
+    pub min_duration: Option<u64>,
+
+    /// A json file with rules that rewrite labels and arguments, e.g. to merge items that only differ in a hash
+    #[arg(long = "label-rules")]
+    pub label_rules: Option<std::path::PathBuf>,
+}
+
+#[cfg(feature = "clap")]
+impl FilterArgs {
+    pub fn to_filter(&self) -> EventFilter {
+        let mut filter = EventFilter::new();
+
+        if !self.kinds.is_empty() {
+            filter = filter.event_kinds(self.kinds.iter().cloned());
+        }
+        if let Some(label) = &self.label {
+            filter = filter.label(label.clone());
+        }
+        if !self.threads.is_empty() {
+            filter = filter.thread_ids(self.threads.iter().copied());
+        }
+        if let Some(from) = self.from {
+            filter = filter.from(Duration::from_micros(from));
+        }
+        if let Some(to) = self.to {
+            filter = filter.to(Duration::from_micros(to));
+        }
+        if let Some(min_duration) = self.min_duration {
+            filter = filter.min_duration(Duration::from_micros(min_duration));
+        }
+
+        filter
+    }
+
+    /// Rewrites the labels of the given profile with the label rules, if any,
+    /// and applies the filter to it.
+    pub fn apply(
+        &self,
+        data: ProfilingData,
+    ) -> Result<ProfilingData, Box<dyn std::error::Error + Send + Sync>> {
+        let data = match &self.label_rules {
+            Some(path) => data.with_label_rules(crate::LabelRules::load(path)?),
+            None => data,
+        };
+
+        Ok(data.filter(&self.to_filter()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EventFilter;
+    use crate::{collapse_stacks, ProfilingData, ProfilingDataBuilder};
+    use regex::Regex;
+    use std::time::Duration;
+
+    fn profile() -> ProfilingData {
+        let mut b = ProfilingDataBuilder::new();
+
+        b.interval("Query", "typeck", 0, 100, 200, |b| {
+            b.interval("GenericActivity", "expand", 0, 110, 120, |_| {});
+            b.instant("QueryCacheHit", "type_of", 0, 130);
+        });
+        b.interval("Query", "type_of", 1, 150, 300, |_| {});
+        b.interval("Query", "typeck", 1, 400, 401, |_| {});
+
+        b.into_profiling_data()
+    }
+
+    fn labels(data: &ProfilingData) -> Vec<String> {
+        data.iter_full()
+            .map(|event| event.label.into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn predicates() {
+        let filter = EventFilter::new().event_kinds(["Query"]);
+        assert_eq!(
+            labels(&profile().filter(&filter)),
+            ["typeck", "type_of", "typeck"]
+        );
+
+        let filter = EventFilter::new().label(Regex::new("^type").unwrap());
+        assert_eq!(
+            labels(&profile().filter(&filter)),
+            ["type_of", "typeck", "type_of", "typeck"]
+        );
+
+        let filter = EventFilter::new().thread_ids([1]);
+        assert_eq!(labels(&profile().filter(&filter)), ["type_of", "typeck"]);
+
+        // The builder starts at 0, the events are placed relative to that.
+        let filter = EventFilter::new()
+            .from(Duration::from_nanos(125))
+            .to(Duration::from_nanos(350));
+        assert_eq!(
+            labels(&profile().filter(&filter)),
+            ["type_of", "typeck", "type_of"]
+        );
+
+        let filter = EventFilter::new().min_duration(Duration::from_nanos(100));
+        assert_eq!(labels(&profile().filter(&filter)), ["typeck", "type_of"]);
+    }
+
+    #[test]
+    fn views_compose() {
+        let data = profile()
+            .filter(&EventFilter::new().event_kinds(["Query"]))
+            .filter(&EventFilter::new().thread_ids([0]));
+
+        assert_eq!(data.num_events(), 1);
+        assert_eq!(data.iter().next_back().unwrap().event_index, 0);
+        assert_eq!(labels(&data), ["typeck"]);
+
+        // Analyses only see the selected events.
+        let stacks = collapse_stacks(&data);
+        assert_eq!(stacks["rustc;typeck"], 100);
+
+        let results = data.perform_analysis();
+        assert_eq!(results.query_data.len(), 1);
+        assert_eq!(results.total_time, Duration::from_nanos(100));
+    }
+}
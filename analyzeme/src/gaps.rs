@@ -0,0 +1,182 @@
+use crate::{NodeId, ProfilingData, ThreadCallTree};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::time::{Duration, SystemTime};
+
+/// The time between the top-level interval events of a single thread, as
+/// computed by [`ProfilingData::gaps`]. During a gap, the thread either was
+/// idle or did something that is not instrumented.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ThreadGaps {
+    pub thread_id: u32,
+    /// The start of the first top-level interval of the thread.
+    pub start: SystemTime,
+    /// The end of the last top-level interval of the thread.
+    pub end: SystemTime,
+    /// The number of gaps.
+    pub count: usize,
+    /// The summed up duration of all gaps.
+    pub total: Duration,
+    /// The largest gaps, sorted by descending duration.
+    pub largest: Vec<Gap>,
+}
+
+/// A stretch of time in which a thread was not inside any interval event.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Gap {
+    pub start: SystemTime,
+    pub end: SystemTime,
+    /// The top-level event that ended at the start of the gap.
+    pub preceding: GapNeighbor,
+    /// The top-level event that started at the end of the gap.
+    pub following: GapNeighbor,
+}
+
+/// An event next to a [`Gap`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GapNeighbor {
+    pub event_kind: String,
+    pub label: String,
+}
+
+impl ThreadGaps {
+    /// The time from the start of the first to the end of the last top-level
+    /// interval, i.e. what `AnalysisResults::total_time` counts for the thread.
+    pub fn span(&self) -> Duration {
+        self.end.duration_since(self.start).unwrap_or_default()
+    }
+
+    /// The fraction of the span that is not covered by any interval event.
+    pub fn untracked_fraction(&self) -> f64 {
+        let span = self.span();
+        if span == Duration::ZERO {
+            return 0.0;
+        }
+
+        self.total.as_secs_f64() / span.as_secs_f64()
+    }
+}
+
+impl Gap {
+    pub fn duration(&self) -> Duration {
+        self.end.duration_since(self.start).unwrap_or_default()
+    }
+}
+
+impl ProfilingData {
+    /// Finds the gaps between the top-level interval events of each thread
+    /// and returns their number, their total duration and the `n` largest of
+    /// them for each thread that recorded interval events, sorted by thread
+    /// id.
+    pub fn gaps(&self, n: usize) -> Vec<ThreadGaps> {
+        let call_tree = self.call_tree();
+
+        call_tree
+            .threads()
+            .iter()
+            .filter(|thread| !thread.roots().is_empty())
+            .map(|thread| self.thread_gaps(thread, n))
+            .collect()
+    }
+
+    fn thread_gaps(&self, thread: &ThreadCallTree, n: usize) -> ThreadGaps {
+        let roots = thread.roots();
+        let neighbor = |id: NodeId| {
+            let event = self.to_full_event(&thread[id].event);
+            GapNeighbor {
+                event_kind: event.event_kind.into_owned(),
+                label: event.label.into_owned(),
+            }
+        };
+
+        let mut gaps = Vec::new();
+        let mut count = 0;
+        let mut total = Duration::ZERO;
+
+        // The top-level event that ended last so far. Top-level events that
+        // touch or overlap don't leave a gap between them.
+        let mut last = roots[0];
+
+        for &root in &roots[1..] {
+            let (start, end) = (thread[root].start(), thread[root].end());
+            let last_end = thread[last].end();
+
+            if start > last_end {
+                count += 1;
+                total += start.duration_since(last_end).unwrap();
+                gaps.push((last_end, start, last, root));
+            }
+
+            if end > last_end {
+                last = root;
+            }
+        }
+
+        // Only decode the neighbors of the gaps that are actually returned.
+        gaps.sort_by_key(|&(start, end, _, _)| {
+            (Reverse(end.duration_since(start).unwrap()), start)
+        });
+        gaps.truncate(n);
+
+        let largest = gaps
+            .into_iter()
+            .map(|(start, end, preceding, following)| Gap {
+                start,
+                end,
+                preceding: neighbor(preceding),
+                following: neighbor(following),
+            })
+            .collect();
+
+        ThreadGaps {
+            thread_id: thread.thread_id,
+            start: thread[roots[0]].start(),
+            end: thread[last].end(),
+            count,
+            total,
+            largest,
+        }
+    }
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ProfilingDataBuilder;
+    use measureme::rustc::*;
+
+    #[test]
+    fn gaps() {
+        let mut b = ProfilingDataBuilder::new();
+
+        b.interval(GENERIC_ACTIVITY_EVENT_KIND, "a", 0, 100, 200, |b| {
+            b.interval(QUERY_EVENT_KIND, "q1", 0, 110, 120, |_| {});
+        });
+        b.interval(GENERIC_ACTIVITY_EVENT_KIND, "b", 0, 200, 250, |_| {});
+        b.interval(GENERIC_ACTIVITY_EVENT_KIND, "c", 0, 300, 310, |_| {});
+        b.interval(QUERY_EVENT_KIND, "d", 0, 400, 500, |_| {});
+        b.instant(QUERY_CACHE_HIT_EVENT_KIND, "q1", 0, 600);
+        b.interval(GENERIC_ACTIVITY_EVENT_KIND, "e", 1, 0, 50, |_| {});
+
+        let gaps = b.into_profiling_data().gaps(1);
+        assert_eq!(gaps.len(), 2);
+
+        let thread = &gaps[0];
+        assert_eq!(thread.thread_id, 0);
+        assert_eq!(thread.span(), Duration::from_nanos(400));
+        assert_eq!(thread.count, 2);
+        assert_eq!(thread.total, Duration::from_nanos(140));
+        assert!((thread.untracked_fraction() - 0.35).abs() < 1e-9);
+
+        assert_eq!(thread.largest.len(), 1);
+        let gap = &thread.largest[0];
+        assert_eq!(gap.duration(), Duration::from_nanos(90));
+        assert_eq!(gap.preceding, GapNeighbor { event_kind: GENERIC_ACTIVITY_EVENT_KIND.to_string(), label: "c".to_string() });
+        assert_eq!(gap.following, GapNeighbor { event_kind: QUERY_EVENT_KIND.to_string(), label: "d".to_string() });
+
+        let thread = &gaps[1];
+        assert_eq!((thread.thread_id, thread.count, thread.total), (1, 0, Duration::ZERO));
+        assert!(thread.largest.is_empty());
+    }
+}
@@ -0,0 +1,300 @@
+use crate::analysis::query_data_event_kind;
+use crate::{Counter, NodeId, ProfilingData, ThreadCallTree};
+use measureme::rustc::INCREMENTAL_RESULT_HASHING_EVENT_KIND;
+use rustc_hash::{FxHashMap, FxHashSet};
+use serde::{Deserialize, Serialize};
+use std::io::{self, Write};
+use std::time::Duration;
+
+/// Which items are nested in which others, summed up over all threads, as
+/// computed by [`ProfilingData::call_graph`]. An item is an event kind and a
+/// label, so e.g. a query and a generic activity with the same label are
+/// different items.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CallGraph {
+    /// Sorted by descending time.
+    pub nodes: Vec<CallGraphNode>,
+    /// Sorted by descending time.
+    pub edges: Vec<CallGraphEdge>,
+}
+
+/// All interval events of a given item.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CallGraphNode {
+    pub event_kind: String,
+    pub label: String,
+    pub invocation_count: usize,
+    pub self_time: Duration,
+    /// The summed up duration of all events. Events nested in another event
+    /// of the same item are counted twice.
+    pub time: Duration,
+}
+
+/// The events of the item `callee` that were directly nested in an event of
+/// the item `caller`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CallGraphEdge {
+    pub caller_event_kind: String,
+    pub caller: String,
+    pub callee_event_kind: String,
+    pub callee: String,
+    pub count: usize,
+    /// The summed up duration of the callee events.
+    pub time: Duration,
+}
+
+impl CallGraph {
+    /// Keeps the `n` nodes with the highest time and the edges between them.
+    pub fn truncate(&mut self, n: usize) {
+        self.nodes.truncate(n);
+
+        let items: FxHashSet<(&str, &str)> = self
+            .nodes
+            .iter()
+            .map(|node| (&node.event_kind[..], &node.label[..]))
+            .collect();
+        self.edges.retain(|edge| {
+            items.contains(&(&edge.caller_event_kind[..], &edge.caller[..]))
+                && items.contains(&(&edge.callee_event_kind[..], &edge.callee[..]))
+        });
+    }
+
+    /// Writes the graph in the Graphviz DOT format. Nodes show their label,
+    /// event kind, self-time, time and invocation count, edges their count
+    /// and time, and the width of an edge grows with its time. `counter` is
+    /// used to format durations.
+    pub fn write_dot(&self, mut writer: impl Write, counter: &Counter) -> io::Result<()> {
+        let max_edge_time = self
+            .edges
+            .iter()
+            .map(|edge| edge.time)
+            .max()
+            .unwrap_or_default()
+            .as_secs_f64();
+
+        writeln!(writer, "digraph calls {{")?;
+        writeln!(writer, "    node [shape=box];")?;
+
+        for node in &self.nodes {
+            writeln!(
+                writer,
+                "    \"{}\" [label=\"{}\\n({})\\nself: {}\\ntotal: {}\\n{}x\"];",
+                dot_id(&node.event_kind, &node.label),
+                dot_escape(&node.label),
+                dot_escape(&node.event_kind),
+                counter.format_duration(node.self_time),
+                counter.format_duration(node.time),
+                node.invocation_count,
+            )?;
+        }
+
+        for edge in &self.edges {
+            let penwidth = if max_edge_time > 0.0 {
+                1.0 + 4.0 * edge.time.as_secs_f64() / max_edge_time
+            } else {
+                1.0
+            };
+
+            writeln!(
+                writer,
+                "    \"{}\" -> \"{}\" [label=\"{}x\\n{}\", penwidth={:.2}];",
+                dot_id(&edge.caller_event_kind, &edge.caller),
+                dot_id(&edge.callee_event_kind, &edge.callee),
+                edge.count,
+                counter.format_duration(edge.time),
+                penwidth,
+            )?;
+        }
+
+        writeln!(writer, "}}")
+    }
+}
+
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// The name of the node of an item, which must be unique within the graph.
+fn dot_id(event_kind: &str, label: &str) -> String {
+    dot_escape(&format!("{}/{}", event_kind, label))
+}
+
+impl ProfilingData {
+    /// Builds a graph with a node for each item, i.e. event kind and label, of
+    /// an interval event and an edge from each item to the items of the
+    /// events directly nested in its events. Events that are part of a query,
+    /// like incremental result hashing events, are counted as part of the
+    /// query they belong to.
+    pub fn call_graph(&self) -> CallGraph {
+        let call_tree = self.call_tree();
+
+        let mut nodes = FxHashMap::<(String, String), CallGraphNode>::default();
+        let mut edges = FxHashMap::<((String, String), (String, String)), CallGraphEdge>::default();
+
+        for thread in call_tree.threads() {
+            for (id, node) in thread.nodes() {
+                let event = self.to_full_event(&node.event);
+                let item = (
+                    query_data_event_kind(&event.event_kind).to_owned(),
+                    event.label.into_owned(),
+                );
+
+                let graph_node = nodes.entry(item.clone()).or_insert_with(|| CallGraphNode {
+                    event_kind: item.0.clone(),
+                    label: item.1.clone(),
+                    invocation_count: 0,
+                    self_time: Duration::ZERO,
+                    time: Duration::ZERO,
+                });
+                graph_node.self_time += node.exclusive_time;
+
+                if event.event_kind == INCREMENTAL_RESULT_HASHING_EVENT_KIND {
+                    continue;
+                }
+
+                graph_node.invocation_count += 1;
+                graph_node.time += node.inclusive_time;
+
+                if let Some(caller) = self.caller(thread, id) {
+                    let edge = edges
+                        .entry((caller.clone(), item.clone()))
+                        .or_insert_with(|| CallGraphEdge {
+                            caller_event_kind: caller.0,
+                            caller: caller.1,
+                            callee_event_kind: item.0,
+                            callee: item.1,
+                            count: 0,
+                            time: Duration::ZERO,
+                        });
+                    edge.count += 1;
+                    edge.time += node.inclusive_time;
+                }
+            }
+        }
+
+        let mut nodes: Vec<_> = nodes.into_values().collect();
+        nodes.sort_by(|a, b| {
+            b.time
+                .cmp(&a.time)
+                .then_with(|| (&a.label, &a.event_kind).cmp(&(&b.label, &b.event_kind)))
+        });
+
+        let mut edges: Vec<_> = edges.into_iter().collect();
+        edges.sort_by(|(a_key, a), (b_key, b)| b.time.cmp(&a.time).then_with(|| a_key.cmp(b_key)));
+        let edges = edges.into_iter().map(|(_, edge)| edge).collect();
+
+        CallGraph { nodes, edges }
+    }
+
+    /// The event kind and label of the innermost enclosing event that isn't an
+    /// incremental result hashing event.
+    fn caller(&self, thread: &ThreadCallTree, id: NodeId) -> Option<(String, String)> {
+        let mut parent = thread[id].parent;
+
+        while let Some(id) = parent {
+            let event = self.to_full_event(&thread[id].event);
+            if event.event_kind != INCREMENTAL_RESULT_HASHING_EVENT_KIND {
+                return Some((
+                    query_data_event_kind(&event.event_kind).to_owned(),
+                    event.label.into_owned(),
+                ));
+            }
+            parent = thread[id].parent;
+        }
+
+        None
+    }
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ProfilingDataBuilder;
+    use measureme::rustc::*;
+
+    #[test]
+    fn call_graph() {
+        let mut b = ProfilingDataBuilder::new();
+
+        b.interval(QUERY_EVENT_KIND, "typeck", 0, 100, 200, |b| {
+            b.interval(QUERY_EVENT_KIND, "mir_built", 0, 110, 150, |_| {});
+            b.interval(INCREMENTAL_RESULT_HASHING_EVENT_KIND, "typeck", 0, 160, 190, |b| {
+                b.interval(QUERY_EVENT_KIND, "type_of", 0, 170, 180, |_| {});
+            });
+        });
+        b.interval(QUERY_EVENT_KIND, "typeck", 1, 100, 150, |b| {
+            b.interval(QUERY_EVENT_KIND, "mir_built", 1, 110, 130, |_| {});
+        });
+
+        let graph = b.into_profiling_data().call_graph();
+
+        let nodes: Vec<_> = graph.nodes.iter()
+            .map(|node| (&node.event_kind[..], &node.label[..], node.invocation_count, node.self_time.as_nanos(), node.time.as_nanos()))
+            .collect();
+        assert_eq!(nodes, vec![
+            (QUERY_EVENT_KIND, "typeck", 2, 80, 150),
+            (QUERY_EVENT_KIND, "mir_built", 2, 60, 60),
+            (QUERY_EVENT_KIND, "type_of", 1, 10, 10),
+        ]);
+
+        let edges: Vec<_> = graph.edges.iter()
+            .map(|edge| (&edge.caller[..], &edge.callee[..], edge.count, edge.time.as_nanos()))
+            .collect();
+        assert_eq!(edges, vec![
+            ("typeck", "mir_built", 2, 60),
+            ("typeck", "type_of", 1, 10),
+        ]);
+
+        let mut truncated = graph.clone();
+        truncated.truncate(2);
+        assert_eq!(truncated.nodes.len(), 2);
+        assert_eq!(truncated.edges.len(), 1);
+
+        let mut dot = Vec::new();
+        graph.write_dot(&mut dot, &Counter::default()).unwrap();
+        let dot = String::from_utf8(dot).unwrap();
+        assert!(dot.starts_with("digraph calls {\n"));
+        assert!(dot.contains("    \"Query/typeck\" [label=\"typeck\\n(Query)\\nself: 80.00ns\\ntotal: 150.00ns\\n2x\"];\n"));
+        assert!(dot.contains("    \"Query/typeck\" -> \"Query/mir_built\" [label=\"2x\\n60.00ns\", penwidth=5.00];\n"));
+    }
+
+    #[test]
+    fn same_label_different_kinds() {
+        let mut b = ProfilingDataBuilder::new();
+
+        b.interval(GENERIC_ACTIVITY_EVENT_KIND, "codegen", 0, 100, 200, |b| {
+            b.interval(QUERY_EVENT_KIND, "codegen", 0, 110, 150, |b| {
+                b.interval(QUERY_EVENT_KIND, "type_of", 0, 120, 130, |_| {});
+            });
+        });
+
+        let graph = b.into_profiling_data().call_graph();
+
+        let nodes: Vec<_> = graph.nodes.iter()
+            .map(|node| (&node.event_kind[..], &node.label[..], node.invocation_count, node.self_time.as_nanos()))
+            .collect();
+        assert_eq!(nodes, vec![
+            (GENERIC_ACTIVITY_EVENT_KIND, "codegen", 1, 60),
+            (QUERY_EVENT_KIND, "codegen", 1, 30),
+            (QUERY_EVENT_KIND, "type_of", 1, 10),
+        ]);
+
+        let edges: Vec<_> = graph.edges.iter()
+            .map(|edge| (&edge.caller_event_kind[..], &edge.caller[..], &edge.callee_event_kind[..], &edge.callee[..]))
+            .collect();
+        assert_eq!(edges, vec![
+            (GENERIC_ACTIVITY_EVENT_KIND, "codegen", QUERY_EVENT_KIND, "codegen"),
+            (QUERY_EVENT_KIND, "codegen", QUERY_EVENT_KIND, "type_of"),
+        ]);
+
+        let mut truncated = graph.clone();
+        truncated.truncate(2);
+        assert_eq!(truncated.edges.len(), 1);
+
+        let mut dot = Vec::new();
+        graph.write_dot(&mut dot, &Counter::default()).unwrap();
+        let dot = String::from_utf8(dot).unwrap();
+        assert!(dot.contains("    \"GenericActivity/codegen\" -> \"Query/codegen\""));
+    }
+}
@@ -0,0 +1,155 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// The number of bits of a value that determine its bucket. Values below
+/// `2^SUB_BUCKET_BITS` nanoseconds get a bucket of their own, larger values
+/// share a bucket with values that differ by less than `2^-(SUB_BUCKET_BITS - 1)`,
+/// i.e. about 6%.
+const SUB_BUCKET_BITS: u32 = 5;
+
+/// A histogram of durations with logarithmically sized buckets, like an HDR
+/// histogram: the bucket of a duration is determined by its highest
+/// `SUB_BUCKET_BITS` bits, so the relative error of all reported values is
+/// bounded.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Histogram {
+    /// The number of recorded durations.
+    pub count: u64,
+    /// The exact maximum of the recorded durations.
+    pub max: Duration,
+    /// The non-empty buckets, in ascending order.
+    pub buckets: Vec<HistogramBucket>,
+}
+
+/// The durations of a `Histogram` that lie within `start..=end`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct HistogramBucket {
+    pub start: Duration,
+    pub end: Duration,
+    pub count: u64,
+}
+
+impl Histogram {
+    pub fn record(&mut self, duration: Duration) {
+        self.record_n(duration, 1);
+    }
+
+    fn record_n(&mut self, duration: Duration, count: u64) {
+        let (start, end) = bucket_bounds(duration.as_nanos().min(u64::MAX as u128) as u64);
+
+        match self
+            .buckets
+            .binary_search_by_key(&start, |bucket| bucket.start)
+        {
+            Ok(index) => self.buckets[index].count += count,
+            Err(index) => self
+                .buckets
+                .insert(index, HistogramBucket { start, end, count }),
+        }
+
+        self.count += count;
+        self.max = self.max.max(duration);
+    }
+
+    /// Adds all durations recorded in `other`.
+    pub fn add(&mut self, other: &Histogram) {
+        for bucket in &other.buckets {
+            self.record_n(bucket.start, bucket.count);
+        }
+
+        self.max = self.max.max(other.max);
+    }
+
+    /// Returns the duration below or at which `percentile` percent of the
+    /// recorded durations lie, rounded up to the end of its bucket, or `None`
+    /// if the histogram is empty. `percentile` must be within `0.0..=100.0`.
+    pub fn percentile(&self, percentile: f64) -> Option<Duration> {
+        assert!((0.0..=100.0).contains(&percentile));
+
+        if self.count == 0 {
+            return None;
+        }
+
+        let rank = ((percentile / 100.0 * self.count as f64).ceil() as u64).max(1);
+
+        let mut seen = 0;
+        for bucket in &self.buckets {
+            seen += bucket.count;
+            if seen >= rank {
+                return Some(bucket.end.min(self.max));
+            }
+        }
+
+        Some(self.max)
+    }
+}
+
+/// Returns the first and last value, in nanoseconds, of the bucket of `value`.
+fn bucket_bounds(value: u64) -> (Duration, Duration) {
+    let significant_bits = u64::BITS - value.leading_zeros();
+    let shift = significant_bits.saturating_sub(SUB_BUCKET_BITS);
+
+    let start = (value >> shift) << shift;
+    let end = start + ((1 << shift) - 1);
+
+    (Duration::from_nanos(start), Duration::from_nanos(end))
+}
+
+/// The distributions of the self-time and time of the individual events of
+/// one label and event kind. Only collected if `AnalysisOptions::histograms`
+/// is set.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct QueryHistograms {
+    pub label: String,
+    pub event_kind: String,
+    pub self_time: Histogram,
+    pub time: Histogram,
+}
+
+impl QueryHistograms {
+    pub(crate) fn add(&mut self, other: &QueryHistograms) {
+        self.self_time.add(&other.self_time);
+        self.time.add(&other.time);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets() {
+        let nanos = Duration::from_nanos;
+
+        assert_eq!(bucket_bounds(0), (nanos(0), nanos(0)));
+        assert_eq!(bucket_bounds(31), (nanos(31), nanos(31)));
+        assert_eq!(bucket_bounds(32), (nanos(32), nanos(33)));
+        assert_eq!(bucket_bounds(1000), (nanos(992), nanos(1023)));
+        assert_eq!(bucket_bounds(u64::MAX).1, nanos(u64::MAX));
+    }
+
+    #[test]
+    fn percentiles() {
+        let mut histogram = Histogram::default();
+        assert_eq!(histogram.percentile(50.0), None);
+
+        for nanos in 1..=100 {
+            histogram.record(Duration::from_nanos(nanos));
+        }
+        histogram.record(Duration::from_millis(10));
+
+        assert_eq!(histogram.count, 101);
+        assert_eq!(histogram.percentile(0.0), Some(Duration::from_nanos(1)));
+        assert_eq!(histogram.percentile(50.0), Some(Duration::from_nanos(51)));
+        assert_eq!(histogram.percentile(90.0), Some(Duration::from_nanos(91)));
+        assert_eq!(histogram.percentile(99.0), Some(Duration::from_nanos(103)));
+        assert_eq!(histogram.percentile(100.0), Some(Duration::from_millis(10)));
+
+        let mut sum = Histogram::default();
+        sum.add(&histogram);
+        sum.add(&histogram);
+        assert_eq!(sum.count, 202);
+        assert_eq!(sum.max, Duration::from_millis(10));
+        assert_eq!(sum.percentile(50.0), histogram.percentile(50.0));
+    }
+}
@@ -0,0 +1,203 @@
+use crate::{AnalysisResults, QueryData};
+use measureme::rustc::QUERY_EVENT_KIND;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// How much incremental compilation helped a single query.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IncrementalQueryData {
+    pub label: String,
+    /// How many times the result was loaded from the incremental cache.
+    pub load_count: usize,
+    pub load_time: Duration,
+    /// How many times the query provider was executed.
+    pub recompute_count: usize,
+    /// The time spent executing the query provider, including hashing its
+    /// result.
+    pub recompute_time: Duration,
+    pub hashing_time: Duration,
+    /// The total time of the query, including loading and blocked time.
+    pub time: Duration,
+    pub number_of_cache_hits: usize,
+}
+
+impl IncrementalQueryData {
+    fn new(data: &QueryData) -> IncrementalQueryData {
+        IncrementalQueryData {
+            label: data.label.clone(),
+            load_count: data.incremental_load_count,
+            load_time: data.incremental_load_time,
+            recompute_count: data.invocation_count,
+            recompute_time: data
+                .time
+                .saturating_sub(data.incremental_load_time)
+                .saturating_sub(data.blocked_time),
+            hashing_time: data.incremental_hashing_time,
+            time: data.time,
+            number_of_cache_hits: data.number_of_cache_hits,
+        }
+    }
+
+    pub fn average_load_time(&self) -> Option<Duration> {
+        average(self.load_time, self.load_count)
+    }
+
+    pub fn average_recompute_time(&self) -> Option<Duration> {
+        average(self.recompute_time, self.recompute_count)
+    }
+
+    /// The time spent hashing results as a percentage of the query's time.
+    pub fn hashing_overhead(&self) -> f64 {
+        percentage(self.hashing_time, self.time)
+    }
+
+    /// Whether loading a result took longer on average than computing it.
+    /// `false` if the query was never loaded or never computed.
+    pub fn is_loading_slower(&self) -> bool {
+        match (self.average_load_time(), self.average_recompute_time()) {
+            (Some(load), Some(recompute)) => load > recompute,
+            _ => false,
+        }
+    }
+
+    /// An estimate of the time saved by loading results instead of computing
+    /// them, assuming that each load would have taken as long as an average
+    /// computation. `None` if the query was never computed, so that there is
+    /// nothing to compare with.
+    pub fn estimated_savings(&self) -> Option<Duration> {
+        let recompute = self.average_recompute_time()?;
+        let would_have_taken =
+            Duration::from_nanos((recompute.as_nanos() * self.load_count as u128) as u64);
+        Some(would_have_taken.saturating_sub(self.load_time))
+    }
+}
+
+/// How much incremental compilation helped, as computed by
+/// [`AnalysisResults::incremental_report`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IncrementalReport {
+    /// The data of each query, sorted by label.
+    pub queries: Vec<IncrementalQueryData>,
+    pub load_count: usize,
+    pub load_time: Duration,
+    pub recompute_count: usize,
+    pub recompute_time: Duration,
+    pub hashing_time: Duration,
+    /// The total time of all queries.
+    pub time: Duration,
+    /// The sum of the estimated savings of all queries.
+    pub estimated_savings: Duration,
+}
+
+impl IncrementalReport {
+    /// The time spent hashing results as a percentage of the time of all
+    /// queries.
+    pub fn hashing_overhead(&self) -> f64 {
+        percentage(self.hashing_time, self.time)
+    }
+
+    /// The queries for which loading a result took longer on average than
+    /// computing it.
+    pub fn loading_slower_than_recomputing(&self) -> impl Iterator<Item = &IncrementalQueryData> {
+        self.queries
+            .iter()
+            .filter(|query| query.is_loading_slower())
+    }
+}
+
+impl AnalysisResults {
+    /// Summarizes how much time the queries spent loading results from the
+    /// incremental cache compared to computing them.
+    pub fn incremental_report(&self) -> IncrementalReport {
+        let queries: Vec<_> = self
+            .query_data
+            .iter()
+            .filter(|data| data.event_kind == QUERY_EVENT_KIND)
+            .map(IncrementalQueryData::new)
+            .collect();
+
+        IncrementalReport {
+            load_count: queries.iter().map(|query| query.load_count).sum(),
+            load_time: queries.iter().map(|query| query.load_time).sum(),
+            recompute_count: queries.iter().map(|query| query.recompute_count).sum(),
+            recompute_time: queries.iter().map(|query| query.recompute_time).sum(),
+            hashing_time: queries.iter().map(|query| query.hashing_time).sum(),
+            time: queries.iter().map(|query| query.time).sum(),
+            estimated_savings: queries
+                .iter()
+                .filter_map(|query| query.estimated_savings())
+                .sum(),
+            queries,
+        }
+    }
+}
+
+fn average(total: Duration, count: usize) -> Option<Duration> {
+    if count == 0 {
+        return None;
+    }
+
+    Some(Duration::from_nanos(
+        (total.as_nanos() / count as u128) as u64,
+    ))
+}
+
+fn percentage(part: Duration, total: Duration) -> f64 {
+    if total == Duration::ZERO {
+        return 0.0;
+    }
+
+    part.as_nanos() as f64 / total.as_nanos() as f64 * 100.0
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ProfilingDataBuilder;
+    use measureme::rustc::*;
+
+    #[test]
+    fn incremental_report() {
+        let mut b = ProfilingDataBuilder::new();
+
+        b.interval(QUERY_EVENT_KIND, "q1", 0, 0, 100, |b| {
+            b.interval(INCREMENTAL_RESULT_HASHING_EVENT_KIND, "q1", 0, 80, 90, |_| {});
+        });
+        b.interval(QUERY_EVENT_KIND, "q1", 0, 100, 200, |b| {
+            b.interval(INCREMENTAL_RESULT_HASHING_EVENT_KIND, "q1", 0, 180, 190, |_| {});
+        });
+        b.interval(INCREMENTAL_LOAD_RESULT_EVENT_KIND, "q1", 0, 200, 220, |_| {});
+        b.interval(INCREMENTAL_LOAD_RESULT_EVENT_KIND, "q1", 0, 220, 240, |_| {});
+        b.interval(INCREMENTAL_LOAD_RESULT_EVENT_KIND, "q1", 0, 240, 260, |_| {});
+        b.interval(QUERY_EVENT_KIND, "q2", 0, 260, 270, |_| {});
+        b.interval(INCREMENTAL_LOAD_RESULT_EVENT_KIND, "q2", 0, 270, 320, |_| {});
+        b.interval(GENERIC_ACTIVITY_EVENT_KIND, "a", 0, 320, 400, |_| {});
+
+        let report = b.into_profiling_data().perform_analysis().incremental_report();
+
+        assert_eq!(report.queries.len(), 2);
+
+        let q1 = &report.queries[0];
+        assert_eq!(q1.label, "q1");
+        assert_eq!((q1.load_count, q1.recompute_count), (3, 2));
+        assert_eq!(q1.average_load_time(), Some(Duration::from_nanos(20)));
+        assert_eq!(q1.average_recompute_time(), Some(Duration::from_nanos(100)));
+        assert_eq!(q1.hashing_time, Duration::from_nanos(20));
+        assert!((q1.hashing_overhead() - 20.0 / 260.0 * 100.0).abs() < 1e-9);
+        assert_eq!(q1.estimated_savings(), Some(Duration::from_nanos(240)));
+        assert!(!q1.is_loading_slower());
+
+        let q2 = &report.queries[1];
+        assert_eq!(q2.estimated_savings(), Some(Duration::ZERO));
+        assert!(q2.is_loading_slower());
+
+        assert_eq!(report.load_time, Duration::from_nanos(110));
+        assert_eq!(report.recompute_time, Duration::from_nanos(210));
+        assert_eq!(report.time, Duration::from_nanos(320));
+        assert_eq!(report.estimated_savings, Duration::from_nanos(240));
+
+        let slower: Vec<_> = report.loading_slower_than_recomputing().map(|query| &query.label[..]).collect();
+        assert_eq!(slower, vec!["q2"]);
+    }
+}
@@ -0,0 +1,148 @@
+use crate::ProfilingData;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::time::{Duration, SystemTime};
+
+/// What [`ProfilingData::slowest_invocations`] ranks invocations by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InvocationOrder {
+    SelfTime,
+    Time,
+}
+
+/// A single interval event, e.g. one execution of a query.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Invocation {
+    pub event_kind: String,
+    pub label: String,
+    /// The arguments of the event, e.g. the query key.
+    pub args: Vec<String>,
+    pub thread_id: u32,
+    pub start: SystemTime,
+    pub time: Duration,
+    /// The time minus the time of the directly nested events.
+    pub self_time: Duration,
+}
+
+impl ProfilingData {
+    /// Returns the `n` interval events with the highest self-time or time,
+    /// in descending order, optionally only those with the given label.
+    /// Events that took equally long are ordered by their start time.
+    pub fn slowest_invocations(
+        &self,
+        n: usize,
+        order: InvocationOrder,
+        label: Option<&str>,
+    ) -> Vec<Invocation> {
+        if n == 0 {
+            return Vec::new();
+        }
+
+        let call_tree = self.call_tree();
+
+        // A min-heap of the slowest invocations seen so far, so that the
+        // fastest of them can be dropped once there are more than `n`.
+        let mut slowest = BinaryHeap::with_capacity(n + 1);
+
+        for thread in call_tree.threads() {
+            for (_, node) in thread.nodes() {
+                if let Some(label) = label {
+                    if self.to_full_event(&node.event).label != label {
+                        continue;
+                    }
+                }
+
+                let time = match order {
+                    InvocationOrder::SelfTime => node.exclusive_time,
+                    InvocationOrder::Time => node.inclusive_time,
+                };
+
+                slowest.push(Reverse((
+                    time,
+                    Reverse(node.start()),
+                    thread.thread_id,
+                    node.event.event_index,
+                    node.exclusive_time,
+                )));
+
+                if slowest.len() > n {
+                    slowest.pop();
+                }
+            }
+        }
+
+        slowest
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(invocation)| {
+                let (_, Reverse(start), thread_id, event_index, self_time) = invocation;
+                let event = self.decode_full_event(event_index);
+
+                Invocation {
+                    event_kind: event.event_kind.to_string(),
+                    label: event.label.to_string(),
+                    args: event
+                        .additional_data
+                        .iter()
+                        .map(|arg| arg.to_string())
+                        .collect(),
+                    thread_id,
+                    start,
+                    time: event.duration().unwrap(),
+                    self_time,
+                }
+            })
+            .collect()
+    }
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ProfilingDataBuilder;
+    use measureme::rustc::*;
+
+    fn slowest(data: &ProfilingData, n: usize, order: InvocationOrder, label: Option<&str>) -> Vec<(String, Vec<String>, u32, u64)> {
+        data.slowest_invocations(n, order, label)
+            .into_iter()
+            .map(|invocation| {
+                let nanos = match order {
+                    InvocationOrder::SelfTime => invocation.self_time,
+                    InvocationOrder::Time => invocation.time,
+                };
+                (invocation.label, invocation.args, invocation.thread_id, nanos.as_nanos() as u64)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn slowest_invocations() {
+        let mut b = ProfilingDataBuilder::new();
+
+        b.interval(QUERY_EVENT_KIND, "typeck\u{1e}a", 0, 100, 200, |b| {
+            b.interval(QUERY_EVENT_KIND, "mir_built\u{1e}a", 0, 110, 170, |_| {});
+        });
+        b.interval(QUERY_EVENT_KIND, "typeck\u{1e}b", 1, 100, 150, |_| {});
+        b.interval(QUERY_EVENT_KIND, "typeck\u{1e}c", 1, 150, 190, |_| {});
+
+        let data = b.into_profiling_data();
+        let s = |label: &str| label.to_string();
+
+        assert_eq!(slowest(&data, 2, InvocationOrder::SelfTime, None), vec![
+            (s("mir_built"), vec![s("a")], 0, 60),
+            (s("typeck"), vec![s("b")], 1, 50),
+        ]);
+        assert_eq!(slowest(&data, 2, InvocationOrder::Time, None), vec![
+            (s("typeck"), vec![s("a")], 0, 100),
+            (s("mir_built"), vec![s("a")], 0, 60),
+        ]);
+        assert_eq!(slowest(&data, 10, InvocationOrder::SelfTime, Some("typeck")), vec![
+            (s("typeck"), vec![s("b")], 1, 50),
+            (s("typeck"), vec![s("a")], 0, 40),
+            (s("typeck"), vec![s("c")], 1, 40),
+        ]);
+        assert!(slowest(&data, 0, InvocationOrder::Time, None).is_empty());
+    }
+}
@@ -0,0 +1,222 @@
+use crate::{Event, ProfilingData};
+use regex::Regex;
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::error::Error;
+use std::path::Path;
+
+/// Rules that rewrite the labels and arguments of events, e.g. to strip the
+/// hashes from codegen unit names so that equivalent labels are merged.
+/// Applied to a profile with [`ProfilingData::with_label_rules`].
+///
+/// The rules are applied in order, each to the result of the previous ones.
+#[derive(Clone, Debug, Default)]
+pub struct LabelRules {
+    rules: Vec<LabelRule>,
+}
+
+#[derive(Clone, Debug)]
+struct LabelRule {
+    pattern: Regex,
+    action: LabelRuleAction,
+}
+
+#[derive(Clone, Debug)]
+enum LabelRuleAction {
+    /// Replaces every match, see `Regex::replace_all`.
+    Replace(String),
+    /// Replaces the whole string if there is any match.
+    Group(String),
+}
+
+/// A rule as written in a rules file, see `LabelRules::from_json`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawLabelRule {
+    pattern: String,
+    #[serde(default)]
+    replace: Option<String>,
+    #[serde(default)]
+    group: Option<String>,
+}
+
+impl LabelRules {
+    /// Creates an empty set of rules, which leaves every label as it is.
+    pub fn new() -> LabelRules {
+        LabelRules::default()
+    }
+
+    /// Replaces every match of `pattern` with `replacement`, which can refer
+    /// to capture groups like `$1`.
+    pub fn replace(mut self, pattern: Regex, replacement: impl Into<String>) -> Self {
+        self.rules.push(LabelRule {
+            pattern,
+            action: LabelRuleAction::Replace(replacement.into()),
+        });
+        self
+    }
+
+    /// Replaces labels and arguments that match `pattern` with `name`.
+    pub fn group(mut self, pattern: Regex, name: impl Into<String>) -> Self {
+        self.rules.push(LabelRule {
+            pattern,
+            action: LabelRuleAction::Group(name.into()),
+        });
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Parses rules from a json array like the following, where each rule
+    /// either has a `replace` or a `group`:
+    ///
+    /// ```json
+    /// [
+    ///     { "pattern": "-cgu\\.[0-9]+$", "replace": "-cgu" },
+    ///     { "pattern": "DefId\\([0-9]+:[0-9]+ ~ ", "replace": "DefId(" },
+    ///     { "pattern": "^LLVM_", "group": "LLVM" }
+    /// ]
+    /// ```
+    pub fn from_json(json: &str) -> Result<LabelRules, Box<dyn Error + Send + Sync>> {
+        let raw_rules: Vec<RawLabelRule> = serde_json::from_str(json)?;
+        let mut rules = LabelRules::new();
+
+        for raw_rule in raw_rules {
+            let pattern = Regex::new(&raw_rule.pattern)?;
+
+            rules = match (raw_rule.replace, raw_rule.group) {
+                (Some(replacement), None) => rules.replace(pattern, replacement),
+                (None, Some(name)) => rules.group(pattern, name),
+                _ => {
+                    return Err(format!(
+                        "label rule `{}` must have either a `replace` or a `group`",
+                        raw_rule.pattern
+                    )
+                    .into())
+                }
+            };
+        }
+
+        Ok(rules)
+    }
+
+    /// Reads rules from a json file, see `LabelRules::from_json`.
+    pub fn load(path: &Path) -> Result<LabelRules, Box<dyn Error + Send + Sync>> {
+        let json = std::fs::read_to_string(path)?;
+        LabelRules::from_json(&json)
+            .map_err(|e| format!("invalid label rules in `{}`: {}", path.display(), e).into())
+    }
+
+    /// Applies all rules to `s`.
+    pub fn apply<'a>(&self, mut s: Cow<'a, str>) -> Cow<'a, str> {
+        for rule in &self.rules {
+            let rewritten = match &rule.action {
+                LabelRuleAction::Replace(replacement) => {
+                    match rule.pattern.replace_all(&s, &replacement[..]) {
+                        Cow::Owned(replaced) => Some(replaced),
+                        Cow::Borrowed(_) => None,
+                    }
+                }
+                LabelRuleAction::Group(name) => {
+                    if rule.pattern.is_match(&s) && *s != name[..] {
+                        Some(name.clone())
+                    } else {
+                        None
+                    }
+                }
+            };
+
+            if let Some(rewritten) = rewritten {
+                s = Cow::Owned(rewritten);
+            }
+        }
+
+        s
+    }
+
+    pub(crate) fn apply_to_event(&self, event: &mut Event<'_>) {
+        event.label = self.apply(std::mem::take(&mut event.label));
+
+        for arg in &mut event.additional_data {
+            *arg = self.apply(std::mem::take(arg));
+        }
+    }
+}
+
+impl ProfilingData {
+    /// Rewrites the labels and arguments of all events with `rules`. Since
+    /// all analyses see the rewritten labels, events whose labels only differ
+    /// in e.g. a hash are merged everywhere, which keeps diffs across commits
+    /// meaningful. Filters applied afterwards see the rewritten labels, too.
+    pub fn with_label_rules(mut self, rules: LabelRules) -> ProfilingData {
+        if rules.is_empty() {
+            return self;
+        }
+
+        self.label_rules = Some(match self.label_rules {
+            Some(mut existing) => {
+                existing.rules.extend(rules.rules);
+                existing
+            }
+            None => rules,
+        });
+        self
+    }
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{collapse_stacks, ProfilingDataBuilder};
+    use measureme::rustc::*;
+
+    #[test]
+    fn apply() {
+        let rules = LabelRules::from_json(r#"[
+            { "pattern": "-cgu\\.[0-9]+$", "replace": "-cgu" },
+            { "pattern": "\\{closure#([0-9]+)\\}", "replace": "{closure}" },
+            { "pattern": "^LLVM_", "group": "LLVM" }
+        ]"#).unwrap();
+
+        let apply = |s: &str| rules.apply(Cow::Borrowed(s)).into_owned();
+        assert_eq!(apply("regex-cgu.12"), "regex-cgu");
+        assert_eq!(apply("main::{closure#0}::{closure#1}"), "main::{closure}::{closure}");
+        assert_eq!(apply("LLVM_passes"), "LLVM");
+        assert_eq!(apply("typeck"), "typeck");
+        assert!(matches!(rules.apply(Cow::Borrowed("typeck")), Cow::Borrowed(_)));
+
+        assert!(LabelRules::from_json(r#"[{ "pattern": "a" }]"#).is_err());
+        assert!(LabelRules::from_json(r#"[{ "pattern": "a", "replace": "b", "group": "c" }]"#).is_err());
+        assert!(LabelRules::from_json(r#"[{ "pattern": "(", "replace": "b" }]"#).is_err());
+    }
+
+    #[test]
+    fn with_label_rules() {
+        let mut b = ProfilingDataBuilder::new();
+
+        b.interval(GENERIC_ACTIVITY_EVENT_KIND, "codegen_module\u{1e}regex-cgu.0", 0, 100, 200, |b| {
+            b.interval(GENERIC_ACTIVITY_EVENT_KIND, "LLVM_module_passes", 0, 110, 150, |_| {});
+        });
+        b.interval(GENERIC_ACTIVITY_EVENT_KIND, "codegen_module\u{1e}regex-cgu.1", 0, 200, 250, |b| {
+            b.interval(GENERIC_ACTIVITY_EVENT_KIND, "LLVM_emit_obj", 0, 210, 230, |_| {});
+        });
+
+        let rules = LabelRules::new()
+            .replace(Regex::new("-cgu\\.[0-9]+$").unwrap(), "-cgu")
+            .group(Regex::new("^LLVM_").unwrap(), "LLVM");
+        let data = b.into_profiling_data().with_label_rules(rules);
+
+        let args: Vec<_> = data.iter_full().map(|event| event.additional_data[..].join(",")).collect();
+        assert_eq!(args, vec!["", "regex-cgu", "", "regex-cgu"]);
+
+        let stacks = collapse_stacks(&data);
+        assert_eq!(stacks["rustc;codegen_module"], 90);
+        assert_eq!(stacks["rustc;codegen_module;LLVM"], 60);
+
+        let results = data.perform_analysis();
+        assert_eq!(results.query_data_by_label("LLVM").invocation_count, 2);
+    }
+}
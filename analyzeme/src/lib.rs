@@ -9,16 +9,97 @@
 //!
 //! To retrieve an `Iterator` of all of the events in the file,
 //! call the [`ProfilingData::iter()`] method.
+//!
+//! To analyze the profiles of several processes, e.g. of a whole `cargo
+//! build`, at once, merge them with [`ProfilingData::merge()`].
+//!
+//! To restrict a profile to certain event kinds, labels, threads or a time
+//! window, create a filtered view of it with [`ProfilingData::filter()`].
+//!
+//! To merge labels that only differ in e.g. a hash or a closure number, rewrite
+//! them with [`LabelRules`] via [`ProfilingData::with_label_rules()`].
+//!
+//! To look up the events of a thread within a certain time range, use
+//! [`ProfilingData::events_in_range()`] or [`ProfilingData::events_overlapping()`].
+//!
+//! To find the individual events that took longest, e.g. the slowest
+//! executions of a query together with their query keys, use
+//! [`ProfilingData::slowest_invocations()`].
+//!
+//! To walk the interval events of each thread in the order they are nested,
+//! use [`ProfilingData::call_tree()`]. To see which labels are nested in which
+//! others, summed up over all events, use [`ProfilingData::call_graph()`].
+//!
+//! To find out which events determined the wall-clock time of a profile with
+//! several threads, use [`ProfilingData::critical_path()`]. To see how many
+//! threads were busy over time, use [`ProfilingData::concurrency()`]. To find
+//! the time between the top-level events of each thread, where a thread was
+//! idle or instrumentation is missing, use [`ProfilingData::gaps()`].
+//!
+//! To see how much time incremental compilation saved by loading query
+//! results instead of recomputing them, use
+//! [`AnalysisResults::incremental_report()`].
+//!
+//! To see which labels or event kinds dominated at which point in time, split
+//! the profile into fixed time windows with [`ProfilingData::activity_series()`].
+//!
+//! Integer events other than artifact sizes, e.g. custom metrics recorded by
+//! tools other than rustc, are summed up per event kind and label in
+//! [`AnalysisResults::integer_metrics`].
+//!
+//! To compare the results of repeated runs of the same workload, aggregate
+//! their [`AnalysisResults`] into [`AggregatedResults`].
+//!
+//! To check a profile for corrupt strings and improperly nested events, call
+//! [`ProfilingData::validate()`].
 
 mod analysis;
+mod call_tree;
+mod concurrency;
+mod critical_path;
+mod event_index;
 mod file_formats;
+mod filter;
+mod gaps;
+mod graph;
+mod histogram;
+mod incremental;
+mod invocations;
+mod label_rules;
+mod merge;
+mod parallel;
 mod profiling_data;
+mod series;
 mod stack_collapse;
+mod statistics;
 pub mod testing_common;
+mod validation;
 
+pub use crate::call_tree::{CallTree, CallTreeNode, NodeId, ThreadCallTree};
+pub use crate::concurrency::{Concurrency, ConcurrencyStep, SerialStretch};
+pub use crate::critical_path::{CriticalPath, CriticalPathItem, CriticalPathSegment};
+pub use crate::filter::EventFilter;
+#[cfg(feature = "clap")]
+pub use crate::filter::FilterArgs;
+pub use crate::gaps::{Gap, GapNeighbor, ThreadGaps};
+pub use crate::graph::{CallGraph, CallGraphEdge, CallGraphNode};
+pub use crate::histogram::{Histogram, HistogramBucket, QueryHistograms};
+pub use crate::incremental::{IncrementalQueryData, IncrementalReport};
+pub use crate::invocations::{Invocation, InvocationOrder};
+pub use crate::label_rules::LabelRules;
+pub use crate::merge::ThreadOrigin;
+pub use crate::parallel::AnalysisOptions;
 pub use crate::profiling_data::{ProfilingData, ProfilingDataBuilder};
-pub use crate::stack_collapse::collapse_stacks;
-pub use analysis::{AnalysisResults, ArtifactSize, QueryData};
+pub use crate::series::{ActivitySeries, ActivitySeriesRow, SeriesKey};
+pub use crate::stack_collapse::{collapse_stacks, collapse_stacks_with_options};
+pub use crate::statistics::{welch_t_test, AggregatedQueryData, AggregatedResults, Statistics};
+pub use analysis::{
+    AnalysisResults, ArtifactSize, EventKindData, IntegerMetric, IntegerSample, QueryArgsData,
+    QueryData,
+};
+pub use decodeme::counter::Counter;
 pub use decodeme::event::Event;
-pub use decodeme::event_payload::{EventPayload, Timestamp};
+pub use decodeme::event_payload::{CounterPayload, CounterReading, EventPayload, Timestamp};
 pub use decodeme::lightweight_event::LightweightEvent;
+pub use decodeme::validation::{Problem, ProblemKind};
+pub use decodeme::Metadata;
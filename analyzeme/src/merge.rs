@@ -0,0 +1,292 @@
+use crate::file_formats::EventDecoder;
+use crate::{Event, LightweightEvent, ProfilingData};
+use decodeme::{validation::Problem, Metadata};
+use rustc_hash::FxHashMap;
+use std::error::Error;
+
+/// The process and thread an event of a merged profile was recorded on. See
+/// [`ProfilingData::merge`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ThreadOrigin {
+    pub process_id: u32,
+    pub thread_id: u32,
+}
+
+/// Presents the events of several profiles as one. The thread ids of each
+/// profile are renumbered so that threads of different processes never share
+/// an id.
+#[derive(Debug)]
+pub(crate) struct MergedEventDecoder {
+    /// Sorted by start time.
+    profiles: Vec<ProfilingData>,
+    /// The index of the first event of each profile in the merged profile.
+    first_events: Vec<usize>,
+    /// For each profile, maps its thread ids to the ones in the merged
+    /// profile.
+    thread_ids: Vec<FxHashMap<u32, u32>>,
+    /// Indexed by the thread ids of the merged profile.
+    thread_origins: Vec<ThreadOrigin>,
+    num_events: usize,
+}
+
+impl MergedEventDecoder {
+    fn new(mut profiles: Vec<ProfilingData>) -> MergedEventDecoder {
+        profiles.sort_by_key(|data| data.metadata().start_time);
+
+        let mut first_events = Vec::with_capacity(profiles.len());
+        let mut thread_ids = Vec::with_capacity(profiles.len());
+        let mut thread_origins = Vec::new();
+        let mut num_events = 0;
+
+        for data in &profiles {
+            first_events.push(num_events);
+            num_events += data.num_events();
+
+            let mut original_thread_ids: Vec<u32> =
+                (0..data.num_events()).map(|i| data.thread_id(i)).collect();
+            original_thread_ids.sort_unstable();
+            original_thread_ids.dedup();
+
+            let process_id = data.metadata().process_id;
+            let mut mapping = FxHashMap::default();
+
+            for thread_id in original_thread_ids {
+                mapping.insert(thread_id, thread_origins.len() as u32);
+                thread_origins.push(ThreadOrigin {
+                    process_id,
+                    thread_id: data.thread_origin(thread_id).thread_id,
+                });
+            }
+
+            thread_ids.push(mapping);
+        }
+
+        MergedEventDecoder {
+            profiles,
+            first_events,
+            thread_ids,
+            thread_origins,
+            num_events,
+        }
+    }
+
+    /// Returns the index of the profile containing the given event and the
+    /// index of the event in that profile.
+    fn locate(&self, event_index: usize) -> (usize, usize) {
+        let profile = self
+            .first_events
+            .partition_point(|&first_event| first_event <= event_index)
+            - 1;
+
+        (profile, event_index - self.first_events[profile])
+    }
+}
+
+impl EventDecoder for MergedEventDecoder {
+    fn num_events(&self) -> usize {
+        self.num_events
+    }
+
+    fn metadata(&self) -> Metadata {
+        self.profiles[0].metadata().clone()
+    }
+
+    fn decode_full_event(&self, event_index: usize) -> Event<'_> {
+        let (profile, local_index) = self.locate(event_index);
+
+        let mut event = self.profiles[profile].decode_full_event(local_index);
+        event.thread_id = self.thread_ids[profile][&event.thread_id];
+        event
+    }
+
+    fn decode_lightweight_event(&self, event_index: usize) -> LightweightEvent {
+        let (profile, local_index) = self.locate(event_index);
+
+        let mut event = self.profiles[profile].decode_lightweight_event(local_index);
+        event.event_index = event_index;
+        event.thread_id = self.thread_ids[profile][&event.thread_id];
+        event
+    }
+
+    fn thread_id(&self, event_index: usize) -> u32 {
+        let (profile, local_index) = self.locate(event_index);
+        let thread_id = self.profiles[profile].thread_id(local_index);
+        self.thread_ids[profile][&thread_id]
+    }
+
+    fn check_strings(&self) -> Vec<Problem> {
+        let mut problems = Vec::new();
+
+        for (data, &first_event) in self.profiles.iter().zip(&self.first_events) {
+            problems.extend(data.check_strings().into_iter().map(|mut problem| {
+                problem.event_index += first_event;
+                problem
+            }));
+        }
+
+        problems
+    }
+
+    fn thread_origin(&self, thread_id: u32) -> Option<ThreadOrigin> {
+        self.thread_origins.get(thread_id as usize).copied()
+    }
+}
+
+impl ProfilingData {
+    /// Merges the profiles of several processes, e.g. of all rustc
+    /// invocations of a `cargo build`, into one.
+    ///
+    /// Events keep their absolute timestamps, so the processes line up in
+    /// time. The threads of all processes get new, distinct thread ids, which
+    /// [`ProfilingData::thread_origin`] maps back to the process and thread
+    /// they were recorded on. The metadata of the merged profile is that of
+    /// the process that started first.
+    ///
+    /// Fails if the profiles were recorded with different counters.
+    pub fn merge(
+        profiles: Vec<ProfilingData>,
+    ) -> Result<ProfilingData, Box<dyn Error + Send + Sync>> {
+        if profiles.is_empty() {
+            return Err("cannot merge zero profiles".into());
+        }
+
+        let counter = &profiles[0].metadata().counter;
+        if let Some(other) = profiles
+            .iter()
+            .find(|data| data.metadata().counter != *counter)
+        {
+            return Err(format!(
+                "cannot merge profiles recorded with different counters (`{}` and `{}`)",
+                counter.name,
+                other.metadata().counter.name
+            )
+            .into());
+        }
+
+        if profiles.len() == 1 {
+            return Ok(profiles.into_iter().next().unwrap());
+        }
+
+        Ok(ProfilingData::from_event_decoder(Box::new(
+            MergedEventDecoder::new(profiles),
+        )))
+    }
+
+    /// Returns the process and thread the events with the given thread id
+    /// were recorded on. Only merged profiles renumber their threads.
+    pub fn thread_origin(&self, thread_id: u32) -> ThreadOrigin {
+        self.event_decoder()
+            .thread_origin(thread_id)
+            .unwrap_or(ThreadOrigin {
+                process_id: self.metadata().process_id,
+                thread_id,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ThreadOrigin;
+    use crate::{EventFilter, ProfilingData, ProfilingDataBuilder};
+    use std::collections::HashSet;
+    use std::io::Read;
+    use std::time::Duration;
+
+    #[test]
+    fn merge_builder_profiles() {
+        let mut b = ProfilingDataBuilder::new();
+        b.interval("Query", "a", 0, 100, 200, |b| {
+            b.interval("Query", "b", 0, 120, 150, |_| {});
+        });
+        b.interval("Query", "c", 3, 100, 120, |_| {});
+        let first = b.into_profiling_data();
+
+        let mut b = ProfilingDataBuilder::new();
+        b.interval("Query", "a", 0, 300, 400, |_| {});
+        let second = b
+            .into_profiling_data()
+            .filter(&EventFilter::new().thread_ids([0]));
+
+        let merged = ProfilingData::merge(vec![first, second]).unwrap();
+
+        assert_eq!(merged.num_events(), 4);
+
+        let events: Vec<_> = merged
+            .iter_full()
+            .map(|event| (event.label.into_owned(), event.thread_id))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                ("b".to_string(), 0),
+                ("a".to_string(), 0),
+                ("c".to_string(), 1),
+                ("a".to_string(), 2)
+            ]
+        );
+
+        assert_eq!(
+            merged.thread_origin(1),
+            ThreadOrigin {
+                process_id: 0,
+                thread_id: 3
+            }
+        );
+
+        let results = merged.perform_analysis();
+        let a = results.query_data.iter().find(|q| q.label == "a").unwrap();
+        assert_eq!(a.invocation_count, 2);
+        assert_eq!(a.self_time, Duration::from_nanos(170));
+    }
+
+    #[test]
+    fn merge_files() {
+        let read = |path: &str| {
+            let data = std::fs::read(path).expect("Test data not found");
+            let mut data_gz = flate2::read::GzDecoder::new(&data[..]);
+            let mut data = Vec::new();
+            data_gz.read_to_end(&mut data).unwrap();
+            ProfilingData::from_paged_buffer(data, None).unwrap()
+        };
+
+        let profiles = vec![
+            read("tests/profdata/v8.mm_profdata.gz"),
+            read("tests/profdata/v9.mm_profdata.gz"),
+        ];
+        let num_events: usize = profiles.iter().map(|data| data.num_events()).sum();
+        let processes: HashSet<_> = profiles
+            .iter()
+            .map(|data| data.metadata().process_id)
+            .collect();
+        let start_time = profiles
+            .iter()
+            .map(|data| data.metadata().start_time)
+            .min()
+            .unwrap();
+
+        let merged = ProfilingData::merge(profiles).unwrap();
+
+        assert_eq!(merged.num_events(), num_events);
+        assert_eq!(merged.metadata().start_time, start_time);
+
+        let threads: HashSet<_> = merged
+            .iter()
+            .map(|event| merged.thread_origin(event.thread_id))
+            .collect();
+        assert_eq!(
+            threads
+                .iter()
+                .map(|origin| origin.process_id)
+                .collect::<HashSet<_>>(),
+            processes
+        );
+
+        // The whole build can be analyzed at once.
+        assert!(!merged.perform_analysis().query_data.is_empty());
+    }
+
+    #[test]
+    fn merge_nothing() {
+        assert!(ProfilingData::merge(Vec::new()).is_err());
+    }
+}
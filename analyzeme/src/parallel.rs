@@ -0,0 +1,126 @@
+//! Helpers for running analyses on several threads at once.
+//!
+//! Analyses like `perform_analysis` and `collapse_stacks` keep a separate
+//! invocation stack per thread, so the events of different threads can be
+//! processed independently. We split the threads of a profile into a number of
+//! partitions of roughly equal size, decode and process each partition on its
+//! own worker thread and merge the results afterwards.
+
+use crate::ProfilingData;
+use rustc_hash::FxHashMap;
+
+/// Options for `ProfilingData::perform_analysis_with_options` and
+/// `collapse_stacks_with_options`.
+#[derive(Clone, Debug)]
+pub struct AnalysisOptions {
+    /// The number of worker threads to use. With `1`, all events are processed
+    /// sequentially on the calling thread. The results are the same either way.
+    pub num_threads: usize,
+    /// Whether `perform_analysis_with_options` should also collect query data
+    /// for each combination of label and arguments, see
+    /// `AnalysisResults::query_data_by_args`.
+    pub by_args: bool,
+    /// Whether `perform_analysis_with_options` should also collect the
+    /// distributions of the self-time and time of each label, see
+    /// `AnalysisResults::histograms`.
+    pub histograms: bool,
+    /// Whether `perform_analysis_with_options` should subtract the cost of
+    /// recording an interval event, as measured by the profiler, from the
+    /// self-time of its parent, but never below zero for any invocation. Tiny
+    /// events that are recorded very often otherwise inflate the self-time of
+    /// their parents noticeably.
+    pub correct_overhead: bool,
+}
+
+impl Default for AnalysisOptions {
+    fn default() -> Self {
+        AnalysisOptions {
+            num_threads: std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1),
+            by_args: false,
+            histograms: false,
+            correct_overhead: false,
+        }
+    }
+}
+
+/// The indices of all events recorded on the given thread, in stream order.
+pub(crate) struct ThreadEvents {
+    pub thread_id: u32,
+    pub event_indices: Vec<usize>,
+}
+
+/// Groups the events of `data` by thread and distributes the threads over at
+/// most `num_partitions` partitions, trying to give each partition the same
+/// number of events. Only the thread ids of the events are read here, all
+/// actual decoding is left to the workers.
+pub(crate) fn partition_by_thread(
+    data: &ProfilingData,
+    num_partitions: usize,
+) -> Vec<Vec<ThreadEvents>> {
+    let mut threads = FxHashMap::<u32, Vec<usize>>::default();
+
+    for event_index in 0..data.num_events() {
+        threads
+            .entry(data.thread_id(event_index))
+            .or_default()
+            .push(event_index);
+    }
+
+    let mut threads: Vec<_> = threads
+        .into_iter()
+        .map(|(thread_id, event_indices)| ThreadEvents {
+            thread_id,
+            event_indices,
+        })
+        .collect();
+
+    // Assign the largest threads first, each to the partition with the fewest
+    // events so far.
+    threads.sort_by_key(|t| (std::cmp::Reverse(t.event_indices.len()), t.thread_id));
+
+    let num_partitions = num_partitions.clamp(1, threads.len().max(1));
+    let mut partitions: Vec<(usize, Vec<ThreadEvents>)> =
+        (0..num_partitions).map(|_| (0, Vec::new())).collect();
+
+    for thread in threads {
+        let (event_count, partition) = partitions.iter_mut().min_by_key(|(n, _)| *n).unwrap();
+        *event_count += thread.event_indices.len();
+        partition.push(thread);
+    }
+
+    partitions
+        .into_iter()
+        .map(|(_, partition)| partition)
+        .collect()
+}
+
+/// Calls `f` for each partition, each on its own thread, and returns the
+/// results in the order of the partitions.
+pub(crate) fn map_partitions<T, F>(partitions: &[Vec<ThreadEvents>], f: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(&[ThreadEvents]) -> T + Sync,
+{
+    if let [partition] = partitions {
+        return vec![f(partition)];
+    }
+
+    let f = &f;
+
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = partitions
+            .iter()
+            .map(|partition| scope.spawn(move || f(partition)))
+            .collect();
+
+        workers
+            .into_iter()
+            .map(|worker| match worker.join() {
+                Ok(result) => result,
+                Err(panic) => std::panic::resume_unwind(panic),
+            })
+            .collect()
+    })
+}
@@ -1,22 +1,30 @@
+use crate::event_index::EventIndex;
 use crate::file_formats::EventDecoder;
-use crate::{file_formats, Event, LightweightEvent};
-use decodeme::{read_file_header, Metadata};
+use crate::{file_formats, Event, LabelRules, LightweightEvent};
+use decodeme::{read_file_header, validation::Problem, Metadata};
 use measureme::file_header::{
     write_file_header, FILE_EXTENSION, FILE_MAGIC_EVENT_STREAM, FILE_MAGIC_TOP_LEVEL,
 };
 use measureme::{
     EventId, PageTag, RawEvent, SerializationSink, SerializationSinkBuilder, StringTableBuilder,
 };
-use std::cell::OnceCell;
 use std::fs;
 use std::path::Path;
 use std::sync::Arc;
+use std::sync::OnceLock;
 use std::{error::Error, path::PathBuf};
 
 #[derive(Debug)]
 pub struct ProfilingData {
     event_decoder: Box<dyn EventDecoder>,
-    metadata: OnceCell<Metadata>,
+    /// For a filtered view of a profile, the sorted indices of the events in
+    /// `event_decoder` that are part of the view.
+    selection: Option<Vec<usize>>,
+    /// The rules that rewrite the labels and arguments of decoded events, see
+    /// `ProfilingData::with_label_rules`.
+    pub(crate) label_rules: Option<LabelRules>,
+    metadata: OnceLock<Metadata>,
+    event_index: OnceLock<EventIndex>,
 }
 
 impl ProfilingData {
@@ -85,10 +93,21 @@ impl ProfilingData {
             }
         };
 
-        Ok(ProfilingData {
+        Ok(ProfilingData::from_event_decoder(event_decoder))
+    }
+
+    pub(crate) fn from_event_decoder(event_decoder: Box<dyn EventDecoder>) -> ProfilingData {
+        ProfilingData {
             event_decoder,
-            metadata: OnceCell::new(),
-        })
+            selection: None,
+            label_rules: None,
+            metadata: OnceLock::new(),
+            event_index: OnceLock::new(),
+        }
+    }
+
+    pub(crate) fn event_decoder(&self) -> &dyn EventDecoder {
+        &*self.event_decoder
     }
 
     pub fn metadata(&self) -> &Metadata {
@@ -96,6 +115,11 @@ impl ProfilingData {
         self.metadata.get_or_init(|| self.event_decoder.metadata())
     }
 
+    pub(crate) fn event_index(&self) -> &EventIndex {
+        // Build the index during the first access
+        self.event_index.get_or_init(|| EventIndex::new(self))
+    }
+
     pub fn iter<'a>(&'a self) -> ProfilerEventIterator<'a> {
         ProfilerEventIterator::new(&self)
     }
@@ -107,7 +131,10 @@ impl ProfilingData {
     }
 
     pub fn num_events(&self) -> usize {
-        self.event_decoder.num_events()
+        match &self.selection {
+            Some(selection) => selection.len(),
+            None => self.event_decoder.num_events(),
+        }
     }
 
     pub fn to_full_event<'a>(&'a self, light_weight_event: &LightweightEvent) -> Event<'a> {
@@ -115,11 +142,75 @@ impl ProfilingData {
     }
 
     pub(crate) fn decode_full_event<'a>(&'a self, event_index: usize) -> Event<'a> {
-        self.event_decoder.decode_full_event(event_index)
+        let mut event = self
+            .event_decoder
+            .decode_full_event(self.decoder_index(event_index));
+
+        if let Some(label_rules) = &self.label_rules {
+            label_rules.apply_to_event(&mut event);
+        }
+
+        event
+    }
+
+    pub(crate) fn decode_lightweight_event(&self, event_index: usize) -> LightweightEvent {
+        let mut event = self
+            .event_decoder
+            .decode_lightweight_event(self.decoder_index(event_index));
+        event.event_index = event_index;
+        event
+    }
+
+    /// The id of the thread the given event was recorded on, without decoding
+    /// the event's payload or strings.
+    pub(crate) fn thread_id(&self, event_index: usize) -> u32 {
+        self.event_decoder
+            .thread_id(self.decoder_index(event_index))
+    }
+
+    pub(crate) fn check_strings(&self) -> Vec<Problem> {
+        let mut problems = self.event_decoder.check_strings();
+
+        if let Some(selection) = &self.selection {
+            // Only report problems of events in the view, by their index in it.
+            problems.retain_mut(
+                |problem| match selection.binary_search(&problem.event_index) {
+                    Ok(index) => {
+                        problem.event_index = index;
+                        true
+                    }
+                    Err(_) => false,
+                },
+            );
+        }
+
+        problems
+    }
+
+    /// Maps the index of an event in this profile to its index in the
+    /// underlying file.
+    fn decoder_index(&self, event_index: usize) -> usize {
+        match &self.selection {
+            Some(selection) => selection[event_index],
+            None => event_index,
+        }
     }
 
-    fn decode_lightweight_event(&self, event_index: usize) -> LightweightEvent {
-        self.event_decoder.decode_lightweight_event(event_index)
+    /// Turns this profile into a view of the events with the given indices,
+    /// which must be sorted.
+    pub(crate) fn select(self, event_indices: &[usize]) -> ProfilingData {
+        let selection = event_indices
+            .iter()
+            .map(|&event_index| self.decoder_index(event_index))
+            .collect();
+
+        ProfilingData {
+            event_decoder: self.event_decoder,
+            selection: Some(selection),
+            label_rules: self.label_rules,
+            metadata: self.metadata,
+            event_index: OnceLock::new(),
+        }
     }
 }
 
@@ -162,6 +253,16 @@ impl<'a> Iterator for ProfilerEventIterator<'a> {
             .unwrap();
         (items_left, Some(items_left))
     }
+
+    fn nth(&mut self, n: usize) -> Option<LightweightEvent> {
+        // Skip the events in between without decoding them.
+        self.forward_event_idx = self
+            .forward_event_idx
+            .saturating_add(n)
+            .min(self.backward_event_idx);
+
+        self.next()
+    }
 }
 
 impl<'a> DoubleEndedIterator for ProfilerEventIterator<'a> {
@@ -193,6 +294,22 @@ pub struct ProfilingDataBuilder {
 
 impl ProfilingDataBuilder {
     pub fn new() -> ProfilingDataBuilder {
+        Self::with_metadata(&format!(
+            r#"{{ "start_time": {}, "process_id": {}, "cmd": "{}" }}"#,
+            0, 0, "test cmd",
+        ))
+    }
+
+    /// Like `ProfilingDataBuilder::new`, but the metadata states that
+    /// recording an event cost `recording_overhead` nanoseconds.
+    pub fn with_recording_overhead(recording_overhead: u64) -> ProfilingDataBuilder {
+        Self::with_metadata(&format!(
+            r#"{{ "start_time": {}, "process_id": {}, "cmd": "{}", "recording_overhead": {} }}"#,
+            0, 0, "test cmd", recording_overhead,
+        ))
+    }
+
+    fn with_metadata(metadata: &str) -> ProfilingDataBuilder {
         let sink_builder = SerializationSinkBuilder::new_in_memory();
 
         let event_sink = sink_builder.new_sink(PageTag::Events);
@@ -208,10 +325,7 @@ impl ProfilingDataBuilder {
         )
         .unwrap();
 
-        string_table.alloc_metadata(&*format!(
-            r#"{{ "start_time": {}, "process_id": {}, "cmd": "{}" }}"#,
-            0, 0, "test cmd",
-        ));
+        string_table.alloc_metadata(metadata);
 
         ProfilingDataBuilder {
             event_sink,
@@ -307,7 +421,10 @@ impl ProfilingDataBuilder {
                 )
                 .unwrap(),
             ),
-            metadata: OnceCell::new(),
+            selection: None,
+            label_rules: None,
+            metadata: OnceLock::new(),
+            event_index: OnceLock::new(),
         }
     }
 
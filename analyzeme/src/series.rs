@@ -0,0 +1,242 @@
+use crate::ProfilingData;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use std::io::{self, Write};
+use std::time::{Duration, SystemTime};
+
+/// The name of the row that [`ActivitySeries::truncate`] folds the remaining
+/// rows into.
+const OTHER: &str = "<other>";
+
+/// What the rows of an [`ActivitySeries`] stand for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SeriesKey {
+    Label,
+    EventKind,
+}
+
+/// The self-time of each label or event kind within consecutive time windows
+/// of a fixed size, as computed by [`ProfilingData::activity_series`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActivitySeries {
+    /// The start of the first window, i.e. the start of the profile.
+    pub start: SystemTime,
+    pub bucket_size: Duration,
+    pub num_buckets: usize,
+    /// Sorted by descending total self-time.
+    pub rows: Vec<ActivitySeriesRow>,
+}
+
+/// The self-time of a label or event kind in each time window. The self-time
+/// of all threads is added up, so it can exceed the size of a window.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActivitySeriesRow {
+    pub name: String,
+    pub self_time: Vec<Duration>,
+}
+
+impl ActivitySeriesRow {
+    pub fn total(&self) -> Duration {
+        self.self_time.iter().sum()
+    }
+}
+
+impl ActivitySeries {
+    pub fn bucket_start(&self, index: usize) -> SystemTime {
+        self.start + Duration::from_nanos(self.bucket_offset(index) as u64)
+    }
+
+    /// The start of the window at `index` in nanoseconds since `start`.
+    fn bucket_offset(&self, index: usize) -> u128 {
+        self.bucket_size.as_nanos() * index as u128
+    }
+
+    /// Keeps the `n` rows with the highest total self-time and adds up the
+    /// remaining ones in a single row named `<other>`.
+    pub fn truncate(&mut self, n: usize) {
+        if self.rows.len() <= n {
+            return;
+        }
+
+        let mut other = ActivitySeriesRow {
+            name: OTHER.to_string(),
+            self_time: vec![Duration::ZERO; self.num_buckets],
+        };
+
+        for row in self.rows.drain(n..) {
+            for (sum, self_time) in other.self_time.iter_mut().zip(row.self_time) {
+                *sum += self_time;
+            }
+        }
+
+        self.rows.push(other);
+    }
+
+    /// Writes the series as CSV, with one line per time window and one column
+    /// per row. The first column holds the start of the window and all values
+    /// are in nanoseconds.
+    pub fn write_csv(&self, mut writer: impl Write) -> io::Result<()> {
+        write!(writer, "start")?;
+        for row in &self.rows {
+            write!(writer, ",{}", csv_field(&row.name))?;
+        }
+        writeln!(writer)?;
+
+        for index in 0..self.num_buckets {
+            write!(writer, "{}", self.bucket_offset(index))?;
+            for row in &self.rows {
+                write!(writer, ",{}", row.self_time[index].as_nanos())?;
+            }
+            writeln!(writer)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+impl ProfilingData {
+    /// Splits the profile into consecutive windows of `bucket_size`, starting
+    /// at the start of the profile, and computes the self-time of each label
+    /// or event kind within each window.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bucket_size` is zero.
+    pub fn activity_series(&self, bucket_size: Duration, key: SeriesKey) -> ActivitySeries {
+        assert!(bucket_size > Duration::ZERO, "bucket size must not be zero");
+
+        let start = self.metadata().start_time;
+        let offset = |t: SystemTime| t.duration_since(start).unwrap_or_default().as_nanos();
+        let bucket_nanos = bucket_size.as_nanos();
+
+        let mut rows = FxHashMap::<String, Vec<Duration>>::default();
+        let mut num_buckets = 0;
+
+        let call_tree = self.call_tree();
+        for thread in call_tree.threads() {
+            for (id, node) in thread.nodes() {
+                let event = self.to_full_event(&node.event);
+                let name = match key {
+                    SeriesKey::Label => event.label,
+                    SeriesKey::EventKind => event.event_kind,
+                };
+
+                let self_time = match rows.get_mut(&name[..]) {
+                    Some(self_time) => self_time,
+                    None => rows.entry(name.into_owned()).or_default(),
+                };
+
+                for (interval_start, interval_end) in thread.exclusive_intervals(id) {
+                    let mut t = offset(interval_start);
+                    let end = offset(interval_end);
+
+                    // Split the interval at the boundaries of the windows.
+                    while t < end {
+                        let index = (t / bucket_nanos) as usize;
+                        let window_end = (index as u128 + 1) * bucket_nanos;
+                        let part = end.min(window_end) - t;
+
+                        if self_time.len() <= index {
+                            self_time.resize(index + 1, Duration::ZERO);
+                        }
+                        self_time[index] += Duration::from_nanos(part as u64);
+                        num_buckets = num_buckets.max(index + 1);
+
+                        t += part;
+                    }
+                }
+            }
+        }
+
+        let mut rows: Vec<_> = rows
+            .into_iter()
+            .map(|(name, mut self_time)| {
+                self_time.resize(num_buckets, Duration::ZERO);
+                ActivitySeriesRow { name, self_time }
+            })
+            .collect();
+        rows.sort_by(|a, b| b.total().cmp(&a.total()).then_with(|| a.name.cmp(&b.name)));
+
+        ActivitySeries {
+            start,
+            bucket_size,
+            num_buckets,
+            rows,
+        }
+    }
+}
+
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ProfilingDataBuilder;
+    use measureme::rustc::*;
+
+    fn nanos(values: &[u64]) -> Vec<Duration> {
+        values.iter().copied().map(Duration::from_nanos).collect()
+    }
+
+    #[test]
+    fn activity_series() {
+        //  0        10        20        30
+        //  |  <----a--|-------|-->      |
+        //  |          | <-b-> |         |   thread 0
+        //  |      <---|-c---->|         |   thread 1
+
+        let mut b = ProfilingDataBuilder::new();
+
+        b.interval(GENERIC_ACTIVITY_EVENT_KIND, "a", 0, 3, 23, |b| {
+            b.interval(QUERY_EVENT_KIND, "b", 0, 12, 16, |_| {});
+        });
+        b.interval(QUERY_EVENT_KIND, "c", 1, 7, 20, |_| {});
+
+        let data = b.into_profiling_data();
+
+        let series = data.activity_series(Duration::from_nanos(10), SeriesKey::Label);
+        assert_eq!(series.num_buckets, 3);
+
+        let rows: Vec<_> = series.rows.iter().map(|row| (&row.name[..], row.self_time.clone())).collect();
+        assert_eq!(rows, vec![
+            ("a", nanos(&[7, 6, 3])),
+            ("c", nanos(&[3, 10, 0])),
+            ("b", nanos(&[0, 4, 0])),
+        ]);
+
+        let series = data.activity_series(Duration::from_nanos(10), SeriesKey::EventKind);
+        let rows: Vec<_> = series.rows.iter().map(|row| (&row.name[..], row.self_time.clone())).collect();
+        assert_eq!(rows, vec![
+            (QUERY_EVENT_KIND, nanos(&[3, 14, 0])),
+            (GENERIC_ACTIVITY_EVENT_KIND, nanos(&[7, 6, 3])),
+        ]);
+
+        let mut series = data.activity_series(Duration::from_nanos(10), SeriesKey::Label);
+        series.truncate(1);
+
+        let mut csv = Vec::new();
+        series.write_csv(&mut csv).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap(), "start,a,<other>\n0,7,3\n10,6,14\n20,3,0\n");
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn bucket_start_beyond_u32() {
+        let series = ActivitySeries {
+            start: SystemTime::UNIX_EPOCH,
+            bucket_size: Duration::from_nanos(2),
+            num_buckets: 0,
+            rows: Vec::new(),
+        };
+
+        let index = u32::MAX as usize + 1;
+        assert_eq!(series.bucket_start(index), SystemTime::UNIX_EPOCH + Duration::from_nanos(1 << 33));
+    }
+}
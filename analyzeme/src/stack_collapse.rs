@@ -2,6 +2,7 @@ use rustc_hash::FxHashMap;
 use std::cmp;
 use std::time::SystemTime;
 
+use crate::parallel::{map_partitions, partition_by_thread, AnalysisOptions};
 use crate::{LightweightEvent, ProfilingData};
 
 // This state is kept up-to-date while iteration over events.
@@ -18,14 +19,71 @@ struct PerThreadState {
 // Original implementation provided by @andjo403 in
 // https://github.com/michaelwoerister/measureme/pull/1
 pub fn collapse_stacks<'a>(profiling_data: &ProfilingData) -> FxHashMap<String, u64> {
+    collapse_stacks_with_options(profiling_data, &AnalysisOptions::default())
+}
+
+/// Like [`collapse_stacks`], but allows for controlling how many threads are
+/// used. The result is the same regardless of the number of threads.
+pub fn collapse_stacks_with_options(
+    profiling_data: &ProfilingData,
+    options: &AnalysisOptions,
+) -> FxHashMap<String, u64> {
+    let (mut counters, threads) = if options.num_threads <= 1 {
+        collapse_thread_stacks(profiling_data, profiling_data.iter().rev())
+    } else {
+        let partitions = partition_by_thread(profiling_data, options.num_threads);
+
+        let partial_results = map_partitions(&partitions, |threads| {
+            let events = threads.iter().flat_map(|thread| {
+                thread
+                    .event_indices
+                    .iter()
+                    .rev()
+                    .map(|&event_index| profiling_data.decode_lightweight_event(event_index))
+            });
+
+            collapse_thread_stacks(profiling_data, events)
+        });
+
+        let mut counters = FxHashMap::default();
+        let mut threads = FxHashMap::default();
+
+        for (partial_counters, partial_threads) in partial_results {
+            for (stack_id, self_time) in partial_counters {
+                *counters.entry(stack_id).or_default() += self_time;
+            }
+            threads.extend(partial_threads);
+        }
+
+        (counters, threads)
+    };
+
+    // Finally add a stack that accounts for the gaps between any recorded
+    // events.
+    let mut rustc_time = 0;
+    for thread in threads.values() {
+        // For each thread we take the time between the start of the first and
+        // the end of the last event, and subtract the duration of all top-level
+        // events of that thread. That leaves us with the duration of all gaps
+        // on the threads timeline.
+        rustc_time += thread.end.duration_since(thread.start).unwrap().as_nanos() as u64
+            - thread.total_event_time_nanos;
+    }
+    counters.insert("rustc".to_owned(), rustc_time);
+
+    counters
+}
+
+/// Computes the stack counters for the given events, which must contain all
+/// events of the threads involved, in reverse order.
+fn collapse_thread_stacks(
+    profiling_data: &ProfilingData,
+    events: impl Iterator<Item = LightweightEvent>,
+) -> (FxHashMap<String, u64>, FxHashMap<u32, PerThreadState>) {
     let mut counters = FxHashMap::default();
     let mut threads = FxHashMap::<_, PerThreadState>::default();
 
-    for current_event in profiling_data
-        .iter()
-        .rev()
-        .filter(|e| e.payload.is_interval())
-    {
+    for current_event in events.filter(|e| e.payload.is_interval()) {
         let start = current_event.start().unwrap();
         let end = current_event.end().unwrap();
         let thread = threads
@@ -81,25 +139,12 @@ pub fn collapse_stacks<'a>(profiling_data: &ProfilingData) -> FxHashMap<String,
         thread.stack.push(current_event)
     }
 
-    // Finally add a stack that accounts for the gaps between any recorded
-    // events.
-    let mut rustc_time = 0;
-    for thread in threads.values() {
-        // For each thread we take the time between the start of the first and
-        // the end of the last event, and subtract the duration of all top-level
-        // events of that thread. That leaves us with the duration of all gaps
-        // on the threads timeline.
-        rustc_time += thread.end.duration_since(thread.start).unwrap().as_nanos() as u64
-            - thread.total_event_time_nanos;
-    }
-    counters.insert("rustc".to_owned(), rustc_time);
-
-    counters
+    (counters, threads)
 }
 
 #[cfg(test)]
 mod test {
-    use crate::ProfilingDataBuilder;
+    use crate::{AnalysisOptions, ProfilingDataBuilder};
     use rustc_hash::FxHashMap;
 
     #[test]
@@ -179,4 +224,49 @@ mod test {
 
         assert_eq!(expected_stacks, recorded_stacks);
     }
+
+    #[test]
+    fn parallel_matches_sequential() {
+        let mut b = ProfilingDataBuilder::new();
+
+        for thread_id in 0..6 {
+            let offset = thread_id as u64 * 3;
+            b.interval("Query", "e1", thread_id, offset + 1, offset + 20, |b| {
+                b.interval("Query", "e2", thread_id, offset + 2, offset + 10, |b| {
+                    b.interval("Query", "e3", thread_id, offset + 3, offset + 5, |_| {});
+                });
+                b.interval("Query", "e3", thread_id, offset + 11, offset + 19, |_| {});
+            });
+            b.interval(
+                "Query",
+                "e2",
+                thread_id % 2,
+                offset + 30,
+                offset + 40,
+                |_| {},
+            );
+        }
+
+        let profiling_data = b.into_profiling_data();
+
+        let sequential = super::collapse_stacks_with_options(
+            &profiling_data,
+            &AnalysisOptions {
+                num_threads: 1,
+                ..Default::default()
+            },
+        );
+
+        for num_threads in 2..8 {
+            let parallel = super::collapse_stacks_with_options(
+                &profiling_data,
+                &AnalysisOptions {
+                    num_threads,
+                    ..Default::default()
+                },
+            );
+
+            assert_eq!(parallel, sequential);
+        }
+    }
 }
@@ -0,0 +1,484 @@
+use crate::{AnalysisResults, ArtifactSize, Counter, EventKindData, QueryData};
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// The confidence level of `Statistics::confidence_interval`.
+pub const CONFIDENCE_LEVEL: f64 = 0.95;
+
+/// Summary statistics of a set of durations, e.g. the self-time of a query
+/// across several profiles of the same workload.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Statistics {
+    pub samples: usize,
+    pub mean: Duration,
+    pub median: Duration,
+    /// The sample standard deviation. Zero if there is only one sample.
+    pub std_dev: Duration,
+    pub min: Duration,
+    pub max: Duration,
+    /// The 95% confidence interval of the mean, based on Student's
+    /// t-distribution. Only the mean if there is only one sample. The lower
+    /// bound is clamped to zero.
+    pub confidence_interval: (Duration, Duration),
+}
+
+impl Statistics {
+    /// Computes the statistics of the given samples, or `None` if there are
+    /// none.
+    pub fn new(samples: &[Duration]) -> Option<Statistics> {
+        if samples.is_empty() {
+            return None;
+        }
+
+        let mut sorted = samples.to_vec();
+        sorted.sort();
+
+        let n = sorted.len();
+        let median = if n % 2 == 1 {
+            sorted[n / 2]
+        } else {
+            (sorted[n / 2 - 1] + sorted[n / 2]) / 2
+        };
+
+        let (mean, variance) = mean_and_variance(samples);
+        let std_dev = variance.sqrt();
+
+        let half_width = if n > 1 {
+            let t = student_t_quantile(1.0 - (1.0 - CONFIDENCE_LEVEL) / 2.0, (n - 1) as f64);
+            t * std_dev / (n as f64).sqrt()
+        } else {
+            0.0
+        };
+
+        Some(Statistics {
+            samples: n,
+            mean: from_nanos(mean),
+            median,
+            std_dev: from_nanos(std_dev),
+            min: sorted[0],
+            max: sorted[n - 1],
+            confidence_interval: (from_nanos(mean - half_width), from_nanos(mean + half_width)),
+        })
+    }
+}
+
+/// The self-time statistics of a single label across several profiles.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AggregatedQueryData {
+    pub label: String,
+    #[serde(default)]
+    pub event_kind: String,
+    pub self_time: Statistics,
+    /// The number of profiles in which the label occurs. Profiles in which
+    /// it doesn't are counted as a self-time of zero.
+    pub occurrences: usize,
+}
+
+/// Statistics of the analysis results of repeated runs of the same workload.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AggregatedResults {
+    pub num_profiles: usize,
+    pub total_time: Statistics,
+    /// Sorted by descending mean self-time.
+    pub query_data: Vec<AggregatedQueryData>,
+    /// The counter of the first profile. Mixing counters makes the results
+    /// meaningless.
+    pub counter: Counter,
+}
+
+impl AggregatedResults {
+    /// Aggregates the analysis results of several profiles.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `results` is empty.
+    pub fn new(results: &[AnalysisResults]) -> AggregatedResults {
+        assert!(!results.is_empty(), "cannot aggregate zero profiles");
+
+        let num_profiles = results.len();
+        let mut self_times = FxHashMap::<(&str, &str), Vec<Duration>>::default();
+
+        for result in results {
+            for query_data in &result.query_data {
+                self_times
+                    .entry((&query_data.event_kind, &query_data.label))
+                    .or_default()
+                    .push(query_data.self_time);
+            }
+        }
+
+        let mut query_data: Vec<_> = self_times
+            .into_iter()
+            .map(|((event_kind, label), mut samples)| {
+                let occurrences = samples.len();
+                samples.resize(num_profiles, Duration::ZERO);
+
+                AggregatedQueryData {
+                    label: label.to_owned(),
+                    event_kind: event_kind.to_owned(),
+                    self_time: Statistics::new(&samples).unwrap(),
+                    occurrences,
+                }
+            })
+            .collect();
+
+        query_data.sort_by(|a, b| {
+            b.self_time
+                .mean
+                .cmp(&a.self_time.mean)
+                .then_with(|| (&a.label, &a.event_kind).cmp(&(&b.label, &b.event_kind)))
+        });
+
+        let total_times: Vec<_> = results.iter().map(|result| result.total_time).collect();
+
+        AggregatedResults {
+            num_profiles,
+            total_time: Statistics::new(&total_times).unwrap(),
+            query_data,
+            counter: results[0].counter.clone(),
+        }
+    }
+}
+
+impl AnalysisResults {
+    /// Computes the mean of the results of several profiles of the same
+    /// workload, item by item. Items missing from a profile count as zero
+    /// there. The argument breakdown is not carried over.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `results` is empty.
+    pub fn mean(results: &[AnalysisResults]) -> AnalysisResults {
+        assert!(!results.is_empty(), "cannot average zero profiles");
+
+        let n = results.len();
+        let mut query_data = FxHashMap::<(&str, &str), QueryData>::default();
+        let mut artifact_sizes = FxHashMap::<&str, u64>::default();
+
+        for result in results {
+            for data in &result.query_data {
+                query_data
+                    .entry((&data.event_kind, &data.label))
+                    .or_insert_with(|| {
+                        QueryData::with_event_kind(&data.event_kind, data.label.clone())
+                    })
+                    .add(data.clone());
+            }
+
+            for artifact_size in &result.artifact_sizes {
+                *artifact_sizes.entry(&artifact_size.label).or_default() += artifact_size.value;
+            }
+        }
+
+        let mut query_data: Vec<_> = query_data.into_values().collect();
+        for data in &mut query_data {
+            data.divide(n);
+        }
+        query_data.sort_by(|a, b| (&a.label, &a.event_kind).cmp(&(&b.label, &b.event_kind)));
+
+        let mut artifact_sizes: Vec<_> = artifact_sizes
+            .into_iter()
+            .map(|(label, value)| ArtifactSize {
+                label: label.to_owned(),
+                value: value / n as u64,
+            })
+            .collect();
+        artifact_sizes.sort_by(|a, b| a.label.cmp(&b.label));
+
+        AnalysisResults {
+            event_kinds: EventKindData::from_query_data(&query_data),
+            query_data,
+            query_data_by_args: Vec::new(),
+            histograms: Vec::new(),
+            artifact_sizes,
+            integer_metrics: Vec::new(),
+            recording_overhead: None,
+            total_time: results
+                .iter()
+                .map(|result| result.total_time)
+                .sum::<Duration>()
+                / n as u32,
+            counter: results[0].counter.clone(),
+        }
+    }
+}
+
+/// Performs Welch's t-test on two sets of durations and returns the
+/// two-sided p-value, i.e. the probability of seeing a difference of the
+/// means at least this large if both sets were drawn from distributions with
+/// the same mean. Returns `None` if either set has fewer than two samples.
+pub fn welch_t_test(a: &[Duration], b: &[Duration]) -> Option<f64> {
+    if a.len() < 2 || b.len() < 2 {
+        return None;
+    }
+
+    let (mean_a, variance_a) = mean_and_variance(a);
+    let (mean_b, variance_b) = mean_and_variance(b);
+    let (n_a, n_b) = (a.len() as f64, b.len() as f64);
+
+    let error_a = variance_a / n_a;
+    let error_b = variance_b / n_b;
+    let standard_error = (error_a + error_b).sqrt();
+
+    if standard_error == 0.0 {
+        // Without any noise, every difference is significant.
+        return Some(if mean_a == mean_b { 1.0 } else { 0.0 });
+    }
+
+    let t = (mean_a - mean_b) / standard_error;
+    // The Welch-Satterthwaite approximation of the degrees of freedom.
+    let df = (error_a + error_b).powi(2)
+        / (error_a.powi(2) / (n_a - 1.0) + error_b.powi(2) / (n_b - 1.0));
+
+    Some(2.0 * student_t_cdf(-t.abs(), df))
+}
+
+fn from_nanos(nanos: f64) -> Duration {
+    Duration::from_nanos(nanos.max(0.0).round() as u64)
+}
+
+/// The mean and sample variance of the given durations, in nanoseconds.
+pub(crate) fn mean_and_variance(samples: &[Duration]) -> (f64, f64) {
+    let n = samples.len() as f64;
+    let mean = samples.iter().map(|s| s.as_nanos() as f64).sum::<f64>() / n;
+
+    let variance = if samples.len() > 1 {
+        samples
+            .iter()
+            .map(|s| (s.as_nanos() as f64 - mean).powi(2))
+            .sum::<f64>()
+            / (n - 1.0)
+    } else {
+        0.0
+    };
+
+    (mean, variance)
+}
+
+/// The cumulative distribution function of Student's t-distribution with
+/// `df` degrees of freedom.
+pub(crate) fn student_t_cdf(t: f64, df: f64) -> f64 {
+    let tail = 0.5 * regularized_incomplete_beta(df / 2.0, 0.5, df / (df + t * t));
+
+    if t > 0.0 {
+        1.0 - tail
+    } else {
+        tail
+    }
+}
+
+/// The inverse of `student_t_cdf`, found by bisection.
+pub(crate) fn student_t_quantile(p: f64, df: f64) -> f64 {
+    let (mut low, mut high) = (-1e3, 1e3);
+
+    for _ in 0..100 {
+        let mid = (low + high) / 2.0;
+        if student_t_cdf(mid, df) < p {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+
+    (low + high) / 2.0
+}
+
+/// The natural logarithm of the gamma function (Lanczos approximation).
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.18009172947146,
+        -86.50532032941677,
+        24.01409824083091,
+        -1.231739572450155,
+        0.1208650973866179e-2,
+        -0.5395239384953e-5,
+    ];
+
+    let tmp = x + 5.5;
+    let tmp = tmp - (x + 0.5) * tmp.ln();
+
+    let mut y = x;
+    let mut series = 1.000000000190015;
+    for c in COEFFICIENTS {
+        y += 1.0;
+        series += c / y;
+    }
+
+    -tmp + (2.5066282746310005 * series / x).ln()
+}
+
+/// The regularized incomplete beta function `I_x(a, b)`.
+fn regularized_incomplete_beta(a: f64, b: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+
+    let front =
+        (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+
+    // The continued fraction converges quickly only on one side of this.
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_continued_fraction(a, b, x) / a
+    } else {
+        1.0 - front * beta_continued_fraction(b, a, 1.0 - x) / b
+    }
+}
+
+/// Evaluates the continued fraction of the incomplete beta function with
+/// Lentz's method.
+fn beta_continued_fraction(a: f64, b: f64, x: f64) -> f64 {
+    const TINY: f64 = 1e-300;
+    const EPSILON: f64 = 1e-15;
+
+    let mut c = 1.0;
+    let mut d = 1.0 - (a + b) * x / (a + 1.0);
+    if d.abs() < TINY {
+        d = TINY;
+    }
+    d = 1.0 / d;
+    let mut result = d;
+
+    for m in 1..300 {
+        let m = m as f64;
+
+        for numerator in [
+            m * (b - m) * x / ((a + 2.0 * m - 1.0) * (a + 2.0 * m)),
+            -(a + m) * (a + b + m) * x / ((a + 2.0 * m) * (a + 2.0 * m + 1.0)),
+        ] {
+            d = 1.0 + numerator * d;
+            if d.abs() < TINY {
+                d = TINY;
+            }
+            c = 1.0 + numerator / c;
+            if c.abs() < TINY {
+                c = TINY;
+            }
+            d = 1.0 / d;
+            result *= d * c;
+        }
+
+        if (d * c - 1.0).abs() < EPSILON {
+            break;
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AnalysisResults, QueryData};
+
+    #[test]
+    fn t_quantiles() {
+        // Reference values from a t-table.
+        for &(df, expected) in &[
+            (1.0, 12.7062),
+            (3.0, 3.1824),
+            (10.0, 2.2281),
+            (1000.0, 1.9623),
+        ] {
+            let t = student_t_quantile(0.975, df);
+            assert!(
+                (t - expected).abs() < 1e-3,
+                "df {}: {} != {}",
+                df,
+                t,
+                expected
+            );
+        }
+
+        assert!((student_t_cdf(0.0, 5.0) - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn statistics() {
+        let samples: Vec<_> = [40, 10, 30, 20]
+            .iter()
+            .map(|&n| Duration::from_nanos(n))
+            .collect();
+        let stats = Statistics::new(&samples).unwrap();
+
+        assert_eq!(stats.samples, 4);
+        assert_eq!(stats.mean, Duration::from_nanos(25));
+        assert_eq!(stats.median, Duration::from_nanos(25));
+        // sqrt(500 / 3)
+        assert_eq!(stats.std_dev, Duration::from_nanos(13));
+        assert_eq!(stats.min, Duration::from_nanos(10));
+        assert_eq!(stats.max, Duration::from_nanos(40));
+        // 25 ± 3.1824 * 12.91 / 2
+        assert_eq!(
+            stats.confidence_interval,
+            (Duration::from_nanos(4), Duration::from_nanos(46))
+        );
+
+        let single = Statistics::new(&samples[..1]).unwrap();
+        assert_eq!(single.std_dev, Duration::ZERO);
+        assert_eq!(
+            single.confidence_interval,
+            (Duration::from_nanos(40), Duration::from_nanos(40))
+        );
+
+        assert_eq!(Statistics::new(&[]), None);
+    }
+
+    #[test]
+    fn welch() {
+        let nanos = |values: &[u64]| -> Vec<Duration> {
+            values.iter().map(|&n| Duration::from_nanos(n)).collect()
+        };
+
+        // t = -5 with 8 degrees of freedom.
+        let p = welch_t_test(
+            &nanos(&[100, 102, 98, 101, 99]),
+            &nanos(&[104, 106, 103, 105, 107]),
+        )
+        .unwrap();
+        assert!((p - 0.00105).abs() < 1e-5, "{}", p);
+
+        let p = welch_t_test(&nanos(&[100, 110, 90]), &nanos(&[101, 111, 91])).unwrap();
+        assert!(p > 0.5);
+
+        assert_eq!(welch_t_test(&nanos(&[5, 5]), &nanos(&[5, 5])), Some(1.0));
+        assert_eq!(welch_t_test(&nanos(&[5, 5]), &nanos(&[6, 6])), Some(0.0));
+        assert_eq!(welch_t_test(&nanos(&[5]), &nanos(&[6, 6])), None);
+    }
+
+    #[test]
+    fn aggregate() {
+        let results = |self_times: &[(&str, u64)], total_time: u64| AnalysisResults {
+            query_data: self_times
+                .iter()
+                .map(|&(label, self_time)| QueryData {
+                    self_time: Duration::from_nanos(self_time),
+                    ..QueryData::new(label.to_owned())
+                })
+                .collect(),
+            total_time: Duration::from_nanos(total_time),
+            ..AnalysisResults::default()
+        };
+
+        let aggregated = AggregatedResults::new(&[
+            results(&[("a", 10), ("b", 100)], 110),
+            results(&[("a", 20), ("b", 110)], 130),
+            results(&[("b", 120)], 120),
+        ]);
+
+        assert_eq!(aggregated.num_profiles, 3);
+        assert_eq!(aggregated.total_time.mean, Duration::from_nanos(120));
+
+        let labels: Vec<_> = aggregated.query_data.iter().map(|q| &q.label[..]).collect();
+        assert_eq!(labels, ["b", "a"]);
+
+        let a = &aggregated.query_data[1];
+        assert_eq!(a.occurrences, 2);
+        assert_eq!(a.self_time.mean, Duration::from_nanos(10));
+        assert_eq!(a.self_time.min, Duration::ZERO);
+        assert_eq!(a.self_time.median, Duration::from_nanos(10));
+    }
+}
@@ -116,6 +116,9 @@ fn generate_profiling_data(
 fn process_profiling_data(filestem: &Path, expected_events: &[Event<'static>]) {
     let profiling_data = ProfilingData::new(filestem).unwrap();
 
+    // The profiler measures how long recording an event takes when it starts.
+    assert!(profiling_data.metadata().recording_overhead.is_some());
+
     // Check iterating forward over the events
     check_profiling_data(
         &mut profiling_data.iter_full(),
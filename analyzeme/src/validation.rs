@@ -0,0 +1,123 @@
+use crate::{EventPayload, Problem, ProblemKind, ProfilingData, Timestamp};
+use rustc_hash::FxHashMap;
+use std::time::SystemTime;
+
+impl ProfilingData {
+    /// Checks the profile for problems that don't prevent it from being
+    /// decoded but lead to wrong results: strings referring to unmapped
+    /// virtual string IDs or to strings that don't exist, malformed string
+    /// data, and interval events on the same thread that overlap without being
+    /// nested. The problems are sorted by event index.
+    pub fn validate(&self) -> Vec<Problem> {
+        let mut problems = self.check_strings();
+        problems.extend(overlapping_intervals(self));
+        problems.sort_by_key(|problem| problem.event_index);
+        problems
+    }
+}
+
+struct Interval {
+    start: SystemTime,
+    end: SystemTime,
+    event_index: usize,
+}
+
+fn overlapping_intervals(data: &ProfilingData) -> Vec<Problem> {
+    let mut threads = FxHashMap::<u32, Vec<Interval>>::default();
+
+    for event in data.iter() {
+        if let EventPayload::Timestamp(Timestamp::Interval { start, end }) = event.payload {
+            threads.entry(event.thread_id).or_default().push(Interval {
+                start,
+                end,
+                event_index: event.event_index,
+            });
+        }
+    }
+
+    let mut problems = Vec::new();
+
+    for intervals in threads.values_mut() {
+        // Sort enclosing intervals before the intervals they contain, so that
+        // properly nested intervals always end up within the ones on the stack.
+        intervals.sort_by(|a, b| {
+            a.start
+                .cmp(&b.start)
+                .then_with(|| b.end.cmp(&a.end))
+                .then_with(|| a.event_index.cmp(&b.event_index))
+        });
+
+        let mut stack: Vec<&Interval> = Vec::new();
+
+        for interval in intervals.iter() {
+            while let Some(top) = stack.last() {
+                if top.end <= interval.start {
+                    // `top` ended before `interval` started.
+                } else if top.end < interval.end {
+                    problems.push(Problem {
+                        event_index: interval.event_index,
+                        kind: ProblemKind::OverlappingIntervals {
+                            other_event_index: top.event_index,
+                        },
+                    });
+                } else {
+                    break;
+                }
+
+                stack.pop();
+            }
+
+            stack.push(interval);
+        }
+    }
+
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Problem, ProblemKind, ProfilingDataBuilder};
+
+    fn overlap(event_index: usize, other_event_index: usize) -> Problem {
+        Problem {
+            event_index,
+            kind: ProblemKind::OverlappingIntervals { other_event_index },
+        }
+    }
+
+    #[test]
+    fn valid_profile() {
+        let mut b = ProfilingDataBuilder::new();
+
+        b.interval("Query", "e1", 0, 100, 200, |b| {
+            b.interval("Query", "e2", 0, 100, 150, |b| {
+                b.instant("QueryCacheHit", "e3", 0, 120);
+            });
+            b.interval("Query", "e4", 0, 150, 200, |_| {});
+        });
+        b.interval("Query", "e5", 0, 200, 200, |_| {});
+        b.interval("Query", "e6", 1, 110, 300, |_| {});
+
+        assert_eq!(b.into_profiling_data().validate(), vec![]);
+    }
+
+    #[test]
+    fn overlapping_intervals() {
+        let mut b = ProfilingDataBuilder::new();
+
+        // Event indices are given in the comments.
+        b.interval("Query", "e1", 0, 100, 200, |b| {
+            b.interval("Query", "e2", 0, 120, 140, |_| {}); // 0
+        }); // 1
+        b.interval("Query", "e3", 0, 150, 250, |b| {
+            b.interval("Query", "e4", 0, 160, 170, |_| {}); // 2
+            b.interval("Query", "e5", 0, 190, 260, |_| {}); // 3
+        }); // 4
+        b.interval("Query", "e6", 1, 150, 250, |_| {}); // 5
+
+        assert_eq!(
+            b.into_profiling_data().validate(),
+            vec![overlap(3, 4), overlap(4, 1)]
+        );
+    }
+}
@@ -4,7 +4,7 @@ use std::io::BufWriter;
 use std::path::PathBuf;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use analyzeme::{ProfilingData, Timestamp};
+use analyzeme::{CounterPayload, CounterReading, FilterArgs, ProfilingData, Timestamp};
 use measureme::file_header::FILE_EXTENSION;
 
 use clap::Parser;
@@ -52,9 +52,8 @@ struct Opt {
     /// collapse threads without overlapping events
     #[arg(long = "collapse-threads")]
     collapse_threads: bool,
-    /// filter out events with shorter duration (in microseconds)
-    #[arg(long = "minimum-duration")]
-    minimum_duration: Option<u128>,
+    #[command(flatten)]
+    filter: FilterArgs,
 }
 
 // generate mapping from thread_id to collapsed thread_id or an empty map
@@ -134,6 +133,14 @@ fn get_args(full_event: &analyzeme::Event<'_>) -> Option<FxHashMap<String, Strin
     }
 }
 
+fn counter_start_and_count(reading: &CounterReading<'_>) -> (u64, u64) {
+    match reading.payload {
+        CounterPayload::Interval { start, end } => (start, end.saturating_sub(start)),
+        CounterPayload::Instant(t) => (t, 0),
+        CounterPayload::Integer(_) => (0, 0),
+    }
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let opt = Opt::parse();
 
@@ -145,31 +152,53 @@ fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let dir_paths = file_prefixes_in_dir(&opt)?;
 
     for file_prefix in opt.file_prefix.iter().chain(dir_paths.iter()) {
-        let data = ProfilingData::new(&file_prefix)?;
+        let data = opt.filter.apply(ProfilingData::new(&file_prefix)?)?;
 
         let thread_to_collapsed_thread = generate_thread_to_collapsed_thread_mapping(&opt, &data);
 
+        let metadata = data.metadata();
+
         // Chrome does not seem to like how many QueryCacheHit events we generate
         // only handle Interval events for now
         for event in data.iter().filter(|e| e.payload.is_interval()) {
             let duration = event.duration().unwrap();
-            if let Some(minimum_duration) = opt.minimum_duration {
-                if duration.as_micros() < minimum_duration {
-                    continue;
-                }
-            }
             let full_event = data.to_full_event(&event);
+            let mut args = get_args(&full_event);
+
+            let (timestamp, duration) = if metadata.counter.is_wall_time() {
+                (
+                    event.start().unwrap().duration_since(UNIX_EPOCH).unwrap(),
+                    duration,
+                )
+            } else {
+                // The trace format only knows about time, so we pretend that
+                // every counter step is a microsecond and record the actual
+                // counts as arguments.
+                let reading = event.payload.counter_reading(metadata);
+                let (start, count) = counter_start_and_count(&reading);
+                if let Some(unit) = reading.unit() {
+                    args.get_or_insert_with(FxHashMap::default)
+                        .insert(unit.to_string(), count.to_string());
+                }
+
+                (
+                    metadata.start_time.duration_since(UNIX_EPOCH).unwrap()
+                        + Duration::from_micros(start),
+                    Duration::from_micros(count),
+                )
+            };
+
             let crox_event = Event {
                 name: full_event.label.clone().into_owned(),
                 category: full_event.event_kind.clone().into_owned(),
                 event_type: EventType::Complete,
-                timestamp: event.start().unwrap().duration_since(UNIX_EPOCH).unwrap(),
+                timestamp,
                 duration,
-                process_id: data.metadata().process_id,
+                process_id: metadata.process_id,
                 thread_id: *thread_to_collapsed_thread
                     .get(&event.thread_id)
                     .unwrap_or(&event.thread_id),
-                args: get_args(&full_event),
+                args,
             };
             seq.serialize_element(&crox_event)?;
         }
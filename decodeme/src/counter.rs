@@ -0,0 +1,136 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+const WALL_TIME_COUNTER_NAME: &str = "wall-time";
+
+/// Describes the counter that was used for recording event timestamps, as
+/// written by `measureme::counters::Counter::describe_as_json`.
+///
+/// Timestamps are always decoded as if the counter values were nanoseconds
+/// since the profile's `start_time`. For counters other than `wall-time` (e.g.
+/// `instructions:u`) the resulting durations are really counter deltas and
+/// should be displayed with [`Counter::format_duration`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Counter {
+    pub name: String,
+    /// The units the counter values can be displayed in, together with the
+    /// number of raw counter steps they comprise, sorted in ascending order.
+    pub units: Vec<(String, u64)>,
+}
+
+impl Default for Counter {
+    /// Profiles written before the counter was recorded in the metadata always
+    /// used the wall-time counter.
+    fn default() -> Self {
+        Counter {
+            name: WALL_TIME_COUNTER_NAME.to_string(),
+            units: vec![
+                ("ns".to_string(), 1),
+                ("μs".to_string(), 1_000),
+                ("ms".to_string(), 1_000_000),
+                ("s".to_string(), 1_000_000_000),
+            ],
+        }
+    }
+}
+
+impl Counter {
+    pub fn is_wall_time(&self) -> bool {
+        self.name == WALL_TIME_COUNTER_NAME
+    }
+
+    /// The name of the smallest unit, i.e. what a single counter step is
+    /// called (e.g. `ns` or `instructions`).
+    pub fn base_unit(&self) -> &str {
+        self.units
+            .first()
+            .map(|(name, _)| &name[..])
+            .unwrap_or(&self.name)
+    }
+
+    /// Formats a raw counter value using the largest unit that is not greater
+    /// than the value.
+    pub fn format_value(&self, value: u64) -> String {
+        let unit = self
+            .units
+            .iter()
+            .filter(|&&(_, steps)| steps != 0 && steps <= value)
+            .max_by_key(|&&(_, steps)| steps);
+
+        match unit {
+            Some((name, 1)) => format!("{} {}", value, name),
+            Some((name, steps)) => format!("{:.2} {}", value as f64 / *steps as f64, name),
+            None => format!("{} {}", value, self.base_unit()),
+        }
+    }
+
+    /// Formats a duration computed from decoded timestamps. For the wall-time
+    /// counter this is a regular duration, for all other counters the number of
+    /// nanoseconds is the raw counter delta.
+    pub fn format_duration(&self, duration: Duration) -> String {
+        if self.is_wall_time() {
+            format!("{:.2?}", duration)
+        } else {
+            self.format_value(duration.as_nanos() as u64)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_payload::{CounterPayload, CounterReading};
+
+    #[test]
+    fn parse_counter_description() {
+        let counter: Counter =
+            serde_json::from_str(r#"{ "name": "instructions:u", "units": [["instructions", 1]] }"#)
+                .unwrap();
+
+        assert_eq!(counter.name, "instructions:u");
+        assert!(!counter.is_wall_time());
+        assert_eq!(counter.base_unit(), "instructions");
+        assert_eq!(counter.format_value(1_234_567), "1234567 instructions");
+        assert_eq!(
+            counter.format_duration(Duration::from_nanos(42)),
+            "42 instructions"
+        );
+    }
+
+    #[test]
+    fn format_wall_time() {
+        let counter = Counter::default();
+
+        assert!(counter.is_wall_time());
+        assert_eq!(counter.format_value(0), "0 ns");
+        assert_eq!(counter.format_value(999), "999 ns");
+        assert_eq!(counter.format_value(1_500), "1.50 μs");
+        assert_eq!(counter.format_value(2_000_000_000), "2.00 s");
+        assert_eq!(
+            counter.format_duration(Duration::from_micros(1_500)),
+            "1.50ms"
+        );
+    }
+
+    #[test]
+    fn counter_reading() {
+        let counter: Counter =
+            serde_json::from_str(r#"{ "name": "instructions:u", "units": [["instructions", 1]] }"#)
+                .unwrap();
+
+        let interval = CounterReading {
+            counter: &counter,
+            payload: CounterPayload::Interval { start: 10, end: 52 },
+        };
+        assert_eq!(interval.unit(), Some("instructions"));
+        assert_eq!(interval.duration(), Some(42));
+        assert_eq!(interval.format_duration().unwrap(), "42 instructions");
+
+        let integer = CounterReading {
+            counter: &counter,
+            payload: CounterPayload::Integer(7),
+        };
+        assert_eq!(integer.unit(), None);
+        assert_eq!(integer.format_duration(), None);
+    }
+}
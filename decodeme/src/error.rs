@@ -0,0 +1,71 @@
+use measureme::PageError;
+use std::error::Error;
+use std::fmt;
+
+/// The error returned when profiling data cannot be decoded. Each variant
+/// records the stream the problem was found in and, where applicable, the
+/// byte offset of the offending data.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The file header of a stream is missing, has the wrong magic bytes, or
+    /// declares an unsupported version.
+    InvalidHeader {
+        stream: &'static str,
+        message: String,
+    },
+    /// The page starting at `offset` of the top-level file is malformed.
+    InvalidPage { offset: usize, error: PageError },
+    /// The file does not contain any pages for the given stream.
+    MissingStream { stream: &'static str },
+    /// The stream is made up of fixed size entries, but its last entry,
+    /// starting at `offset`, is incomplete.
+    TruncatedEntry {
+        stream: &'static str,
+        offset: usize,
+        entry_size: usize,
+    },
+    /// The metadata string could not be parsed.
+    InvalidMetadata { message: String },
+}
+
+impl DecodeError {
+    pub(crate) fn from_page_error(error: PageError, base_offset: usize) -> DecodeError {
+        DecodeError::InvalidPage {
+            offset: base_offset + error.offset(),
+            error,
+        }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::InvalidHeader { message, .. } => write!(f, "{}", message),
+            DecodeError::InvalidPage { offset, error } => {
+                write!(
+                    f,
+                    "Invalid file: Malformed page at offset {}: {}",
+                    offset, error
+                )
+            }
+            DecodeError::MissingStream { stream } => {
+                write!(f, "Invalid file: No {} stream found", stream)
+            }
+            DecodeError::TruncatedEntry {
+                stream,
+                offset,
+                entry_size,
+            } => write!(
+                f,
+                "Invalid file: Incomplete entry at offset {} of the {} stream \
+                 (entries are {} bytes)",
+                offset, stream, entry_size
+            ),
+            DecodeError::InvalidMetadata { message } => {
+                write!(f, "Invalid file: Could not parse metadata: {}", message)
+            }
+        }
+    }
+}
+
+impl Error for DecodeError {}
@@ -1,3 +1,5 @@
+use crate::counter::Counter;
+use crate::Metadata;
 use measureme::RawEvent;
 use std::time::{Duration, SystemTime};
 
@@ -70,6 +72,103 @@ impl EventPayload {
             Self::Integer(i) => Some(*i),
         }
     }
+
+    /// Recovers the raw counter values of the payload. `start_time` must be
+    /// the start time from the profile's metadata that the timestamps were
+    /// decoded relative to.
+    pub fn counter_payload(&self, start_time: SystemTime) -> CounterPayload {
+        let counter_value = |t: SystemTime| {
+            t.duration_since(start_time)
+                .unwrap_or(Duration::ZERO)
+                .as_nanos() as u64
+        };
+
+        match *self {
+            Self::Timestamp(Timestamp::Interval { start, end }) => CounterPayload::Interval {
+                start: counter_value(start),
+                end: counter_value(end),
+            },
+            Self::Timestamp(Timestamp::Instant(t)) => CounterPayload::Instant(counter_value(t)),
+            Self::Integer(i) => CounterPayload::Integer(i),
+        }
+    }
+
+    /// Recovers the raw counter values of the payload together with the
+    /// counter they were recorded with. `metadata` must be the metadata of the
+    /// profile the payload was decoded from.
+    pub fn counter_reading<'a>(&self, metadata: &'a Metadata) -> CounterReading<'a> {
+        CounterReading {
+            counter: &metadata.counter,
+            payload: self.counter_payload(metadata.start_time),
+        }
+    }
+}
+
+/// The payload of an event with the raw counter values as recorded, instead of
+/// the points in time `EventPayload` maps them to. The unit of the values is
+/// given by the `Counter` in the profile's metadata, e.g. nanoseconds for
+/// `wall-time` or instructions for `instructions:u`.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum CounterPayload {
+    Interval { start: u64, end: u64 },
+    Instant(u64),
+    Integer(u64),
+}
+
+impl CounterPayload {
+    pub fn from_raw_event(raw_event: &RawEvent) -> Self {
+        if raw_event.is_integer() {
+            Self::Integer(raw_event.value())
+        } else if raw_event.is_instant() {
+            Self::Instant(raw_event.start_value())
+        } else {
+            Self::Interval {
+                start: raw_event.start_value(),
+                end: raw_event.end_value(),
+            }
+        }
+    }
+
+    /// The counter delta between the start and the end of an interval.
+    pub fn duration(&self) -> Option<u64> {
+        match *self {
+            Self::Interval { start, end } => end.checked_sub(start),
+            Self::Instant(_) | Self::Integer(_) => None,
+        }
+    }
+}
+
+/// A `CounterPayload` together with the counter its values were recorded with,
+/// so that they can be displayed in the right unit (e.g. nanoseconds for
+/// `wall-time` or instructions for `instructions:u`).
+#[derive(Copy, Clone, Debug)]
+pub struct CounterReading<'a> {
+    pub counter: &'a Counter,
+    pub payload: CounterPayload,
+}
+
+impl<'a> CounterReading<'a> {
+    /// The unit of a single counter step, or `None` for integer events, whose
+    /// values are not counter values.
+    pub fn unit(&self) -> Option<&'a str> {
+        match self.payload {
+            CounterPayload::Interval { .. } | CounterPayload::Instant(_) => {
+                Some(self.counter.base_unit())
+            }
+            CounterPayload::Integer(_) => None,
+        }
+    }
+
+    /// The counter delta between the start and the end of an interval.
+    pub fn duration(&self) -> Option<u64> {
+        self.payload.duration()
+    }
+
+    /// Formats the counter delta of an interval in the counter's units.
+    pub fn format_duration(&self) -> Option<String> {
+        self.duration()
+            .map(|delta| self.counter.format_value(delta))
+    }
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
@@ -7,14 +7,17 @@ use std::{
 };
 
 use event::Event;
-use event_payload::EventPayload;
+use event_payload::{CounterPayload, CounterReading, EventPayload};
 use lightweight_event::LightweightEvent;
 use measureme::file_header::{verify_file_header, FILE_MAGIC_EVENT_STREAM};
 
+pub mod counter;
+pub mod error;
 pub mod event;
 pub mod event_payload;
 pub mod lightweight_event;
 pub mod stringtable;
+pub mod validation;
 
 // These re-exports allow us to use some types from the measureme version tied to this
 // version of decodeme, with explicitly mentioning that measureme version in downstream
@@ -24,18 +27,46 @@ pub use measureme::file_header::FILE_HEADER_SIZE;
 pub use measureme::file_header::FILE_MAGIC_TOP_LEVEL;
 pub use measureme::PageTag;
 pub use measureme::RawEvent;
+pub use measureme::StringId;
 
+use counter::Counter;
+use rustc_hash::{FxHashMap, FxHashSet};
 use serde::{Deserialize, Deserializer};
 use stringtable::StringTable;
+use validation::{Problem, ProblemKind};
+
+pub use crate::error::DecodeError;
+
+// The names under which the individual streams are reported in errors.
+const TOP_LEVEL_STREAM: &str = "top-level";
+const EVENT_STREAM: &str = "event";
+pub(crate) const STRING_DATA_STREAM: &str = "string data";
+pub(crate) const STRING_INDEX_STREAM: &str = "string index";
 
 fn system_time_from_nanos<'de, D>(deserializer: D) -> Result<SystemTime, D::Error>
 where
     D: Deserializer<'de>,
 {
     let duration_from_epoch = Duration::from_nanos(u64::deserialize(deserializer)?);
-    Ok(UNIX_EPOCH
+    UNIX_EPOCH
         .checked_add(duration_from_epoch)
-        .expect("a time that can be represented as SystemTime"))
+        .ok_or_else(|| serde::de::Error::custom("start_time cannot be represented as SystemTime"))
+}
+
+/// Checks the file header at the beginning of `bytes`, turning any problem
+/// into a [`DecodeError::InvalidHeader`].
+pub(crate) fn check_file_header(
+    bytes: &[u8],
+    expected_magic: &[u8; 4],
+    diagnostic_file_path: Option<&Path>,
+    stream: &'static str,
+) -> Result<(), DecodeError> {
+    verify_file_header(bytes, expected_magic, diagnostic_file_path, stream).map_err(|e| {
+        DecodeError::InvalidHeader {
+            stream,
+            message: e.to_string(),
+        }
+    })
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -44,6 +75,15 @@ pub struct Metadata {
     pub start_time: SystemTime,
     pub process_id: u32,
     pub cmd: String,
+    /// The counter the event timestamps were recorded with. Older profiles
+    /// don't specify one and always use the wall-time counter.
+    #[serde(default)]
+    pub counter: Counter,
+    /// How much recording a single interval event cost, as measured when the
+    /// profiler started, in the same unit as the event timestamps. Older
+    /// profiles don't specify it.
+    #[serde(default)]
+    pub recording_overhead: Option<u64>,
 }
 
 #[must_use]
@@ -103,25 +143,34 @@ impl EventDecoder {
     pub fn new(
         entire_file_data: Vec<u8>,
         diagnostic_file_path: Option<&Path>,
-    ) -> Result<EventDecoder, Box<dyn Error + Send + Sync>> {
-        verify_file_header(
+    ) -> Result<EventDecoder, DecodeError> {
+        check_file_header(
             &entire_file_data,
             FILE_MAGIC_TOP_LEVEL,
             diagnostic_file_path,
-            "top-level",
+            TOP_LEVEL_STREAM,
         )?;
 
-        let mut split_data = measureme::split_streams(&entire_file_data[FILE_HEADER_SIZE..]);
-
-        let string_data = split_data
-            .remove(&PageTag::StringData)
-            .ok_or("Invalid file: No string data found")?;
-        let index_data = split_data
-            .remove(&PageTag::StringIndex)
-            .ok_or("Invalid file: No string index data found")?;
+        let mut split_data = measureme::split_streams(&entire_file_data[FILE_HEADER_SIZE..])
+            .map_err(|e| DecodeError::from_page_error(e, FILE_HEADER_SIZE))?;
+
+        let string_data =
+            split_data
+                .remove(&PageTag::StringData)
+                .ok_or(DecodeError::MissingStream {
+                    stream: STRING_DATA_STREAM,
+                })?;
+        let index_data =
+            split_data
+                .remove(&PageTag::StringIndex)
+                .ok_or(DecodeError::MissingStream {
+                    stream: STRING_INDEX_STREAM,
+                })?;
         let event_data = split_data
             .remove(&PageTag::Events)
-            .ok_or("Invalid file: No event data found")?;
+            .ok_or(DecodeError::MissingStream {
+                stream: EVENT_STREAM,
+            })?;
 
         Self::from_separate_buffers(string_data, index_data, event_data, diagnostic_file_path)
     }
@@ -131,18 +180,31 @@ impl EventDecoder {
         index_data: Vec<u8>,
         event_data: Vec<u8>,
         diagnostic_file_path: Option<&Path>,
-    ) -> Result<EventDecoder, Box<dyn Error + Send + Sync>> {
-        verify_file_header(
+    ) -> Result<EventDecoder, DecodeError> {
+        check_file_header(
             &event_data,
             FILE_MAGIC_EVENT_STREAM,
             diagnostic_file_path,
-            "event",
+            EVENT_STREAM,
         )?;
 
+        // The non-header data should be divisible into events.
+        let excess_bytes = (event_data.len() - FILE_HEADER_SIZE) % RAW_EVENT_SIZE;
+        if excess_bytes != 0 {
+            return Err(DecodeError::TruncatedEntry {
+                stream: EVENT_STREAM,
+                offset: event_data.len() - excess_bytes,
+                entry_size: RAW_EVENT_SIZE,
+            });
+        }
+
         let stringtable = StringTable::new(string_data, index_data, diagnostic_file_path)?;
 
         let metadata = stringtable.get_metadata().to_string();
-        let metadata: Metadata = serde_json::from_str(&metadata)?;
+        let metadata: Metadata =
+            serde_json::from_str(&metadata).map_err(|e| DecodeError::InvalidMetadata {
+                message: e.to_string(),
+            })?;
 
         Ok(EventDecoder {
             event_data,
@@ -152,21 +214,31 @@ impl EventDecoder {
     }
 
     pub fn num_events(&self) -> usize {
-        let event_byte_count = self.event_data.len() - FILE_HEADER_SIZE;
-        assert!(event_byte_count % RAW_EVENT_SIZE == 0);
-        event_byte_count / RAW_EVENT_SIZE
+        // `from_separate_buffers` makes sure that the event data consists of
+        // whole events.
+        (self.event_data.len() - FILE_HEADER_SIZE) / RAW_EVENT_SIZE
     }
 
     pub fn metadata(&self) -> Metadata {
         self.metadata.clone()
     }
 
-    pub fn decode_full_event<'a>(&'a self, event_index: usize) -> Event<'a> {
+    fn raw_event(&self, event_index: usize) -> RawEvent {
         let event_start_addr = event_index_to_addr(event_index);
         let event_end_addr = event_start_addr.checked_add(RAW_EVENT_SIZE).unwrap();
 
         let raw_event_bytes = &self.event_data[event_start_addr..event_end_addr];
-        let raw_event = RawEvent::deserialize(raw_event_bytes);
+        RawEvent::deserialize(raw_event_bytes)
+    }
+
+    /// The id of the thread the given event was recorded on, without decoding
+    /// the rest of the event.
+    pub fn thread_id(&self, event_index: usize) -> u32 {
+        self.raw_event(event_index).thread_id
+    }
+
+    pub fn decode_full_event<'a>(&'a self, event_index: usize) -> Event<'a> {
+        let raw_event = self.raw_event(event_index);
 
         let stringtable = &self.stringtable;
 
@@ -189,11 +261,7 @@ impl EventDecoder {
     }
 
     pub fn decode_lightweight_event<'a>(&'a self, event_index: usize) -> LightweightEvent {
-        let event_start_addr = event_index_to_addr(event_index);
-        let event_end_addr = event_start_addr.checked_add(RAW_EVENT_SIZE).unwrap();
-
-        let raw_event_bytes = &self.event_data[event_start_addr..event_end_addr];
-        let raw_event = RawEvent::deserialize(raw_event_bytes);
+        let raw_event = self.raw_event(event_index);
 
         let payload = EventPayload::from_raw_event(&raw_event, self.metadata.start_time);
 
@@ -203,6 +271,50 @@ impl EventDecoder {
             thread_id: raw_event.thread_id,
         }
     }
+
+    /// Decodes the payload of the given event without interpreting the
+    /// counter values as timestamps, together with the counter that recorded
+    /// them.
+    pub fn decode_counter_payload(&self, event_index: usize) -> CounterReading<'_> {
+        CounterReading {
+            counter: &self.metadata.counter,
+            payload: CounterPayload::from_raw_event(&self.raw_event(event_index)),
+        }
+    }
+
+    /// Checks that the event kind and event ID strings of all events can be
+    /// decoded, i.e. that they don't refer to unmapped virtual strings or to
+    /// strings that don't exist, and that the string data is well-formed.
+    pub fn check_strings(&self) -> Vec<Problem> {
+        let mut valid = FxHashSet::default();
+        let mut invalid = FxHashMap::<StringId, ProblemKind>::default();
+        let mut problems = Vec::new();
+
+        for event_index in 0..self.num_events() {
+            let raw_event = self.raw_event(event_index);
+
+            for &string_id in &[raw_event.event_kind, raw_event.event_id.to_string_id()] {
+                if valid.contains(&string_id) {
+                    continue;
+                }
+
+                let kind = match invalid.get(&string_id) {
+                    Some(kind) => kind.clone(),
+                    None => match self.stringtable.check_string(string_id, &mut valid) {
+                        Ok(()) => continue,
+                        Err(kind) => {
+                            invalid.insert(string_id, kind.clone());
+                            kind
+                        }
+                    },
+                };
+
+                problems.push(Problem { event_index, kind });
+            }
+        }
+
+        problems
+    }
 }
 
 fn event_index_to_addr(event_index: usize) -> usize {
@@ -1,9 +1,11 @@
 //! See module-level documentation `measureme::stringtable`.
 
+use crate::validation::ProblemKind;
+use crate::{check_file_header, DecodeError, STRING_DATA_STREAM, STRING_INDEX_STREAM};
 use measureme::stringtable::{METADATA_STRING_ID, TERMINATOR};
 use measureme::{
     file_header::{
-        strip_file_header, verify_file_header, FILE_MAGIC_STRINGTABLE_DATA,
+        strip_file_header, FILE_HEADER_SIZE, FILE_MAGIC_STRINGTABLE_DATA,
         FILE_MAGIC_STRINGTABLE_INDEX,
     },
     stringtable::STRING_REF_ENCODED_SIZE,
@@ -11,10 +13,9 @@ use measureme::{
 };
 use measureme::{Addr, StringId};
 use memchr::{memchr, memchr2};
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet};
 use std::borrow::Cow;
 use std::convert::TryInto;
-use std::error::Error;
 use std::path::Path;
 
 const INDEX_ENTRY_SIZE: usize = std::mem::size_of::<StringId>() + std::mem::size_of::<Addr>();
@@ -40,11 +41,23 @@ const UNKNOWN_STRING: &str = "<unknown>";
 // proper terminator.
 const INVALID_STRING: &str = "<invalid>";
 
+// String references in the table data may form arbitrarily deep (or even
+// cyclic) chains in corrupt files. When expanding a string we give up and emit
+// `INVALID_STRING` after following this many nested references ...
+const MAX_STRING_REF_DEPTH: usize = 256;
+
+// ... or after expanding this many references in total.
+const MAX_STRING_REF_COUNT: usize = 1_000_000;
+
 impl<'st> StringRef<'st> {
     /// Expands the StringRef into an actual string. This method will
     /// avoid allocating a `String` if it can instead return a `&str` pointing
     /// into the raw string table data.
     pub fn to_string(&self) -> Cow<'st, str> {
+        self.expand_with_depth(0)
+    }
+
+    fn expand_with_depth(&self, depth: usize) -> Cow<'st, str> {
         let addr = match self.get_addr() {
             Ok(addr) => addr,
             Err(_) => return Cow::from(UNKNOWN_STRING),
@@ -56,22 +69,34 @@ impl<'st> StringRef<'st> {
         //  - a string with a single reference component (`[string_id, 0xFF]`)
 
         let pos = addr.as_usize();
-        let slice_to_search = &self.table.string_data[pos..];
+        let slice_to_search = match self.table.string_data.get(pos..) {
+            Some(slice) if !slice.is_empty() => slice,
+            _ => return Cow::from(INVALID_STRING),
+        };
 
         // Find the first 0xFF byte which which is either the sequence
         // terminator or a byte in the middle of string id. Use `memchr` which
         // is super fast.
-        let terminator_pos = memchr(TERMINATOR, slice_to_search).unwrap();
+        let terminator_pos = match memchr(TERMINATOR, slice_to_search) {
+            Some(terminator_pos) => terminator_pos,
+            None => return Cow::from(INVALID_STRING),
+        };
 
         // Check if this is a string containing a single StringId component
-        let first_byte = self.table.string_data[pos];
+        let first_byte = slice_to_search[0];
         if first_byte == STRING_REF_TAG && terminator_pos == pos + STRING_REF_ENCODED_SIZE {
-            let id = decode_string_ref_from_data(&self.table.string_data[pos..]);
-            return StringRef {
-                id,
-                table: self.table,
+            if depth >= MAX_STRING_REF_DEPTH {
+                return Cow::from(INVALID_STRING);
             }
-            .to_string();
+
+            return match decode_string_ref_from_data(slice_to_search) {
+                Some(id) => StringRef {
+                    id,
+                    table: self.table,
+                }
+                .expand_with_depth(depth + 1),
+                None => Cow::from(INVALID_STRING),
+            };
         }
 
         // Decode the bytes until the terminator. If there is a string id in
@@ -91,6 +116,11 @@ impl<'st> StringRef<'st> {
     }
 
     pub fn write_to_string(&self, output: &mut String) {
+        let mut refs_left = MAX_STRING_REF_COUNT;
+        self.write_expand_with_depth(output, 0, &mut refs_left);
+    }
+
+    fn write_expand_with_depth(&self, output: &mut String, depth: usize, refs_left: &mut usize) {
         let addr = match self.get_addr() {
             Ok(addr) => addr,
             Err(_) => {
@@ -102,17 +132,37 @@ impl<'st> StringRef<'st> {
         let mut pos = addr.as_usize();
 
         loop {
-            let byte = self.table.string_data[pos];
+            let byte = match self.table.string_data.get(pos) {
+                Some(&byte) => byte,
+                None => {
+                    // We ran off the end of the data without encountering a
+                    // terminator.
+                    output.push_str(INVALID_STRING);
+                    return;
+                }
+            };
 
             if byte == TERMINATOR {
                 return;
             } else if byte == STRING_REF_TAG {
+                let id = decode_string_ref_from_data(&self.table.string_data[pos..]);
+
+                let id = match id {
+                    Some(id) if depth < MAX_STRING_REF_DEPTH && *refs_left > 0 => id,
+                    _ => {
+                        output.push_str(INVALID_STRING);
+                        return;
+                    }
+                };
+
+                *refs_left -= 1;
+
                 let string_ref = StringRef {
-                    id: decode_string_ref_from_data(&self.table.string_data[pos..]),
+                    id,
                     table: self.table,
                 };
 
-                string_ref.write_to_string(output);
+                string_ref.write_expand_with_depth(output, depth + 1, refs_left);
 
                 pos += STRING_REF_ENCODED_SIZE;
             } else {
@@ -149,15 +199,17 @@ impl<'st> StringRef<'st> {
 
 // String IDs in the table data are encoded in big endian format, while string
 // IDs in the index are encoded in little endian format. Don't mix the two up.
-fn decode_string_ref_from_data(bytes: &[u8]) -> StringId {
-    // The code below assumes we use a 5-byte encoding for string
+//
+// Returns `None` if `bytes` is too short to contain an encoded string ID.
+fn decode_string_ref_from_data(bytes: &[u8]) -> Option<StringId> {
+    // The code below assumes we use a 9-byte encoding for string
     // refs, where the first byte is STRING_REF_TAG and the
-    // following 4 bytes are a little-endian u32 string ID value.
+    // following 8 bytes are a little-endian u64 string ID value.
     assert!(bytes[0] == STRING_REF_TAG);
     assert!(STRING_REF_ENCODED_SIZE == 9);
 
-    let id = u64::from_le_bytes(bytes[1..9].try_into().unwrap());
-    StringId::new(id)
+    let id = u64::from_le_bytes(bytes.get(1..9)?.try_into().unwrap());
+    Some(StringId::new(id))
 }
 
 /// Read-only version of the string table
@@ -173,25 +225,29 @@ impl StringTable {
         string_data: Vec<u8>,
         index_data: Vec<u8>,
         diagnostic_file_path: Option<&Path>,
-    ) -> Result<StringTable, Box<dyn Error + Send + Sync>> {
-        verify_file_header(
+    ) -> Result<StringTable, DecodeError> {
+        check_file_header(
             &string_data,
             FILE_MAGIC_STRINGTABLE_DATA,
             diagnostic_file_path,
-            "StringTable Data",
+            STRING_DATA_STREAM,
         )?;
-        verify_file_header(
+        check_file_header(
             &index_data,
             FILE_MAGIC_STRINGTABLE_INDEX,
             diagnostic_file_path,
-            "StringTable Index",
+            STRING_INDEX_STREAM,
         )?;
 
         // The non-header data should be divisible into index entries.
-        assert!(
-            (index_data.len() - measureme::file_header::FILE_HEADER_SIZE) % INDEX_ENTRY_SIZE == 0,
-            "StringTable index size appears malformed",
-        );
+        let excess_bytes = (index_data.len() - FILE_HEADER_SIZE) % INDEX_ENTRY_SIZE;
+        if excess_bytes != 0 {
+            return Err(DecodeError::TruncatedEntry {
+                stream: STRING_INDEX_STREAM,
+                offset: index_data.len() - excess_bytes,
+                entry_size: INDEX_ENTRY_SIZE,
+            });
+        }
         assert_eq!(INDEX_ENTRY_SIZE, 16);
 
         let index: FxHashMap<_, _> = strip_file_header(&index_data)
@@ -211,6 +267,79 @@ impl StringTable {
         let id = StringId::new(METADATA_STRING_ID);
         self.get(id)
     }
+
+    /// Checks that the string with the given ID can be expanded without
+    /// running into any of the placeholders `StringRef::to_string` emits for
+    /// corrupt data. `valid` holds the IDs of strings that are already known
+    /// to be fine; it is extended with every string checked successfully.
+    pub(crate) fn check_string(
+        &self,
+        id: StringId,
+        valid: &mut FxHashSet<StringId>,
+    ) -> Result<(), ProblemKind> {
+        match self.get(id).get_addr() {
+            Ok(addr) => self.check_string_data(id, addr, 0, valid),
+            Err(()) if id.is_virtual() => Err(ProblemKind::UnmappedVirtualString { string_id: id }),
+            Err(()) => Err(ProblemKind::MalformedString { string_id: id }),
+        }
+    }
+
+    fn check_string_data(
+        &self,
+        id: StringId,
+        addr: Addr,
+        depth: usize,
+        valid: &mut FxHashSet<StringId>,
+    ) -> Result<(), ProblemKind> {
+        if valid.contains(&id) {
+            return Ok(());
+        }
+
+        let malformed = ProblemKind::MalformedString { string_id: id };
+        let mut pos = addr.as_usize();
+
+        loop {
+            match self.string_data.get(pos) {
+                None => return Err(malformed),
+                Some(&TERMINATOR) => break,
+                Some(&STRING_REF_TAG) => {
+                    let target = match decode_string_ref_from_data(&self.string_data[pos..]) {
+                        Some(target) if depth < MAX_STRING_REF_DEPTH => target,
+                        _ => return Err(malformed),
+                    };
+
+                    let target_addr = match self.get(target).get_addr() {
+                        Ok(target_addr)
+                            if target_addr.as_usize() >= FILE_HEADER_SIZE
+                                && target_addr.as_usize() < self.string_data.len() =>
+                        {
+                            target_addr
+                        }
+                        _ => {
+                            return Err(ProblemKind::DanglingStringRef {
+                                string_id: id,
+                                target,
+                            })
+                        }
+                    };
+
+                    self.check_string_data(target, target_addr, depth + 1, valid)?;
+                    pos += STRING_REF_ENCODED_SIZE;
+                }
+                Some(_) => {
+                    let remaining_data = &self.string_data[pos..];
+                    let len = match memchr2(TERMINATOR, STRING_REF_TAG, remaining_data) {
+                        Some(len) if std::str::from_utf8(&remaining_data[..len]).is_ok() => len,
+                        _ => return Err(malformed),
+                    };
+                    pos += len;
+                }
+            }
+        }
+
+        valid.insert(id);
+        Ok(())
+    }
 }
 
 #[cfg(test)]
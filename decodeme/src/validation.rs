@@ -0,0 +1,62 @@
+//! Consistency checks for profiling data. Problems like dangling string
+//! references don't prevent a profile from being decoded, they just make the
+//! decoded events contain placeholder text, so they are easy to miss.
+
+use measureme::StringId;
+use std::fmt;
+
+/// A problem found in the profiling data, together with the index of the
+/// event it affects.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Problem {
+    pub event_index: usize,
+    pub kind: ProblemKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProblemKind {
+    /// The event refers to a virtual string ID that was never mapped to a
+    /// concrete string via `map_virtual_to_concrete_string`.
+    UnmappedVirtualString { string_id: StringId },
+    /// The string `string_id` contains a `StringComponent::Ref` to `target`,
+    /// which does not exist.
+    DanglingStringRef {
+        string_id: StringId,
+        target: StringId,
+    },
+    /// The data of the string `string_id` is not properly terminated, is not
+    /// valid UTF-8, or contains references nested too deeply (or cyclically).
+    MalformedString { string_id: StringId },
+    /// The event is an interval that overlaps the interval event
+    /// `other_event_index` on the same thread without either one containing
+    /// the other.
+    OverlappingIntervals { other_event_index: usize },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "event {}: ", self.event_index)?;
+
+        match self.kind {
+            ProblemKind::UnmappedVirtualString { string_id } => write!(
+                f,
+                "virtual string id {} is not mapped to a concrete string",
+                string_id.as_u64()
+            ),
+            ProblemKind::DanglingStringRef { string_id, target } => write!(
+                f,
+                "string {} refers to non-existent string {}",
+                string_id.as_u64(),
+                target.as_u64()
+            ),
+            ProblemKind::MalformedString { string_id } => {
+                write!(f, "string {} is malformed", string_id.as_u64())
+            }
+            ProblemKind::OverlappingIntervals { other_event_index } => write!(
+                f,
+                "interval overlaps event {} on the same thread without nesting",
+                other_event_index
+            ),
+        }
+    }
+}
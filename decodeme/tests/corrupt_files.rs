@@ -0,0 +1,497 @@
+//! Checks that decoding corrupt profiling data reports errors instead of
+//! panicking. Apart from a few hand-crafted cases, the inputs are derived from
+//! a valid file by truncating it and by flipping bytes at pseudo-random
+//! positions.
+
+use decodeme::validation::{Problem, ProblemKind};
+use decodeme::{DecodeError, EventDecoder, PageTag, FILE_HEADER_SIZE, FILE_MAGIC_TOP_LEVEL};
+use measureme::file_header::{write_file_header, FILE_MAGIC_EVENT_STREAM};
+use measureme::{
+    Addr, EventId, PageError, RawEvent, SerializationSinkBuilder, StringComponent, StringId,
+    StringTableBuilder,
+};
+use std::sync::Arc;
+
+const METADATA: &str = r#"{ "start_time": 0, "process_id": 1, "cmd": "rustc" }"#;
+
+struct Streams {
+    string_data: Vec<u8>,
+    string_index: Vec<u8>,
+    events: Vec<u8>,
+}
+
+impl Streams {
+    fn new(metadata: &str, fill: impl FnOnce(&StringTableBuilder) -> Vec<RawEvent>) -> Streams {
+        let sink_builder = SerializationSinkBuilder::new_in_memory();
+        let data_sink = Arc::new(sink_builder.new_sink(PageTag::StringData));
+        let index_sink = Arc::new(sink_builder.new_sink(PageTag::StringIndex));
+        let event_sink = sink_builder.new_sink(PageTag::Events);
+
+        write_file_header(&mut event_sink.as_std_write(), FILE_MAGIC_EVENT_STREAM).unwrap();
+
+        {
+            let string_table =
+                StringTableBuilder::new(data_sink.clone(), index_sink.clone()).unwrap();
+            string_table.alloc_metadata(metadata);
+
+            for raw_event in fill(&string_table) {
+                event_sink.write_atomic(std::mem::size_of::<RawEvent>(), |bytes| {
+                    raw_event.serialize(bytes)
+                });
+            }
+        }
+
+        Streams {
+            string_data: Arc::try_unwrap(data_sink).unwrap().into_bytes(),
+            string_index: Arc::try_unwrap(index_sink).unwrap().into_bytes(),
+            events: event_sink.into_bytes(),
+        }
+    }
+
+    fn pages(&self) -> Vec<(u8, Vec<u8>)> {
+        vec![
+            (PageTag::StringData as u8, self.string_data.clone()),
+            (PageTag::Events as u8, self.events.clone()),
+            (PageTag::StringIndex as u8, self.string_index.clone()),
+        ]
+    }
+
+    fn decode(&self) -> Result<EventDecoder, DecodeError> {
+        EventDecoder::from_separate_buffers(
+            self.string_data.clone(),
+            self.string_index.clone(),
+            self.events.clone(),
+            None,
+        )
+    }
+}
+
+/// Builds a top-level file from the given pages. The page sizes are taken
+/// from the contents, so this can also be used to create empty pages.
+fn paged_file(pages: &[(u8, Vec<u8>)]) -> Vec<u8> {
+    let mut file = Vec::new();
+    write_file_header(&mut file, FILE_MAGIC_TOP_LEVEL).unwrap();
+
+    for (tag, contents) in pages {
+        file.push(*tag);
+        file.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+        file.extend_from_slice(contents);
+    }
+
+    file
+}
+
+fn sample_streams() -> Streams {
+    Streams::new(METADATA, |string_table| {
+        let kind = string_table.alloc("Query");
+        let label = string_table.alloc("typeck");
+        let arg = string_table.alloc("foo::bar");
+        let with_arg = string_table.alloc(
+            &[
+                StringComponent::Ref(label),
+                StringComponent::Value(measureme::event_id::SEPARATOR_BYTE),
+                StringComponent::Ref(arg),
+            ][..],
+        );
+
+        let virtual_id = StringId::new_virtual(42u64);
+        string_table.map_virtual_to_concrete_string(virtual_id, label);
+
+        vec![
+            RawEvent::new_interval(kind, EventId::from_label(label), 1, 10, 20),
+            RawEvent::new_interval(kind, EventId::from_label(with_arg), 1, 5, 30),
+            RawEvent::new_instant(kind, EventId::from_virtual(virtual_id), 2, 7),
+            RawEvent::new_integer(kind, EventId::from_label(label), 2, 1234),
+        ]
+    })
+}
+
+/// Decodes everything there is to decode. We only care about not panicking
+/// here, the results themselves are garbage for most inputs.
+fn decode_all(decoder: &EventDecoder) {
+    let _ = decoder.metadata();
+
+    for i in 0..decoder.num_events() {
+        let event = decoder.decode_full_event(i);
+        let _ = event.duration();
+        let _ = decoder.decode_lightweight_event(i);
+    }
+
+    let _ = decoder.check_strings();
+}
+
+fn check_file(file: Vec<u8>) {
+    if let Ok(decoder) = EventDecoder::new(file, None) {
+        decode_all(&decoder);
+    }
+}
+
+/// A small xorshift generator so that failures are reproducible.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+#[test]
+fn valid_file() {
+    let streams = sample_streams();
+    let decoder = EventDecoder::new(paged_file(&streams.pages()), None).unwrap();
+
+    assert_eq!(decoder.num_events(), 4);
+    assert_eq!(decoder.metadata().cmd, "rustc");
+    assert_eq!(decoder.decode_full_event(1).label, "typeck");
+    assert_eq!(
+        decoder.decode_full_event(1).additional_data,
+        vec!["foo::bar"]
+    );
+    assert_eq!(decoder.decode_full_event(2).label, "typeck");
+    assert_eq!(decoder.check_strings(), vec![]);
+}
+
+#[test]
+fn truncated_files() {
+    let file = paged_file(&sample_streams().pages());
+
+    for len in 0..file.len() {
+        check_file(file[..len].to_vec());
+    }
+}
+
+#[test]
+fn mutated_files() {
+    let file = paged_file(&sample_streams().pages());
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+
+    for _ in 0..5_000 {
+        let mut mutated = file.clone();
+
+        for _ in 0..1 + rng.below(4) {
+            let pos = rng.below(mutated.len());
+            mutated[pos] = rng.next() as u8;
+        }
+
+        check_file(mutated);
+    }
+}
+
+#[test]
+fn mutated_streams() {
+    let streams = sample_streams();
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+
+    for _ in 0..5_000 {
+        let mut string_data = streams.string_data.clone();
+        let mut string_index = streams.string_index.clone();
+        let mut events = streams.events.clone();
+
+        let stream = match rng.below(3) {
+            0 => &mut string_data,
+            1 => &mut string_index,
+            _ => &mut events,
+        };
+
+        // Leave the stream header alone, we want to get past it.
+        let pos = FILE_HEADER_SIZE + rng.below(stream.len() - FILE_HEADER_SIZE);
+        stream[pos] = rng.next() as u8;
+
+        if let Ok(decoder) =
+            EventDecoder::from_separate_buffers(string_data, string_index, events, None)
+        {
+            decode_all(&decoder);
+        }
+    }
+}
+
+#[test]
+fn random_garbage() {
+    let mut rng = Rng(0xdead_beef_cafe_f00d);
+
+    for _ in 0..1_000 {
+        let len = rng.below(512);
+        let garbage: Vec<u8> = (0..len).map(|_| rng.next() as u8).collect();
+
+        // Once as is and once with a valid header in front so that we get to
+        // the page parsing.
+        check_file(garbage.clone());
+
+        let mut file = Vec::new();
+        write_file_header(&mut file, FILE_MAGIC_TOP_LEVEL).unwrap();
+        file.extend_from_slice(&garbage);
+        check_file(file);
+    }
+}
+
+#[test]
+fn invalid_header() {
+    let mut file = paged_file(&sample_streams().pages());
+    file[0] = b'X';
+
+    assert!(matches!(
+        EventDecoder::new(file, None),
+        Err(DecodeError::InvalidHeader {
+            stream: "top-level",
+            ..
+        })
+    ));
+}
+
+#[test]
+fn invalid_page_tag() {
+    let mut pages = sample_streams().pages();
+    pages[1].0 = 7;
+
+    assert_eq!(
+        EventDecoder::new(paged_file(&pages), None).unwrap_err(),
+        DecodeError::InvalidPage {
+            offset: FILE_HEADER_SIZE + 5 + pages[0].1.len(),
+            error: PageError::InvalidTag {
+                offset: 5 + pages[0].1.len(),
+                tag: 7
+            },
+        }
+    );
+}
+
+#[test]
+fn empty_page() {
+    let mut pages = sample_streams().pages();
+    pages.insert(0, (PageTag::Events as u8, Vec::new()));
+
+    assert_eq!(
+        EventDecoder::new(paged_file(&pages), None).unwrap_err(),
+        DecodeError::InvalidPage {
+            offset: FILE_HEADER_SIZE,
+            error: PageError::EmptyPage { offset: 0 },
+        }
+    );
+}
+
+#[test]
+fn truncated_page() {
+    let mut file = paged_file(&sample_streams().pages());
+    file.pop();
+
+    assert!(matches!(
+        EventDecoder::new(file, None),
+        Err(DecodeError::InvalidPage {
+            error: PageError::Truncated { .. },
+            ..
+        })
+    ));
+}
+
+#[test]
+fn missing_stream() {
+    let pages: Vec<_> = sample_streams()
+        .pages()
+        .into_iter()
+        .filter(|&(tag, _)| tag != PageTag::Events as u8)
+        .collect();
+
+    assert_eq!(
+        EventDecoder::new(paged_file(&pages), None).unwrap_err(),
+        DecodeError::MissingStream { stream: "event" }
+    );
+}
+
+#[test]
+fn incomplete_event() {
+    let mut streams = sample_streams();
+    let complete_len = streams.events.len();
+    streams.events.extend_from_slice(&[1, 2, 3]);
+
+    assert_eq!(
+        streams.decode().unwrap_err(),
+        DecodeError::TruncatedEntry {
+            stream: "event",
+            offset: complete_len,
+            entry_size: std::mem::size_of::<RawEvent>(),
+        }
+    );
+}
+
+#[test]
+fn incomplete_index_entry() {
+    let mut streams = sample_streams();
+    let complete_len = streams.string_index.len();
+    streams.string_index.push(0);
+
+    assert_eq!(
+        streams.decode().unwrap_err(),
+        DecodeError::TruncatedEntry {
+            stream: "string index",
+            offset: complete_len,
+            entry_size: 16,
+        }
+    );
+}
+
+#[test]
+fn invalid_metadata() {
+    let streams = Streams::new("not json", |_| Vec::new());
+
+    assert!(matches!(
+        streams.decode(),
+        Err(DecodeError::InvalidMetadata { .. })
+    ));
+}
+
+#[test]
+fn cyclic_string_refs() {
+    let streams = Streams::new(METADATA, |string_table| {
+        let virtual_id = StringId::new_virtual(1u64);
+        let cycle = string_table.alloc(&[StringComponent::Ref(virtual_id)][..]);
+        let prefixed_cycle = string_table.alloc(
+            &[
+                StringComponent::Value("prefix"),
+                StringComponent::Ref(virtual_id),
+            ][..],
+        );
+        string_table.map_virtual_to_concrete_string(virtual_id, cycle);
+
+        vec![
+            RawEvent::new_instant(cycle, EventId::from_virtual(virtual_id), 1, 1),
+            RawEvent::new_instant(prefixed_cycle, EventId::from_label(prefixed_cycle), 1, 2),
+        ]
+    });
+
+    let decoder = streams.decode().unwrap();
+
+    assert_eq!(decoder.decode_full_event(0).label, "<invalid>");
+    assert_eq!(decoder.decode_full_event(1).event_kind, "prefix<invalid>");
+}
+
+#[test]
+fn exponential_string_refs() {
+    // Every string refers to the previous one twice, so fully expanding the
+    // last one would take 2^64 steps.
+    let streams = Streams::new(METADATA, |string_table| {
+        let mut id = string_table.alloc("x");
+
+        for _ in 0..64 {
+            id = string_table.alloc(&[StringComponent::Ref(id), StringComponent::Ref(id)][..]);
+        }
+
+        vec![RawEvent::new_instant(id, EventId::from_label(id), 1, 1)]
+    });
+
+    let decoder = streams.decode().unwrap();
+
+    assert!(decoder
+        .decode_full_event(0)
+        .event_kind
+        .ends_with("<invalid>"));
+
+    // The string is perfectly valid, just too large to be expanded.
+    assert_eq!(decoder.check_strings(), vec![]);
+}
+
+#[test]
+fn dangling_string_refs() {
+    let streams = Streams::new(METADATA, |string_table| {
+        let unmapped = StringId::new_virtual(5u64);
+        let kind = string_table.alloc(
+            &[
+                StringComponent::Value("kind:"),
+                StringComponent::Ref(unmapped),
+            ][..],
+        );
+
+        vec![
+            RawEvent::new_instant(kind, EventId::from_virtual(unmapped), 1, 1),
+            // Points way past the end of the string data.
+            RawEvent::new_instant(
+                StringId::from_addr(Addr(1_000_000)),
+                EventId::from_label(kind),
+                1,
+                2,
+            ),
+        ]
+    });
+
+    let decoder = streams.decode().unwrap();
+
+    assert_eq!(decoder.decode_full_event(0).label, "<unknown>");
+    assert_eq!(decoder.decode_full_event(0).event_kind, "kind:<unknown>");
+    assert_eq!(decoder.decode_full_event(1).event_kind, "<invalid>");
+}
+
+#[test]
+fn check_strings() {
+    let mut ids = Vec::new();
+
+    let streams = Streams::new(METADATA, |string_table| {
+        let valid = string_table.alloc("valid");
+        let unmapped = StringId::new_virtual(5u64);
+        let dangling = string_table.alloc(
+            &[
+                StringComponent::Value("dangling:"),
+                StringComponent::Ref(StringId::from_addr(Addr(1_000_000))),
+            ][..],
+        );
+        let cycle_id = StringId::new_virtual(6u64);
+        let cycle = string_table.alloc(&[StringComponent::Ref(cycle_id)][..]);
+        string_table.map_virtual_to_concrete_string(cycle_id, cycle);
+
+        ids.extend_from_slice(&[valid, unmapped, dangling, cycle_id]);
+
+        vec![
+            RawEvent::new_instant(valid, EventId::from_label(valid), 1, 1),
+            RawEvent::new_instant(valid, EventId::from_virtual(unmapped), 1, 2),
+            RawEvent::new_instant(dangling, EventId::from_label(valid), 1, 3),
+            RawEvent::new_instant(valid, EventId::from_label(cycle), 1, 4),
+            RawEvent::new_instant(dangling, EventId::from_virtual(unmapped), 1, 5),
+        ]
+    });
+
+    let (unmapped, dangling, cycle_id) = (ids[1], ids[2], ids[3]);
+    let problem = |event_index, kind| Problem { event_index, kind };
+
+    assert_eq!(
+        streams.decode().unwrap().check_strings(),
+        vec![
+            problem(
+                1,
+                ProblemKind::UnmappedVirtualString {
+                    string_id: unmapped
+                }
+            ),
+            problem(
+                2,
+                ProblemKind::DanglingStringRef {
+                    string_id: dangling,
+                    target: StringId::from_addr(Addr(1_000_000)),
+                }
+            ),
+            problem(
+                3,
+                ProblemKind::MalformedString {
+                    string_id: cycle_id
+                }
+            ),
+            problem(
+                4,
+                ProblemKind::DanglingStringRef {
+                    string_id: dangling,
+                    target: StringId::from_addr(Addr(1_000_000)),
+                }
+            ),
+            problem(
+                4,
+                ProblemKind::UnmappedVirtualString {
+                    string_id: unmapped
+                }
+            ),
+        ]
+    );
+}
@@ -3,19 +3,30 @@ use std::fs::File;
 use std::io::BufWriter;
 use std::path::PathBuf;
 
-use analyzeme::{collapse_stacks, ProfilingData};
+use analyzeme::{collapse_stacks, FilterArgs, ProfilingData};
 use clap::Parser;
 use inferno::flamegraph::{from_lines, Options as FlamegraphOptions};
 
 #[derive(Parser, Debug)]
 struct Opt {
-    file_prefix: PathBuf,
+    /// The profiles to analyze. Profiles of several processes, e.g. of all
+    /// rustc invocations of a build, are merged into one.
+    #[arg(required = true)]
+    file_prefix: Vec<PathBuf>,
+
+    #[command(flatten)]
+    filter: FilterArgs,
 }
 
 fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
     let opt = Opt::parse();
 
-    let profiling_data = ProfilingData::new(&opt.file_prefix)?;
+    let profiles = opt
+        .file_prefix
+        .iter()
+        .map(|file_prefix| ProfilingData::new(file_prefix))
+        .collect::<Result<Vec<_>, _>>()?;
+    let profiling_data = opt.filter.apply(ProfilingData::merge(profiles)?)?;
 
     let recorded_stacks = collapse_stacks(&profiling_data)
         .iter()
@@ -25,6 +36,13 @@ fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
     let file = BufWriter::new(File::create("rustc.svg")?);
     let mut flamegraph_options = FlamegraphOptions::default();
 
+    // The collapsed stacks count counter steps, which are only nanoseconds
+    // for the wall-time counter.
+    let counter = &profiling_data.metadata().counter;
+    if !counter.is_wall_time() {
+        flamegraph_options.count_name = counter.base_unit().to_string();
+    }
+
     from_lines(
         &mut flamegraph_options,
         recorded_stacks.iter().map(|s| s.as_ref()),
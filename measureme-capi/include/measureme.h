@@ -0,0 +1,216 @@
+#ifndef MEASUREME_H
+#define MEASUREME_H
+
+/* This file is generated by cbindgen from measureme-capi/src/lib.rs. Do not edit it by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef enum MmPayloadKind {
+  // An event with a start and an end.
+  MmPayloadKind_Interval,
+  // An event at a single point in time.
+  MmPayloadKind_Instant,
+  // An event carrying an integer value instead of a timestamp.
+  MmPayloadKind_Integer,
+} MmPayloadKind;
+
+// A handle for reading the events of a profile.
+typedef struct MmDecoder MmDecoder;
+
+// An interval event that has been started with
+// [`mm_profiler_start_interval_event`] but not yet finished.
+typedef struct MmIntervalTiming MmIntervalTiming;
+
+// A handle for recording events into a profile.
+typedef struct MmProfiler MmProfiler;
+
+// A decoded event. The strings are owned by the event and must be released
+// with [`mm_event_free`].
+typedef struct MmEvent {
+  char *event_kind;
+  char *label;
+  // An array of `num_additional_data` strings.
+  char **additional_data;
+  size_t num_additional_data;
+  uint32_t thread_id;
+  enum MmPayloadKind payload_kind;
+  // The counter value (nanoseconds since the start of the profile for the
+  // wall-time counter) at which the event started. Also set for instants.
+  uint64_t start;
+  // The counter value at which the event ended. Equal to `start` for
+  // instants.
+  uint64_t end;
+  // The value of an integer event.
+  uint64_t value;
+} MmEvent;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Returns a description of the last error that occurred on the calling
+// thread, or `NULL` if there was none. The string is owned by the library and
+// stays valid until the next failing call on the same thread.
+const char *mm_last_error(void);
+
+// Creates a profiler writing to `<path_stem>.mm_profdata`. Returns `NULL` on
+// error.
+//
+// # Safety
+//
+// `path_stem` must be a valid, NUL-terminated string.
+struct MmProfiler *mm_profiler_new(const char *path_stem);
+
+// Finishes writing the profile and releases the profiler. Passing `NULL` is
+// allowed and does nothing.
+//
+// # Safety
+//
+// `profiler` must be `NULL` or have been returned by [`mm_profiler_new`], and
+// must not be used afterwards.
+void mm_profiler_free(struct MmProfiler *profiler);
+
+// Adds the string `s` to the profile's string table and returns its string
+// ID, or `0` on error.
+//
+// # Safety
+//
+// `profiler` must be a valid profiler handle and `s` a valid, NUL-terminated
+// string.
+uint64_t mm_profiler_alloc_string(const struct MmProfiler *profiler, const char *s);
+
+// Makes the virtual string ID `virtual_id` refer to the string
+// `concrete_id`. Returns `false` if `virtual_id` is out of the range
+// available for virtual string IDs.
+//
+// # Safety
+//
+// `profiler` must be a valid profiler handle.
+bool mm_profiler_map_virtual_to_concrete_string(const struct MmProfiler *profiler,
+                                                uint64_t virtual_id,
+                                                uint64_t concrete_id);
+
+// Returns an event ID made up of the string `label` and the strings in
+// `args`, which holds `num_args` string IDs. `args` may be `NULL` if
+// `num_args` is `0`.
+//
+// # Safety
+//
+// `profiler` must be a valid profiler handle and `args` must point to
+// `num_args` string IDs.
+uint64_t mm_profiler_event_id(const struct MmProfiler *profiler,
+                              uint64_t label,
+                              const uint64_t *args,
+                              size_t num_args);
+
+// Records an instant event at the current time.
+//
+// # Safety
+//
+// `profiler` must be a valid profiler handle.
+void mm_profiler_record_instant_event(const struct MmProfiler *profiler,
+                                      uint64_t event_kind,
+                                      uint64_t event_id,
+                                      uint32_t thread_id);
+
+// Records an integer event. Returns `false` if `value` is too large to be
+// stored in a profile.
+//
+// # Safety
+//
+// `profiler` must be a valid profiler handle.
+bool mm_profiler_record_integer_event(const struct MmProfiler *profiler,
+                                      uint64_t event_kind,
+                                      uint64_t event_id,
+                                      uint32_t thread_id,
+                                      uint64_t value);
+
+// Starts an interval event at the current time. The returned handle must be
+// passed to [`mm_profiler_finish_interval_event`] to record the event.
+//
+// # Safety
+//
+// `profiler` must be a valid profiler handle.
+struct MmIntervalTiming *mm_profiler_start_interval_event(const struct MmProfiler *profiler,
+                                                          uint64_t event_kind,
+                                                          uint64_t event_id,
+                                                          uint32_t thread_id);
+
+// Records the interval event started with
+// [`mm_profiler_start_interval_event`], ending at the current time, and
+// releases `timing`. Returns `false` if `timing` is `NULL`.
+//
+// # Safety
+//
+// `profiler` must be the profiler `timing` was obtained from, and `timing`
+// must not be used afterwards.
+bool mm_profiler_finish_interval_event(const struct MmProfiler *profiler,
+                                       struct MmIntervalTiming *timing);
+
+// Opens the profile at `path`, which must include the `.mm_profdata`
+// extension. Returns `NULL` on error.
+//
+// # Safety
+//
+// `path` must be a valid, NUL-terminated string.
+struct MmDecoder *mm_decoder_open(const char *path);
+
+// Releases the decoder. Passing `NULL` is allowed and does nothing.
+//
+// # Safety
+//
+// `decoder` must be `NULL` or have been returned by [`mm_decoder_open`], and
+// must not be used afterwards.
+void mm_decoder_free(struct MmDecoder *decoder);
+
+// Returns the number of events in the profile.
+//
+// # Safety
+//
+// `decoder` must be a valid decoder handle.
+size_t mm_decoder_num_events(const struct MmDecoder *decoder);
+
+// Returns the time the profile was started at, in nanoseconds since the Unix
+// epoch.
+//
+// # Safety
+//
+// `decoder` must be a valid decoder handle.
+uint64_t mm_decoder_start_time(const struct MmDecoder *decoder);
+
+// Returns the process ID of the profiled process.
+//
+// # Safety
+//
+// `decoder` must be a valid decoder handle.
+uint32_t mm_decoder_process_id(const struct MmDecoder *decoder);
+
+// Decodes the event at `event_index` into `event`. Returns `false` if there
+// is no such event, in which case `event` is left untouched.
+//
+// # Safety
+//
+// `decoder` must be a valid decoder handle and `event` must point to writable
+// memory for an `MmEvent`.
+bool mm_decoder_decode_event(const struct MmDecoder *decoder,
+                             size_t event_index,
+                             struct MmEvent *event);
+
+// Releases the strings of an event filled in by [`mm_decoder_decode_event`].
+// The event itself is not freed, and its string pointers are set to `NULL`.
+//
+// # Safety
+//
+// `event` must point to an event filled in by [`mm_decoder_decode_event`]
+// that has not been released yet.
+void mm_event_free(struct MmEvent *event);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* MEASUREME_H */
@@ -0,0 +1,496 @@
+//! A C interface for writing profiling data with `measureme` and for reading
+//! it back with `decodeme`. The corresponding header is
+//! `include/measureme.h`, which is generated from this file by `cbindgen`.
+//!
+//! All functions taking pointers expect them to be valid for the duration of
+//! the call. Handles returned by `mm_*_new`/`mm_*_open` functions must be
+//! released with the matching `mm_*_free` function. Functions that can fail
+//! return `NULL` or `false` and make a description of the error available via
+//! [`mm_last_error`].
+
+use decodeme::event_payload::CounterPayload;
+use decodeme::{EventDecoder, FILE_MAGIC_TOP_LEVEL};
+use measureme::stringtable::METADATA_STRING_ID;
+use measureme::{DetachedTiming, EventId, EventIdBuilder, Profiler, StringId, MAX_SINGLE_VALUE};
+use std::cell::RefCell;
+use std::error::Error;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::ptr;
+use std::time::UNIX_EPOCH;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: impl ToString) {
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(to_c_string(message.to_string())));
+}
+
+/// Converts `s` into a `CString`, dropping any interior NUL bytes.
+fn to_c_string(s: String) -> CString {
+    CString::new(s).unwrap_or_else(|e| {
+        let mut bytes = e.into_vec();
+        bytes.retain(|&b| b != 0);
+        CString::new(bytes).unwrap()
+    })
+}
+
+/// Turns the C string `s` into a `&str`, recording an error if it is `NULL` or
+/// not valid UTF-8.
+unsafe fn str_from_c<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        set_last_error("unexpected NULL string");
+        return None;
+    }
+
+    match CStr::from_ptr(s).to_str() {
+        Ok(s) => Some(s),
+        Err(e) => {
+            set_last_error(e);
+            None
+        }
+    }
+}
+
+/// Returns a description of the last error that occurred on the calling
+/// thread, or `NULL` if there was none. The string is owned by the library and
+/// stays valid until the next failing call on the same thread.
+#[no_mangle]
+pub extern "C" fn mm_last_error() -> *const c_char {
+    LAST_ERROR.with(|last_error| match &*last_error.borrow() {
+        Some(message) => message.as_ptr(),
+        None => ptr::null(),
+    })
+}
+
+// --- Writing profiles -------------------------------------------------------
+
+/// A handle for recording events into a profile.
+pub struct MmProfiler(Profiler);
+
+/// An interval event that has been started with
+/// [`mm_profiler_start_interval_event`] but not yet finished.
+pub struct MmIntervalTiming(DetachedTiming);
+
+/// Creates a profiler writing to `<path_stem>.mm_profdata`. Returns `NULL` on
+/// error.
+///
+/// # Safety
+///
+/// `path_stem` must be a valid, NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn mm_profiler_new(path_stem: *const c_char) -> *mut MmProfiler {
+    let path_stem = match str_from_c(path_stem) {
+        Some(path_stem) => path_stem,
+        None => return ptr::null_mut(),
+    };
+
+    match Profiler::new(path_stem) {
+        Ok(profiler) => Box::into_raw(Box::new(MmProfiler(profiler))),
+        Err(e) => {
+            set_last_error(e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Finishes writing the profile and releases the profiler. Passing `NULL` is
+/// allowed and does nothing.
+///
+/// # Safety
+///
+/// `profiler` must be `NULL` or have been returned by [`mm_profiler_new`], and
+/// must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn mm_profiler_free(profiler: *mut MmProfiler) {
+    if !profiler.is_null() {
+        drop(Box::from_raw(profiler));
+    }
+}
+
+/// Adds the string `s` to the profile's string table and returns its string
+/// ID, or `0` on error.
+///
+/// # Safety
+///
+/// `profiler` must be a valid profiler handle and `s` a valid, NUL-terminated
+/// string.
+#[no_mangle]
+pub unsafe extern "C" fn mm_profiler_alloc_string(
+    profiler: *const MmProfiler,
+    s: *const c_char,
+) -> u64 {
+    match str_from_c(s) {
+        Some(s) => (*profiler).0.alloc_string(s).as_u64(),
+        None => 0,
+    }
+}
+
+/// Makes the virtual string ID `virtual_id` refer to the string
+/// `concrete_id`. Returns `false` if `virtual_id` is out of the range
+/// available for virtual string IDs.
+///
+/// # Safety
+///
+/// `profiler` must be a valid profiler handle.
+#[no_mangle]
+pub unsafe extern "C" fn mm_profiler_map_virtual_to_concrete_string(
+    profiler: *const MmProfiler,
+    virtual_id: u64,
+    concrete_id: u64,
+) -> bool {
+    if virtual_id >= METADATA_STRING_ID {
+        set_last_error(format!("invalid virtual string id {}", virtual_id));
+        return false;
+    }
+
+    (*profiler).0.map_virtual_to_concrete_string(
+        StringId::new_virtual(virtual_id),
+        StringId::new(concrete_id),
+    );
+
+    true
+}
+
+/// Returns an event ID made up of the string `label` and the strings in
+/// `args`, which holds `num_args` string IDs. `args` may be `NULL` if
+/// `num_args` is `0`.
+///
+/// # Safety
+///
+/// `profiler` must be a valid profiler handle and `args` must point to
+/// `num_args` string IDs.
+#[no_mangle]
+pub unsafe extern "C" fn mm_profiler_event_id(
+    profiler: *const MmProfiler,
+    label: u64,
+    args: *const u64,
+    num_args: usize,
+) -> u64 {
+    let label = StringId::new(label);
+
+    if num_args == 0 {
+        return EventId::from_label(label).as_u64();
+    }
+
+    let args: Vec<_> = std::slice::from_raw_parts(args, num_args)
+        .iter()
+        .map(|&arg| StringId::new(arg))
+        .collect();
+
+    EventIdBuilder::new(&(*profiler).0)
+        .from_label_and_args(label, &args)
+        .as_u64()
+}
+
+/// Records an instant event at the current time.
+///
+/// # Safety
+///
+/// `profiler` must be a valid profiler handle.
+#[no_mangle]
+pub unsafe extern "C" fn mm_profiler_record_instant_event(
+    profiler: *const MmProfiler,
+    event_kind: u64,
+    event_id: u64,
+    thread_id: u32,
+) {
+    (*profiler).0.record_instant_event(
+        StringId::new(event_kind),
+        EventId::from_u64(event_id),
+        thread_id,
+    );
+}
+
+/// Records an integer event. Returns `false` if `value` is too large to be
+/// stored in a profile.
+///
+/// # Safety
+///
+/// `profiler` must be a valid profiler handle.
+#[no_mangle]
+pub unsafe extern "C" fn mm_profiler_record_integer_event(
+    profiler: *const MmProfiler,
+    event_kind: u64,
+    event_id: u64,
+    thread_id: u32,
+    value: u64,
+) -> bool {
+    if value > MAX_SINGLE_VALUE {
+        set_last_error(format!(
+            "integer value {} exceeds the maximum of {}",
+            value, MAX_SINGLE_VALUE
+        ));
+        return false;
+    }
+
+    (*profiler).0.record_integer_event(
+        StringId::new(event_kind),
+        EventId::from_u64(event_id),
+        thread_id,
+        value,
+    );
+
+    true
+}
+
+/// Starts an interval event at the current time. The returned handle must be
+/// passed to [`mm_profiler_finish_interval_event`] to record the event.
+///
+/// # Safety
+///
+/// `profiler` must be a valid profiler handle.
+#[no_mangle]
+pub unsafe extern "C" fn mm_profiler_start_interval_event(
+    profiler: *const MmProfiler,
+    event_kind: u64,
+    event_id: u64,
+    thread_id: u32,
+) -> *mut MmIntervalTiming {
+    let timing = (*profiler).0.start_recording_interval_event_detached(
+        StringId::new(event_kind),
+        EventId::from_u64(event_id),
+        thread_id,
+    );
+
+    Box::into_raw(Box::new(MmIntervalTiming(timing)))
+}
+
+/// Records the interval event started with
+/// [`mm_profiler_start_interval_event`], ending at the current time, and
+/// releases `timing`. Returns `false` if `timing` is `NULL`.
+///
+/// # Safety
+///
+/// `profiler` must be the profiler `timing` was obtained from, and `timing`
+/// must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn mm_profiler_finish_interval_event(
+    profiler: *const MmProfiler,
+    timing: *mut MmIntervalTiming,
+) -> bool {
+    if timing.is_null() {
+        set_last_error("unexpected NULL interval timing");
+        return false;
+    }
+
+    let timing = Box::from_raw(timing);
+    (*profiler).0.finish_recording_interval_event(timing.0);
+
+    true
+}
+
+// --- Reading profiles -------------------------------------------------------
+
+/// A handle for reading the events of a profile.
+pub struct MmDecoder(EventDecoder);
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MmPayloadKind {
+    /// An event with a start and an end.
+    Interval,
+    /// An event at a single point in time.
+    Instant,
+    /// An event carrying an integer value instead of a timestamp.
+    Integer,
+}
+
+/// A decoded event. The strings are owned by the event and must be released
+/// with [`mm_event_free`].
+#[repr(C)]
+pub struct MmEvent {
+    pub event_kind: *mut c_char,
+    pub label: *mut c_char,
+    /// An array of `num_additional_data` strings.
+    pub additional_data: *mut *mut c_char,
+    pub num_additional_data: usize,
+    pub thread_id: u32,
+    pub payload_kind: MmPayloadKind,
+    /// The counter value (nanoseconds since the start of the profile for the
+    /// wall-time counter) at which the event started. Also set for instants.
+    pub start: u64,
+    /// The counter value at which the event ended. Equal to `start` for
+    /// instants.
+    pub end: u64,
+    /// The value of an integer event.
+    pub value: u64,
+}
+
+/// Opens the profile at `path`, which must include the `.mm_profdata`
+/// extension. Returns `NULL` on error.
+///
+/// # Safety
+///
+/// `path` must be a valid, NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn mm_decoder_open(path: *const c_char) -> *mut MmDecoder {
+    let path = match str_from_c(path) {
+        Some(path) => path,
+        None => return ptr::null_mut(),
+    };
+
+    match std::fs::read(path)
+        .map_err(|e| Box::new(e) as Box<dyn Error>)
+        .and_then(|data| new_decoder(data, Some(path.as_ref())))
+    {
+        Ok(decoder) => Box::into_raw(Box::new(MmDecoder(decoder))),
+        Err(e) => {
+            set_last_error(e);
+            ptr::null_mut()
+        }
+    }
+}
+
+fn new_decoder(
+    data: Vec<u8>,
+    path: Option<&std::path::Path>,
+) -> Result<EventDecoder, Box<dyn Error>> {
+    let file_format_version =
+        decodeme::read_file_header(&data, FILE_MAGIC_TOP_LEVEL, path, "top-level")
+            .map_err(|e| e.to_string())?;
+
+    if file_format_version != decodeme::CURRENT_FILE_FORMAT_VERSION {
+        return Err(format!("unsupported file format version {}", file_format_version).into());
+    }
+
+    Ok(EventDecoder::new(data, path)?)
+}
+
+/// Releases the decoder. Passing `NULL` is allowed and does nothing.
+///
+/// # Safety
+///
+/// `decoder` must be `NULL` or have been returned by [`mm_decoder_open`], and
+/// must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn mm_decoder_free(decoder: *mut MmDecoder) {
+    if !decoder.is_null() {
+        drop(Box::from_raw(decoder));
+    }
+}
+
+/// Returns the number of events in the profile.
+///
+/// # Safety
+///
+/// `decoder` must be a valid decoder handle.
+#[no_mangle]
+pub unsafe extern "C" fn mm_decoder_num_events(decoder: *const MmDecoder) -> usize {
+    (*decoder).0.num_events()
+}
+
+/// Returns the time the profile was started at, in nanoseconds since the Unix
+/// epoch.
+///
+/// # Safety
+///
+/// `decoder` must be a valid decoder handle.
+#[no_mangle]
+pub unsafe extern "C" fn mm_decoder_start_time(decoder: *const MmDecoder) -> u64 {
+    let start_time = (*decoder).0.metadata().start_time;
+    start_time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+/// Returns the process ID of the profiled process.
+///
+/// # Safety
+///
+/// `decoder` must be a valid decoder handle.
+#[no_mangle]
+pub unsafe extern "C" fn mm_decoder_process_id(decoder: *const MmDecoder) -> u32 {
+    (*decoder).0.metadata().process_id
+}
+
+/// Decodes the event at `event_index` into `event`. Returns `false` if there
+/// is no such event, in which case `event` is left untouched.
+///
+/// # Safety
+///
+/// `decoder` must be a valid decoder handle and `event` must point to writable
+/// memory for an `MmEvent`.
+#[no_mangle]
+pub unsafe extern "C" fn mm_decoder_decode_event(
+    decoder: *const MmDecoder,
+    event_index: usize,
+    event: *mut MmEvent,
+) -> bool {
+    let decoder = &(*decoder).0;
+
+    if event_index >= decoder.num_events() {
+        set_last_error(format!(
+            "event index {} out of range, the profile has {} events",
+            event_index,
+            decoder.num_events()
+        ));
+        return false;
+    }
+
+    let full_event = decoder.decode_full_event(event_index);
+
+    let (payload_kind, start, end, value) =
+        match decoder.decode_counter_payload(event_index).payload {
+            CounterPayload::Interval { start, end } => (MmPayloadKind::Interval, start, end, 0),
+            CounterPayload::Instant(t) => (MmPayloadKind::Instant, t, t, 0),
+            CounterPayload::Integer(value) => (MmPayloadKind::Integer, 0, 0, value),
+        };
+
+    let additional_data: Box<[*mut c_char]> = full_event
+        .additional_data
+        .iter()
+        .map(|arg| to_c_string(arg.to_string()).into_raw())
+        .collect();
+    let num_additional_data = additional_data.len();
+
+    event.write(MmEvent {
+        event_kind: to_c_string(full_event.event_kind.into_owned()).into_raw(),
+        label: to_c_string(full_event.label.into_owned()).into_raw(),
+        additional_data: Box::into_raw(additional_data) as *mut *mut c_char,
+        num_additional_data,
+        thread_id: full_event.thread_id,
+        payload_kind,
+        start,
+        end,
+        value,
+    });
+
+    true
+}
+
+/// Releases the strings of an event filled in by [`mm_decoder_decode_event`].
+/// The event itself is not freed, and its string pointers are set to `NULL`.
+///
+/// # Safety
+///
+/// `event` must point to an event filled in by [`mm_decoder_decode_event`]
+/// that has not been released yet.
+#[no_mangle]
+pub unsafe extern "C" fn mm_event_free(event: *mut MmEvent) {
+    let event = &mut *event;
+
+    if !event.additional_data.is_null() {
+        let additional_data = Box::from_raw(ptr::slice_from_raw_parts_mut(
+            event.additional_data,
+            event.num_additional_data,
+        ));
+
+        for &arg in additional_data.iter() {
+            drop(CString::from_raw(arg));
+        }
+    }
+
+    for s in [event.event_kind, event.label].iter() {
+        if !s.is_null() {
+            drop(CString::from_raw(*s));
+        }
+    }
+
+    event.event_kind = ptr::null_mut();
+    event.label = ptr::null_mut();
+    event.additional_data = ptr::null_mut();
+    event.num_additional_data = 0;
+}
@@ -0,0 +1,106 @@
+/*
+ * Writes a profile through the C interface, reads it back and checks the
+ * decoded events. Expects the path stem of the profile as its only argument.
+ */
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "measureme.h"
+
+#define CHECK(cond)                                                            \
+    do {                                                                       \
+        if (!(cond)) {                                                         \
+            const char *error = mm_last_error();                               \
+            fprintf(stderr, "%s:%d: check failed: %s (last error: %s)\n",      \
+                    __FILE__, __LINE__, #cond, error ? error : "none");        \
+            exit(1);                                                           \
+        }                                                                      \
+    } while (0)
+
+static void write_profile(const char *path_stem) {
+    MmProfiler *profiler = mm_profiler_new(path_stem);
+    CHECK(profiler != NULL);
+
+    uint64_t kind = mm_profiler_alloc_string(profiler, "Query");
+    uint64_t label = mm_profiler_alloc_string(profiler, "typeck");
+    uint64_t arg = mm_profiler_alloc_string(profiler, "foo::bar");
+    uint64_t with_arg = mm_profiler_event_id(profiler, label, &arg, 1);
+
+    MmIntervalTiming *timing =
+        mm_profiler_start_interval_event(profiler, kind, with_arg, 7);
+    mm_profiler_record_instant_event(profiler, kind, label, 7);
+    CHECK(mm_profiler_finish_interval_event(profiler, timing));
+
+    CHECK(!mm_profiler_finish_interval_event(profiler, NULL));
+    CHECK(strcmp(mm_last_error(), "unexpected NULL interval timing") == 0);
+
+    CHECK(mm_profiler_record_integer_event(profiler, kind, label, 8, 1234));
+    CHECK(!mm_profiler_record_integer_event(profiler, kind, label, 8, UINT64_MAX));
+    CHECK(mm_last_error() != NULL);
+
+    CHECK(mm_profiler_map_virtual_to_concrete_string(profiler, 42, label));
+    mm_profiler_record_instant_event(profiler, kind, 42, 8);
+
+    mm_profiler_free(profiler);
+}
+
+static void read_profile(const char *path) {
+    MmDecoder *decoder = mm_decoder_open(path);
+    CHECK(decoder != NULL);
+    CHECK(mm_decoder_num_events(decoder) == 4);
+    CHECK(mm_decoder_start_time(decoder) > 0);
+
+    MmEvent event;
+
+    CHECK(mm_decoder_decode_event(decoder, 0, &event));
+    CHECK(strcmp(event.event_kind, "Query") == 0);
+    CHECK(strcmp(event.label, "typeck") == 0);
+    CHECK(event.num_additional_data == 0);
+    CHECK(event.payload_kind == MmPayloadKind_Instant);
+    CHECK(event.thread_id == 7);
+    uint64_t instant = event.start;
+    mm_event_free(&event);
+
+    CHECK(mm_decoder_decode_event(decoder, 1, &event));
+    CHECK(strcmp(event.label, "typeck") == 0);
+    CHECK(event.num_additional_data == 1);
+    CHECK(strcmp(event.additional_data[0], "foo::bar") == 0);
+    CHECK(event.payload_kind == MmPayloadKind_Interval);
+    CHECK(event.start <= instant && instant <= event.end);
+    mm_event_free(&event);
+
+    CHECK(mm_decoder_decode_event(decoder, 2, &event));
+    CHECK(event.payload_kind == MmPayloadKind_Integer);
+    CHECK(event.value == 1234);
+    CHECK(event.thread_id == 8);
+    mm_event_free(&event);
+
+    CHECK(mm_decoder_decode_event(decoder, 3, &event));
+    CHECK(strcmp(event.label, "typeck") == 0);
+    mm_event_free(&event);
+
+    CHECK(!mm_decoder_decode_event(decoder, 4, &event));
+
+    mm_decoder_free(decoder);
+}
+
+int main(int argc, char **argv) {
+    if (argc != 2) {
+        fprintf(stderr, "usage: %s <path stem>\n", argv[0]);
+        return 2;
+    }
+
+    char path[4096];
+    snprintf(path, sizeof(path), "%s.mm_profdata", argv[1]);
+
+    write_profile(argv[1]);
+    read_profile(path);
+
+    CHECK(mm_decoder_open("does/not/exist.mm_profdata") == NULL);
+    CHECK(mm_last_error() != NULL);
+
+    printf("ok\n");
+    return 0;
+}
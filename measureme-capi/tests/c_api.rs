@@ -0,0 +1,77 @@
+//! Compiles the C test program in `tests/c` against the shared library and the
+//! generated header, and runs it. The test is skipped if no C compiler is
+//! available (set `CC` to choose one).
+
+#![cfg(unix)]
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The directory containing the shared library built for this test run.
+fn library_dir() -> PathBuf {
+    // Test executables live in `target/<profile>/deps`, next to the library.
+    let deps_dir = env::current_exe().unwrap().parent().unwrap().to_path_buf();
+    let library_name = format!(
+        "{}measureme_capi{}",
+        env::consts::DLL_PREFIX,
+        env::consts::DLL_SUFFIX
+    );
+
+    [deps_dir.clone(), deps_dir.parent().unwrap().to_path_buf()]
+        .iter()
+        .find(|dir| dir.join(&library_name).exists())
+        .unwrap_or_else(|| {
+            panic!(
+                "could not find {} near {}",
+                library_name,
+                deps_dir.display()
+            )
+        })
+        .clone()
+}
+
+#[test]
+fn c_roundtrip() {
+    let crate_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let out_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("c_api");
+    std::fs::create_dir_all(&out_dir).unwrap();
+
+    let library_dir = library_dir();
+    let executable = out_dir.join("roundtrip");
+    let compiler = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+
+    let compile = Command::new(&compiler)
+        .arg("-Wall")
+        .arg("-Werror")
+        .arg("-I")
+        .arg(crate_dir.join("include"))
+        .arg(crate_dir.join("tests/c/roundtrip.c"))
+        .arg("-o")
+        .arg(&executable)
+        .arg("-L")
+        .arg(&library_dir)
+        .arg(format!("-Wl,-rpath,{}", library_dir.display()))
+        .arg("-lmeasureme_capi")
+        .status();
+
+    match compile {
+        Ok(status) => assert!(status.success(), "compiling the C test program failed"),
+        Err(e) => {
+            eprintln!("skipping C test, could not run `{}`: {}", compiler, e);
+            return;
+        }
+    }
+
+    let output = Command::new(&executable)
+        .arg(out_dir.join("profile"))
+        .output()
+        .unwrap();
+
+    assert!(
+        output.status.success(),
+        "C test program failed:\n{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "ok\n");
+}
@@ -50,6 +50,6 @@ pub use crate::event_id::{EventId, EventIdBuilder};
 pub use crate::profiler::{DetachedTiming, Profiler, TimingGuard};
 pub use crate::raw_event::{RawEvent, MAX_INTERVAL_VALUE, MAX_SINGLE_VALUE};
 pub use crate::serialization::{
-    split_streams, Addr, PageTag, SerializationSink, SerializationSinkBuilder,
+    read_page, split_streams, Addr, PageError, PageTag, SerializationSink, SerializationSinkBuilder,
 };
 pub use crate::stringtable::{SerializableString, StringComponent, StringId, StringTableBuilder};
@@ -9,6 +9,11 @@ use std::fs;
 use std::path::Path;
 use std::sync::Arc;
 
+/// The number of events `measure_recording_overhead` records per round.
+const CALIBRATION_EVENTS: u64 = 1000;
+/// The number of rounds `measure_recording_overhead` measures.
+const CALIBRATION_ROUNDS: usize = 5;
+
 pub struct Profiler {
     event_sink: Arc<SerializationSink>,
     string_table: StringTableBuilder,
@@ -52,6 +57,8 @@ impl Profiler {
             counter,
         };
 
+        let recording_overhead = measure_recording_overhead(&profiler.counter);
+
         let mut args = String::new();
         for arg in std::env::args() {
             args.push_str(&arg.escape_default().to_string());
@@ -59,7 +66,7 @@ impl Profiler {
         }
 
         profiler.string_table.alloc_metadata(&*format!(
-            r#"{{ "start_time": {}, "process_id": {}, "cmd": "{}", "counter": {} }}"#,
+            r#"{{ "start_time": {}, "process_id": {}, "cmd": "{}", "counter": {}, "recording_overhead": {} }}"#,
             std::time::SystemTime::now()
                 .duration_since(std::time::UNIX_EPOCH)
                 .unwrap()
@@ -67,6 +74,7 @@ impl Profiler {
             std::process::id(),
             args,
             profiler.counter.describe_as_json(),
+            recording_overhead,
         ));
 
         Ok(profiler)
@@ -179,6 +187,35 @@ impl Profiler {
     }
 }
 
+/// Estimates how much recording a single interval event costs, in units of
+/// `counter`, by recording events the same way `TimingGuard` does, just into a
+/// throwaway in-memory sink. Only the fastest round counts, since the slower
+/// ones were most likely disturbed by something else.
+fn measure_recording_overhead(counter: &Counter) -> u64 {
+    let sink = SerializationSinkBuilder::new_in_memory().new_sink(PageTag::Events);
+    let event_kind = StringId::new_virtual(0u64);
+    let event_id = EventId::from_virtual(StringId::new_virtual(0u64));
+
+    (0..CALIBRATION_ROUNDS)
+        .map(|_| {
+            let round_start = counter.since_start();
+
+            for _ in 0..CALIBRATION_EVENTS {
+                let start = counter.since_start();
+                let raw_event =
+                    RawEvent::new_interval(event_kind, event_id, 0, start, counter.since_start());
+
+                sink.write_atomic(std::mem::size_of::<RawEvent>(), |bytes| {
+                    raw_event.serialize(bytes);
+                });
+            }
+
+            counter.since_start().saturating_sub(round_start) / CALIBRATION_EVENTS
+        })
+        .min()
+        .unwrap()
+}
+
 /// Created by `Profiler::start_recording_interval_event_detached`.
 /// Must be passed to `finish_recording_interval_event` to record an
 /// "end" event.
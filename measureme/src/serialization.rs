@@ -189,10 +189,102 @@ impl SharedState {
             BackingStorage::Memory(ref data) => data,
         };
 
-        split_streams(data).remove(&page_tag).unwrap_or(Vec::new())
+        split_streams(data)
+            .unwrap()
+            .remove(&page_tag)
+            .unwrap_or(Vec::new())
     }
 }
 
+/// The error returned when paged data cannot be split into its streams. All
+/// offsets are relative to the start of the paged data, i.e. they do not
+/// include any file header preceding it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PageError {
+    /// The page at `offset` starts with a byte that is not a valid `PageTag`.
+    InvalidTag { offset: usize, tag: u8 },
+    /// The page at `offset` declares a size of zero bytes, which is something
+    /// we never write.
+    EmptyPage { offset: usize },
+    /// The page at `offset` needs `needed` bytes (including its header) but
+    /// only `available` bytes are left in the data.
+    Truncated {
+        offset: usize,
+        needed: usize,
+        available: usize,
+    },
+}
+
+impl PageError {
+    /// The offset of the page that could not be read.
+    pub fn offset(&self) -> usize {
+        match *self {
+            PageError::InvalidTag { offset, .. }
+            | PageError::EmptyPage { offset }
+            | PageError::Truncated { offset, .. } => offset,
+        }
+    }
+}
+
+impl std::fmt::Display for PageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            PageError::InvalidTag { offset, tag } => {
+                write!(f, "Invalid page tag `{}` at offset {}", tag, offset)
+            }
+            PageError::EmptyPage { offset } => write!(f, "Empty page at offset {}", offset),
+            PageError::Truncated {
+                offset,
+                needed,
+                available,
+            } => write!(
+                f,
+                "Page at offset {} needs {} bytes but only {} bytes are left",
+                offset, needed, available
+            ),
+        }
+    }
+}
+
+impl Error for PageError {}
+
+/// The size of a page header, i.e. one byte for the tag followed by the page
+/// size as a little endian u32.
+const PAGE_HEADER_SIZE: usize = 5;
+
+/// Reads the page starting at `pos` in `paged_data` and returns its tag and
+/// contents. The next page (if any) starts at `pos + 5 + contents.len()`.
+pub fn read_page(paged_data: &[u8], pos: usize) -> Result<(PageTag, &[u8]), PageError> {
+    let available = paged_data.len().saturating_sub(pos);
+
+    if available < PAGE_HEADER_SIZE {
+        return Err(PageError::Truncated {
+            offset: pos,
+            needed: PAGE_HEADER_SIZE,
+            available,
+        });
+    }
+
+    let tag = paged_data[pos];
+    let tag = TryInto::try_into(tag).map_err(|_| PageError::InvalidTag { offset: pos, tag })?;
+    let page_size = u32::from_le_bytes(paged_data[pos + 1..pos + 5].try_into().unwrap()) as usize;
+
+    if page_size == 0 {
+        return Err(PageError::EmptyPage { offset: pos });
+    }
+
+    let needed = PAGE_HEADER_SIZE + page_size;
+    if available < needed {
+        return Err(PageError::Truncated {
+            offset: pos,
+            needed,
+            available,
+        });
+    }
+
+    Ok((tag, &paged_data[pos + PAGE_HEADER_SIZE..pos + needed]))
+}
+
 /// This function reconstructs the individual data streams from their paged
 /// version.
 ///
@@ -212,26 +304,21 @@ impl SharedState {
 ///     string_data: [opqrst],
 /// }
 /// ```
-pub fn split_streams(paged_data: &[u8]) -> FxHashMap<PageTag, Vec<u8>> {
+///
+/// An error is returned if any of the pages is malformed.
+pub fn split_streams(paged_data: &[u8]) -> Result<FxHashMap<PageTag, Vec<u8>>, PageError> {
     let mut result: FxHashMap<PageTag, Vec<u8>> = FxHashMap::default();
 
     let mut pos = 0;
     while pos < paged_data.len() {
-        let tag = TryInto::try_into(paged_data[pos]).unwrap();
-        let page_size =
-            u32::from_le_bytes(paged_data[pos + 1..pos + 5].try_into().unwrap()) as usize;
+        let (tag, contents) = read_page(paged_data, pos)?;
 
-        assert!(page_size > 0);
+        result.entry(tag).or_default().extend_from_slice(contents);
 
-        result
-            .entry(tag)
-            .or_default()
-            .extend_from_slice(&paged_data[pos + 5..pos + 5 + page_size]);
-
-        pos += page_size + 5;
+        pos += PAGE_HEADER_SIZE + contents.len();
     }
 
-    result
+    Ok(result)
 }
 
 impl SerializationSink {
@@ -495,4 +582,45 @@ mod tests {
     mk_roundtrip_test!(exactly_min_page_size, MIN_PAGE_SIZE, 10);
     mk_roundtrip_test!(min_page_size_plus_one, MIN_PAGE_SIZE + 1, 10);
     mk_roundtrip_test!(min_page_size_minus_one, MIN_PAGE_SIZE - 1, 10);
+
+    #[test]
+    fn split_streams_invalid_pages() {
+        // A valid events page with three bytes of content.
+        let valid_page = [0u8, 3, 0, 0, 0, 1, 2, 3];
+
+        assert_eq!(
+            split_streams(&[7, 1, 0, 0, 0, 42]),
+            Err(PageError::InvalidTag { offset: 0, tag: 7 })
+        );
+
+        let mut empty_page = valid_page.to_vec();
+        empty_page.extend_from_slice(&[1, 0, 0, 0, 0]);
+        assert_eq!(
+            split_streams(&empty_page),
+            Err(PageError::EmptyPage { offset: 8 })
+        );
+
+        let mut truncated_header = valid_page.to_vec();
+        truncated_header.extend_from_slice(&[2, 1]);
+        assert_eq!(
+            split_streams(&truncated_header),
+            Err(PageError::Truncated {
+                offset: 8,
+                needed: 5,
+                available: 2
+            })
+        );
+
+        assert_eq!(
+            split_streams(&valid_page[..7]),
+            Err(PageError::Truncated {
+                offset: 0,
+                needed: 8,
+                available: 7
+            })
+        );
+
+        let streams = split_streams(&valid_page).unwrap();
+        assert_eq!(streams[&PageTag::Events], vec![1, 2, 3]);
+    }
 }
@@ -0,0 +1,43 @@
+use analyzeme::{ProblemKind, ProfilingData};
+use clap::Parser;
+use std::error::Error;
+use std::path::PathBuf;
+use std::process;
+
+#[derive(Parser, Debug)]
+struct Opt {
+    file_prefix: PathBuf,
+}
+
+fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    let opt = Opt::parse();
+
+    let data = ProfilingData::new(&opt.file_prefix)?;
+    let problems = data.validate();
+
+    for problem in &problems {
+        let event = data.to_full_event(&data.iter().nth(problem.event_index).unwrap());
+
+        match problem.kind {
+            ProblemKind::OverlappingIntervals { .. } => println!(
+                "{} ({} `{}` on thread {})",
+                problem, event.event_kind, event.label, event.thread_id
+            ),
+            // The strings of the event are what is broken, so there is no
+            // point in printing them.
+            _ => println!("{} (on thread {})", problem, event.thread_id),
+        }
+    }
+
+    if problems.is_empty() {
+        println!("No problems found in {} events.", data.num_events());
+        Ok(())
+    } else {
+        eprintln!(
+            "Found {} problem(s) in {} events.",
+            problems.len(),
+            data.num_events()
+        );
+        process::exit(1);
+    }
+}
@@ -1,6 +1,7 @@
-use std::{convert::TryInto, error::Error, path::PathBuf};
+use std::{error::Error, path::PathBuf};
 
 use decodeme::{read_file_header, PageTag, FILE_HEADER_SIZE, FILE_MAGIC_TOP_LEVEL};
+use measureme::read_page;
 
 use clap::Parser;
 
@@ -31,13 +32,9 @@ fn truncate(file_contents: &[u8]) -> Result<Vec<u8>, Box<dyn Error + Send + Sync
     while pos < paged_data.len() {
         let page_start = pos;
 
-        let tag = TryInto::try_into(paged_data[pos]).unwrap();
-        let page_size =
-            u32::from_le_bytes(paged_data[pos + 1..pos + 5].try_into().unwrap()) as usize;
+        let (tag, page_contents) = read_page(paged_data, pos)?;
 
-        assert!(page_size > 0);
-
-        let page_end = page_start + 5 + page_size;
+        let page_end = page_start + 5 + page_contents.len();
         let page_bytes = &paged_data[page_start..page_end];
 
         match tag {
@@ -1,4 +1,4 @@
-use analyzeme::{Event, EventPayload, ProfilingData, Timestamp};
+use analyzeme::{Event, EventPayload, FilterArgs, ProfilingData, Timestamp};
 use clap::Parser;
 use std::error::Error;
 use std::path::PathBuf;
@@ -8,9 +8,8 @@ use std::time::{Duration, SystemTime};
 struct Opt {
     file_prefix: PathBuf,
 
-    /// Filter to events which occured on the specified thread id
-    #[arg(short = 't', long = "thread-id")]
-    thread_id: Option<u32>,
+    #[command(flatten)]
+    filter: FilterArgs,
 }
 
 fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
@@ -19,12 +18,9 @@ fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
     let data = ProfilingData::new(&opt.file_prefix)?;
 
     if let Some(global_start_time) = data.iter().filter_map(|e| e.start()).min() {
+        let data = opt.filter.apply(data)?;
+
         for event in data.iter() {
-            if let Some(thread_id) = opt.thread_id {
-                if event.thread_id != thread_id {
-                    continue;
-                }
-            }
             print_event(&data.to_full_event(&event), global_start_time);
         }
     } else {
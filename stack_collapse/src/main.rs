@@ -3,18 +3,29 @@ use std::fs::File;
 use std::io::{BufWriter, Write};
 use std::path::PathBuf;
 
-use analyzeme::{collapse_stacks, ProfilingData};
+use analyzeme::{collapse_stacks, FilterArgs, ProfilingData};
 use clap::Parser;
 
 #[derive(Parser, Debug)]
 struct Opt {
-    file_prefix: PathBuf,
+    /// The profiles to analyze. Profiles of several processes, e.g. of all
+    /// rustc invocations of a build, are merged into one.
+    #[arg(required = true)]
+    file_prefix: Vec<PathBuf>,
+
+    #[command(flatten)]
+    filter: FilterArgs,
 }
 
 fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
     let opt = Opt::parse();
 
-    let profiling_data = ProfilingData::new(&opt.file_prefix)?;
+    let profiles = opt
+        .file_prefix
+        .iter()
+        .map(|file_prefix| ProfilingData::new(file_prefix))
+        .collect::<Result<Vec<_>, _>>()?;
+    let profiling_data = opt.filter.apply(ProfilingData::merge(profiles)?)?;
 
     let recorded_stacks = collapse_stacks(&profiling_data);
 
@@ -473,7 +473,7 @@ impl<T: Copy + Ord, S: Clone> Extrema<T, S> {
     }
 }
 
-pub fn aggregate_profiles(profiles: Vec<ProfilingData>) {
+pub fn aggregate_profiles(profiles: &[ProfilingData]) {
     let aggregated_sample_intervals = AggregatedSampleIntervals::new(
         profiles
             .iter()
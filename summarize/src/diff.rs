@@ -1,4 +1,4 @@
-use analyzeme::{AnalysisResults, ArtifactSize, QueryData};
+use analyzeme::{welch_t_test, AnalysisResults, ArtifactSize, Counter, QueryData};
 use rustc_hash::{FxHashMap, FxHashSet};
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
@@ -11,12 +11,23 @@ pub struct DiffResults {
     pub query_data: Vec<QueryDataDiff>,
     pub artifact_sizes: Vec<ArtifactSizeDiff>,
     pub total_time: SignedDuration,
+    #[serde(default)]
+    pub total_time_change: f64,
+    /// The p-value of the change in total time, if both sides consist of
+    /// several profiles.
+    #[serde(default)]
+    pub total_time_p_value: Option<f64>,
+    /// Whether `total_time_p_value` is below the significance level.
+    #[serde(default)]
+    pub total_time_significant: Option<bool>,
+    #[serde(default)]
+    pub counter: Counter,
 }
 
-fn build_query_lookup(query_data: &[QueryData]) -> FxHashMap<&str, usize> {
+fn build_query_lookup(query_data: &[QueryData]) -> FxHashMap<(&str, &str), usize> {
     let mut lookup = FxHashMap::with_capacity_and_hasher(query_data.len(), Default::default());
     for (i, data) in query_data.iter().enumerate() {
-        lookup.insert(&data.label[..], i);
+        lookup.insert((&data.event_kind[..], &data.label[..]), i);
     }
 
     lookup
@@ -45,7 +56,7 @@ pub fn calculate_diff(base: AnalysisResults, change: AnalysisResults) -> DiffRes
         Default::default(),
     );
     for query_data in base.query_data.iter().chain(&change.query_data) {
-        all_labels.insert(&query_data.label[..]);
+        all_labels.insert((&query_data.event_kind[..], &query_data.label[..]));
     }
 
     let mut query_data: Vec<_> = all_labels
@@ -93,13 +104,82 @@ pub fn calculate_diff(base: AnalysisResults, change: AnalysisResults) -> DiffRes
         query_data,
         artifact_sizes,
         total_time: sd(change.total_time) - sd(base.total_time),
+        total_time_change: percentage_change(base.total_time, change.total_time),
+        total_time_p_value: None,
+        total_time_significant: None,
+        counter: change.counter,
+    }
+}
+
+/// Compares the mean results of two groups of profiles of the same workload.
+/// If both groups contain several profiles, each change in self-time is
+/// tested with Welch's t-test and counts as significant if its p-value is
+/// below `significance_level`.
+pub fn calculate_group_diff(
+    base: &[AnalysisResults],
+    change: &[AnalysisResults],
+    significance_level: f64,
+) -> DiffResults {
+    let mut results = calculate_diff(AnalysisResults::mean(base), AnalysisResults::mean(change));
+
+    let base_by_label: Vec<_> = base.iter().map(query_data_by_label).collect();
+    let change_by_label: Vec<_> = change.iter().map(query_data_by_label).collect();
+
+    let self_times = |group: &[FxHashMap<(&str, &str), &QueryData>], diff: &QueryDataDiff| {
+        group
+            .iter()
+            .map(|by_label| {
+                by_label
+                    .get(&(&diff.event_kind[..], &diff.label[..]))
+                    .map_or(Duration::ZERO, |data| data.self_time)
+            })
+            .collect::<Vec<_>>()
+    };
+
+    for query_data in &mut results.query_data {
+        query_data.self_time_p_value = welch_t_test(
+            &self_times(&base_by_label, query_data),
+            &self_times(&change_by_label, query_data),
+        );
+        query_data.significant = query_data
+            .self_time_p_value
+            .map(|p_value| p_value < significance_level);
     }
+
+    let total_times = |group: &[AnalysisResults]| {
+        group
+            .iter()
+            .map(|result| result.total_time)
+            .collect::<Vec<_>>()
+    };
+    results.total_time_p_value = welch_t_test(&total_times(base), &total_times(change));
+    results.total_time_significant = results
+        .total_time_p_value
+        .map(|p_value| p_value < significance_level);
+
+    results
+}
+
+/// The query data of a profile, by label and event kind.
+fn query_data_by_label(results: &AnalysisResults) -> FxHashMap<(&str, &str), &QueryData> {
+    results
+        .query_data
+        .iter()
+        .map(|data| ((&data.event_kind[..], &data.label[..]), data))
+        .collect()
+}
+
+fn percentage_change(base: Duration, change: Duration) -> f64 {
+    let nanos = change.as_nanos() as i128 - base.as_nanos() as i128;
+    nanos as f64 / base.as_nanos() as f64 * 100.0
 }
 
 /// The diff between two `QueryData`
 #[derive(Serialize, Deserialize)]
 pub struct QueryDataDiff {
     pub label: String,
+    #[serde(default)]
+    pub event_kind: String,
     pub time: SignedDuration,
     pub time_change: f64,
     pub self_time: SignedDuration,
@@ -110,6 +190,13 @@ pub struct QueryDataDiff {
     pub blocked_time: SignedDuration,
     pub incremental_load_time: SignedDuration,
     pub incremental_hashing_time: SignedDuration,
+    /// The p-value of the change in self-time, if both sides consist of
+    /// several profiles.
+    #[serde(default)]
+    pub self_time_p_value: Option<f64>,
+    /// Whether `self_time_p_value` is below the significance level.
+    #[serde(default)]
+    pub significant: Option<bool>,
 }
 
 impl QueryDataDiff {
@@ -124,13 +211,9 @@ impl QueryDataDiff {
             u as i64
         }
 
-        fn percentage_change(base: Duration, change: Duration) -> f64 {
-            let nanos = change.as_nanos() as i128 - base.as_nanos() as i128;
-            nanos as f64 / base.as_nanos() as f64 * 100.0
-        }
-
         QueryDataDiff {
             label: lhs.label,
+            event_kind: lhs.event_kind,
             time: sd(lhs.time) - sd(rhs.time),
             time_change: percentage_change(rhs.time, lhs.time),
             self_time: sd(lhs.self_time) - sd(rhs.self_time),
@@ -142,6 +225,8 @@ impl QueryDataDiff {
             incremental_load_time: sd(lhs.incremental_load_time) - sd(rhs.incremental_load_time),
             incremental_hashing_time: sd(lhs.incremental_hashing_time)
                 - sd(rhs.incremental_hashing_time),
+            self_time_p_value: None,
+            significant: None,
         }
     }
 
@@ -155,6 +240,7 @@ impl QueryDataDiff {
 
         QueryDataDiff {
             label: data.label.clone(),
+            event_kind: data.event_kind.clone(),
             time: invert(data.time),
             time_change: -100.0,
             self_time: invert(data.self_time),
@@ -165,12 +251,15 @@ impl QueryDataDiff {
             blocked_time: invert(data.blocked_time),
             incremental_load_time: invert(data.incremental_load_time),
             incremental_hashing_time: invert(data.incremental_hashing_time),
+            self_time_p_value: None,
+            significant: None,
         }
     }
 
     pub fn query_data_as_diff(data: &QueryData) -> QueryDataDiff {
         QueryDataDiff {
             label: data.label.clone(),
+            event_kind: data.event_kind.clone(),
             time: data.time.into(),
             time_change: std::f64::INFINITY,
             self_time: data.self_time.into(),
@@ -181,6 +270,8 @@ impl QueryDataDiff {
             blocked_time: data.blocked_time.into(),
             incremental_load_time: data.incremental_load_time.into(),
             incremental_hashing_time: data.incremental_hashing_time.into(),
+            self_time_p_value: None,
+            significant: None,
         }
     }
 }
@@ -234,6 +325,16 @@ impl SignedDuration {
             is_positive,
         }
     }
+
+    /// Formats the duration as a delta of the given counter.
+    pub fn format_with(&self, counter: &Counter) -> String {
+        if counter.is_wall_time() {
+            format!("{:.2?}", self)
+        } else {
+            let sign = if self.is_positive { "+" } else { "-" };
+            format!("{}{}", sign, counter.format_duration(self.duration))
+        }
+    }
 }
 
 impl From<Duration> for SignedDuration {
@@ -278,10 +379,62 @@ impl fmt::Debug for SignedDuration {
 }
 
 #[cfg(test)]
-mod test {
-    use super::SignedDuration;
+pub(crate) mod test {
+    use super::{calculate_group_diff, SignedDuration};
+    use analyzeme::{AnalysisResults, QueryData};
     use std::time::Duration;
 
+    /// Results with the given self-times, which add up to the total time.
+    pub(crate) fn results(self_times: &[(&str, u64)]) -> AnalysisResults {
+        let query_data: Vec<_> = self_times
+            .iter()
+            .map(|&(label, self_time)| QueryData {
+                self_time: Duration::from_nanos(self_time),
+                ..QueryData::new(label.to_owned())
+            })
+            .collect();
+
+        AnalysisResults {
+            total_time: query_data.iter().map(|data| data.self_time).sum(),
+            query_data,
+            ..AnalysisResults::default()
+        }
+    }
+
+    #[test]
+    fn group_diff() {
+        let base = [
+            results(&[("noisy", 100), ("slower", 100)]),
+            results(&[("noisy", 150), ("slower", 102)]),
+            results(&[("noisy", 50), ("slower", 98)]),
+        ];
+        let change = [
+            results(&[("noisy", 130), ("slower", 120)]),
+            results(&[("noisy", 60), ("slower", 122)]),
+            results(&[("noisy", 140), ("slower", 118)]),
+        ];
+
+        let diff = calculate_group_diff(&base, &change, 0.05);
+        let by_label = |label: &str| diff.query_data.iter().find(|d| d.label == label).unwrap();
+
+        let noisy = by_label("noisy");
+        assert_eq!(noisy.self_time, SignedDuration::from_nanos(10));
+        assert_eq!(noisy.significant, Some(false));
+
+        let slower = by_label("slower");
+        assert_eq!(slower.self_time, SignedDuration::from_nanos(20));
+        assert!(slower.self_time_p_value.unwrap() < 0.01);
+        assert_eq!(slower.significant, Some(true));
+
+        assert!(diff.total_time_p_value.is_some());
+
+        // A single profile on either side cannot be tested.
+        let diff = calculate_group_diff(&base[..1], &change, 0.05);
+        assert_eq!(diff.query_data[0].self_time_p_value, None);
+        assert_eq!(diff.query_data[0].significant, None);
+        assert_eq!(diff.total_time_p_value, None);
+    }
+
     #[test]
     fn op_subtract() {
         let zero_d = Duration::from_nanos(0);
@@ -0,0 +1,246 @@
+use crate::diff::{DiffResults, SignedDuration};
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/// The maximum regressions allowed by `summarize diff` in gate mode. Each
+/// threshold is only checked if it is set. An item violates the gate if its
+/// regression exceeds any of its thresholds. Items that are new or had no
+/// time at all in the base are only checked against `max_increase_ns`, since
+/// any increase from zero is an infinite increase in percent.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Thresholds {
+    /// The maximum increase in percent.
+    pub max_percent: Option<f64>,
+    /// The maximum increase in nanoseconds, or in the unit of the counter the
+    /// profiles were recorded with.
+    pub max_increase_ns: Option<u64>,
+}
+
+/// The gate configuration, as read from the `--gate-config` file:
+///
+/// ```json
+/// {
+///     "max_percent": 5.0,
+///     "total": { "max_percent": 1.0 },
+///     "labels": { "typeck": { "max_increase_ns": 10000000 } },
+///     "allow": ["incr_comp_persist_result"]
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct GateConfig {
+    /// The maximum increase in percent of the self-time of every label and
+    /// of the total time, unless overridden below.
+    pub max_percent: Option<f64>,
+    /// The maximum increase in nanoseconds of the self-time of every label
+    /// and of the total time, unless overridden below.
+    pub max_increase_ns: Option<u64>,
+    /// Overrides of the thresholds for the total time.
+    pub total: Thresholds,
+    /// Overrides of the thresholds for the self-time of specific labels.
+    pub labels: FxHashMap<String, Thresholds>,
+    /// Labels that are too noisy to be checked at all.
+    pub allow: Vec<String>,
+    /// Substrings of labels that are not checked at all, like the items
+    /// hidden from the diff with `--exclude`.
+    pub exclude: Vec<String>,
+}
+
+/// An item whose regression exceeded its thresholds.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Violation {
+    /// The label whose self-time regressed, or `None` for the total time.
+    pub label: Option<String>,
+    pub change: SignedDuration,
+    pub change_percent: f64,
+    /// The thresholds that applied to the item.
+    pub thresholds: Thresholds,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct GateReport {
+    pub violations: Vec<Violation>,
+}
+
+impl Thresholds {
+    /// Fills in the thresholds not set in `self` from `fallback`.
+    fn or(&self, fallback: &Thresholds) -> Thresholds {
+        Thresholds {
+            max_percent: self.max_percent.or(fallback.max_percent),
+            max_increase_ns: self.max_increase_ns.or(fallback.max_increase_ns),
+        }
+    }
+
+    fn is_set(&self) -> bool {
+        self.max_percent.is_some() || self.max_increase_ns.is_some()
+    }
+
+    fn is_exceeded_by(&self, change: SignedDuration, change_percent: f64) -> bool {
+        // The change in percent is infinite or NaN if the base is zero.
+        let above_percent =
+            change_percent.is_finite() && self.max_percent.is_some_and(|max| change_percent > max);
+        let above_increase = self
+            .max_increase_ns
+            .is_some_and(|max| change.as_nanos() > max as i128);
+
+        above_percent || above_increase
+    }
+}
+
+impl GateConfig {
+    pub fn from_file(path: &Path) -> Result<GateConfig, Box<dyn Error + Send + Sync>> {
+        let reader = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    fn thresholds(&self) -> Thresholds {
+        Thresholds {
+            max_percent: self.max_percent,
+            max_increase_ns: self.max_increase_ns,
+        }
+    }
+
+    /// Whether any threshold is configured at all.
+    pub fn is_enabled(&self) -> bool {
+        self.thresholds().is_set()
+            || self.total.is_set()
+            || self.labels.values().any(Thresholds::is_set)
+    }
+
+    /// Checks the diff against the configured thresholds. If the diff has
+    /// p-values, changes that are not significant never count as violations.
+    pub fn check(&self, results: &DiffResults) -> GateReport {
+        let mut violations = Vec::new();
+        let global = self.thresholds();
+
+        for query_data in &results.query_data {
+            let excluded = self.exclude.iter().any(|e| query_data.label.contains(e));
+            if excluded
+                || self.allow.contains(&query_data.label)
+                || query_data.significant == Some(false)
+            {
+                continue;
+            }
+
+            let thresholds = match self.labels.get(&query_data.label) {
+                Some(thresholds) => thresholds.or(&global),
+                None => global.clone(),
+            };
+
+            if thresholds.is_exceeded_by(query_data.self_time, query_data.self_time_change) {
+                violations.push(Violation {
+                    label: Some(query_data.label.clone()),
+                    change: query_data.self_time,
+                    change_percent: query_data.self_time_change,
+                    thresholds,
+                });
+            }
+        }
+
+        let thresholds = self.total.or(&global);
+
+        if results.total_time_significant != Some(false)
+            && thresholds.is_exceeded_by(results.total_time, results.total_time_change)
+        {
+            violations.push(Violation {
+                label: None,
+                change: results.total_time,
+                change_percent: results.total_time_change,
+                thresholds,
+            });
+        }
+
+        GateReport { violations }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::GateConfig;
+    use crate::diff::calculate_group_diff;
+    use crate::diff::test::results;
+
+    fn violations(config: &GateConfig) -> Vec<Option<String>> {
+        let base = results(&[("a", 1000), ("b", 100), ("c", 100)]);
+        let change = results(&[("a", 1040), ("b", 120), ("c", 100)]);
+        let diff = calculate_group_diff(&[base], &[change], 0.05);
+
+        let mut labels: Vec<_> = config
+            .check(&diff)
+            .violations
+            .into_iter()
+            .map(|violation| violation.label)
+            .collect();
+        labels.sort();
+        labels
+    }
+
+    #[test]
+    fn thresholds() {
+        let config: GateConfig = serde_json::from_str(
+            r#"{
+                "max_percent": 10.0,
+                "labels": { "a": { "max_percent": 3.0 } }
+            }"#,
+        )
+        .unwrap();
+        assert!(config.is_enabled());
+
+        // The total time went up by 5%.
+        assert_eq!(
+            violations(&config),
+            vec![Some("a".to_string()), Some("b".to_string())]
+        );
+
+        let config = GateConfig {
+            max_increase_ns: Some(30),
+            allow: vec!["a".to_string()],
+            ..GateConfig::default()
+        };
+        assert_eq!(violations(&config), vec![None]);
+
+        let config = GateConfig {
+            max_increase_ns: Some(30),
+            exclude: vec!["a".to_string()],
+            ..GateConfig::default()
+        };
+        assert_eq!(violations(&config), vec![None]);
+
+        assert!(!GateConfig::default().is_enabled());
+        assert!(violations(&GateConfig::default()).is_empty());
+    }
+
+    #[test]
+    fn new_label() {
+        let base = results(&[("a", 1000), ("zero", 0)]);
+        let change = results(&[("a", 1000), ("zero", 1), ("new", 1)]);
+        let diff = calculate_group_diff(&[base], &[change], 0.05);
+
+        let config = GateConfig {
+            max_percent: Some(5.0),
+            ..GateConfig::default()
+        };
+        assert!(config.check(&diff).violations.is_empty());
+
+        let config: GateConfig = serde_json::from_str(
+            r#"{
+                "max_percent": 5.0,
+                "labels": { "new": { "max_increase_ns": 0 } }
+            }"#,
+        )
+        .unwrap();
+        let violations = config.check(&diff).violations;
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].label.as_deref(), Some("new"));
+    }
+
+    #[test]
+    fn unknown_fields() {
+        assert!(serde_json::from_str::<GateConfig>(r#"{ "max_precent": 1.0 }"#).is_err());
+    }
+}
@@ -1,23 +1,78 @@
 #[macro_use]
 extern crate prettytable;
 
-use analyzeme::AnalysisResults;
-use analyzeme::ProfilingData;
+use analyzeme::{AggregatedResults, AnalysisResults};
+use analyzeme::{
+    AnalysisOptions, FilterArgs, Histogram, InvocationOrder, LabelRules, ProfilingData, SeriesKey,
+};
 use std::error::Error;
 use std::fs::File;
 use std::io::{BufReader, BufWriter};
 use std::{path::PathBuf, time::Duration};
 
 use clap::Parser;
+use gate::GateConfig;
 use prettytable::{Cell, Row, Table};
+use rustc_hash::FxHashMap;
 use serde::Serialize;
 
 mod aggregate;
 mod diff;
+mod gate;
 
 #[derive(Parser, Debug)]
 struct AggregateOpt {
+    #[arg(required = true)]
     files: Vec<PathBuf>,
+
+    #[command(flatten)]
+    filter: FilterArgs,
+
+    /// Prints the self-time statistics of each item as json to stdout instead
+    #[arg(long = "json")]
+    json: bool,
+}
+
+#[derive(Parser, Debug)]
+struct ConcurrencyOpt {
+    /// The profiles to analyze. Profiles of several processes, e.g. of all
+    /// rustc invocations of a build, are merged into one.
+    #[arg(required = true)]
+    file_prefix: Vec<PathBuf>,
+
+    #[command(flatten)]
+    filter: FilterArgs,
+
+    /// Writes the concurrency timeline to a json file next to the first <file_prefix> instead of stdout
+    #[arg(long = "json")]
+    json: bool,
+
+    /// The number of longest serial stretches to show
+    #[arg(long = "serial-stretches", default_value = "5")]
+    serial_stretches: usize,
+
+    /// Prints the number of busy threads over time
+    #[arg(long = "timeline")]
+    timeline: bool,
+}
+
+#[derive(Parser, Debug)]
+struct CriticalPathOpt {
+    /// The profiles to analyze. Profiles of several processes, e.g. of all
+    /// rustc invocations of a build, are merged into one.
+    #[arg(required = true)]
+    file_prefix: Vec<PathBuf>,
+
+    #[command(flatten)]
+    filter: FilterArgs,
+
+    /// Writes the critical path to a json file next to the first <file_prefix> instead of stdout
+    #[arg(long = "json")]
+    json: bool,
+
+    /// Prints each segment of the critical path in chronological order
+    #[arg(long = "segments")]
+    segments: bool,
 }
 
 #[derive(Parser, Debug)]
@@ -25,6 +80,43 @@ struct DiffOpt {
     base: PathBuf,
     change: PathBuf,
 
+    /// Further profiles of the base, e.g. from repeated benchmark runs
+    #[arg(long = "base-runs", num_args = 1..)]
+    base_runs: Vec<PathBuf>,
+
+    /// Further profiles of the change, e.g. from repeated benchmark runs
+    #[arg(long = "change-runs", num_args = 1..)]
+    change_runs: Vec<PathBuf>,
+
+    /// The p-value below which a change in self-time counts as significant
+    #[arg(long = "significance-level", default_value = "0.05")]
+    significance_level: f64,
+
+    /// Fails if the self-time of any item or the total time grows by more than this percentage
+    #[arg(long = "max-regression-percent")]
+    max_regression_percent: Option<f64>,
+
+    /// Fails if the self-time of any item or the total time grows by more than this many nanoseconds
+    #[arg(long = "max-regression-ns")]
+    max_regression_ns: Option<u64>,
+
+    /// A json file with regression thresholds, including per-item overrides
+    #[arg(long = "gate-config")]
+    gate_config: Option<PathBuf>,
+
+    /// An item whose regressions never fail the gate
+    #[arg(long = "allow")]
+    allow: Vec<String>,
+
+    /// Writes the regressions that exceeded their thresholds to this json file
+    #[arg(long = "gate-report")]
+    gate_report: Option<PathBuf>,
+
+    /// A json file with rules that rewrite labels and arguments, e.g. to merge items that only differ in a hash
+    #[arg(long = "label-rules")]
+    label_rules: Option<PathBuf>,
+
+    /// Hides items whose label contains this string, and ignores their regressions in the gate
     #[arg(short = 'e', long = "exclude")]
     exclude: Vec<String>,
 
@@ -32,17 +124,174 @@ struct DiffOpt {
     json: bool,
 }
 
+#[derive(Parser, Debug)]
+struct GapsOpt {
+    /// The profiles to analyze. Profiles of several processes, e.g. of all
+    /// rustc invocations of a build, are merged into one.
+    #[arg(required = true)]
+    file_prefix: Vec<PathBuf>,
+
+    #[command(flatten)]
+    filter: FilterArgs,
+
+    /// Writes the gaps to a json file next to the first <file_prefix> instead of stdout
+    #[arg(long = "json")]
+    json: bool,
+
+    /// The number of largest gaps to show per thread
+    #[arg(long = "largest", default_value = "5")]
+    largest: usize,
+}
+
+#[derive(Parser, Debug)]
+struct GraphOpt {
+    /// The profiles to analyze. Profiles of several processes, e.g. of all
+    /// rustc invocations of a build, are merged into one.
+    #[arg(required = true)]
+    file_prefix: Vec<PathBuf>,
+
+    #[command(flatten)]
+    filter: FilterArgs,
+
+    /// Writes the graph to a json file next to the first <file_prefix> instead of printing DOT
+    #[arg(long = "json")]
+    json: bool,
+
+    /// The number of items with the highest time to include in the graph
+    #[arg(long = "top", default_value = "50")]
+    top: usize,
+}
+
+#[derive(Parser, Debug)]
+struct IncrOpt {
+    /// The profiles to analyze. Profiles of several processes, e.g. of all
+    /// rustc invocations of a build, are merged into one.
+    #[arg(required = true)]
+    file_prefix: Vec<PathBuf>,
+
+    #[command(flatten)]
+    filter: FilterArgs,
+
+    /// Writes the report to a json file next to the first <file_prefix> instead of stdout
+    #[arg(long = "json")]
+    json: bool,
+
+    /// The number of queries with the highest estimated savings to show
+    #[arg(long = "top", default_value = "20")]
+    top: usize,
+}
+
+#[derive(Parser, Debug)]
+struct SeriesOpt {
+    /// The profiles to analyze. Profiles of several processes, e.g. of all
+    /// rustc invocations of a build, are merged into one.
+    #[arg(required = true)]
+    file_prefix: Vec<PathBuf>,
+
+    #[command(flatten)]
+    filter: FilterArgs,
+
+    /// Writes the series to a json file next to the first <file_prefix> instead of printing CSV
+    #[arg(long = "json")]
+    json: bool,
+
+    /// The size of the time windows (in microseconds)
+    #[arg(long = "bucket-size", default_value = "10000")]
+    bucket_size: u64,
+
+    /// Whether to compute the self-time of each item or of each event kind
+    #[arg(long = "by", value_enum, default_value = "label")]
+    by: GroupBy,
+
+    /// The number of items with the highest self-time to show, the others are added up
+    #[arg(long = "top", default_value = "10")]
+    top: usize,
+}
+
 #[derive(Parser, Debug)]
 struct SummarizeOpt {
-    file_prefix: PathBuf,
+    /// The profiles to analyze. Profiles of several processes, e.g. of all
+    /// rustc invocations of a build, are merged into one.
+    #[arg(required = true)]
+    file_prefix: Vec<PathBuf>,
+
+    #[command(flatten)]
+    filter: FilterArgs,
 
-    /// Writes the analysis to a json file next to <file_prefix> instead of stdout
+    /// Writes the analysis to a json file next to the first <file_prefix> instead of stdout
     #[arg(long = "json")]
     json: bool,
 
     /// Filter the output to items whose self-time is greater than this value
     #[arg(short = 'p', long = "percent-above", default_value = "0.0")]
     percent_above: f64,
+
+    /// Break down the self-time of each item by its arguments (e.g. query keys
+    /// recorded with `-Z self-profile-events=query-keys`)
+    #[arg(long = "by-args")]
+    by_args: bool,
+
+    /// The number of argument combinations to show per item with `--by-args`
+    #[arg(long = "top-args", default_value = "10", requires = "by_args")]
+    top_args: usize,
+
+    /// Whether to show the self-time of each item or of each event kind
+    #[arg(
+        long = "group-by",
+        value_enum,
+        default_value = "label",
+        conflicts_with = "by_args"
+    )]
+    group_by: GroupBy,
+
+    /// Show the p50, p90, p99 and maximum self-time and time of the
+    /// invocations of each item. Also adds their histograms to the json output.
+    #[arg(long = "percentiles", conflicts_with_all = ["by_args", "group_by"])]
+    percentiles: bool,
+
+    /// Subtract the cost of recording each event, as measured by the profiler,
+    /// from the self-time of its parent
+    #[arg(long = "correct-overhead")]
+    correct_overhead: bool,
+}
+
+#[derive(Parser, Debug)]
+struct TopOpt {
+    /// The profiles to analyze. Profiles of several processes, e.g. of all
+    /// rustc invocations of a build, are merged into one.
+    #[arg(required = true)]
+    file_prefix: Vec<PathBuf>,
+
+    #[command(flatten)]
+    filter: FilterArgs,
+
+    /// Writes the invocations to a json file next to the first <file_prefix> instead of stdout
+    #[arg(long = "json")]
+    json: bool,
+
+    /// The number of invocations to show
+    #[arg(short = 'n', long = "count", default_value = "20")]
+    count: usize,
+
+    /// Whether to rank the invocations by their self-time or their time
+    #[arg(long = "by", value_enum, default_value = "self-time")]
+    by: TopBy,
+
+    /// Only show invocations of the item with this exact name
+    #[arg(long = "item")]
+    item: Option<String>,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum TopBy {
+    SelfTime,
+    Time,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum GroupBy {
+    Label,
+    Kind,
 }
 
 #[derive(Parser, Debug)]
@@ -51,27 +300,70 @@ enum Opt {
     #[command(name = "aggregate")]
     Aggregate(AggregateOpt),
 
+    /// Shows how many threads were busy over the course of a profile
+    #[command(name = "concurrency")]
+    Concurrency(ConcurrencyOpt),
+
+    /// Shows which items determined the wall-clock time of a multi-threaded profile
+    #[command(name = "critical-path")]
+    CriticalPath(CriticalPathOpt),
+
     #[command(name = "diff")]
     Diff(DiffOpt),
 
+    /// Shows the time between the top-level events of each thread
+    #[command(name = "gaps")]
+    Gaps(GapsOpt),
+
+    /// Prints which items are nested in which others as a Graphviz DOT graph
+    #[command(name = "graph")]
+    Graph(GraphOpt),
+
+    /// Shows how much time incremental compilation saved by loading query results
+    #[command(name = "incr")]
+    Incr(IncrOpt),
+
     /// Processes trace files and produces a summary
     #[command(name = "summarize")]
     Summarize(SummarizeOpt),
+
+    /// Prints the self-time of each item within fixed time windows as CSV
+    #[command(name = "series")]
+    Series(SeriesOpt),
+
+    /// Shows the individual invocations that took longest, with their arguments
+    #[command(name = "top")]
+    Top(TopOpt),
 }
 
-fn process_results(file: &PathBuf) -> Result<AnalysisResults, Box<dyn Error + Send + Sync>> {
+/// Reads the results of a previous analysis from a json file, or analyzes a
+/// profile. The label rules only apply to the latter.
+fn process_results(
+    file: &PathBuf,
+    label_rules: &LabelRules,
+) -> Result<AnalysisResults, Box<dyn Error + Send + Sync>> {
     if file.ends_with("json") {
         let reader = BufReader::new(File::open(&file)?);
 
         let results: AnalysisResults = serde_json::from_reader(reader)?;
         Ok(results)
     } else {
-        let data = ProfilingData::new(&file)?;
+        let data = ProfilingData::new(&file)?.with_label_rules(label_rules.clone());
 
         Ok(data.perform_analysis())
     }
 }
 
+/// Loads the given profiles and merges them into one.
+fn load_profiles(file_prefixes: &[PathBuf]) -> Result<ProfilingData, Box<dyn Error + Send + Sync>> {
+    let profiles = file_prefixes
+        .iter()
+        .map(|file_prefix| ProfilingData::new(file_prefix))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    ProfilingData::merge(profiles)
+}
+
 fn write_results_json(
     file: &PathBuf,
     results: impl Serialize,
@@ -84,32 +376,461 @@ fn write_results_json(
 fn aggregate(opt: AggregateOpt) -> Result<(), Box<dyn Error + Send + Sync>> {
     let profiles = opt
         .files
-        .into_iter()
-        .map(|file| ProfilingData::new(&file))
+        .iter()
+        .map(|file| opt.filter.apply(ProfilingData::new(file)?))
         .collect::<Result<Vec<_>, _>>()?;
 
-    // FIXME(eddyb) return some kind of serializable data structure from `aggregate_profiles`.
-    aggregate::aggregate_profiles(profiles);
+    let results: Vec<_> = profiles
+        .iter()
+        .map(|data| data.perform_analysis())
+        .collect();
+    let aggregated = AggregatedResults::new(&results);
+
+    if opt.json {
+        serde_json::to_writer(std::io::stdout().lock(), &aggregated)?;
+        return Ok(());
+    }
+
+    let counter = &aggregated.counter;
+    let mut table = Table::new();
+    table.set_format(*prettytable::format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+
+    table.set_titles(row!(
+        "Item",
+        "Kind",
+        "Mean self time",
+        "Median",
+        "Std dev",
+        "Min",
+        "Max",
+        "95% confidence interval",
+        "Occurrences",
+    ));
+
+    for query_data in &aggregated.query_data {
+        let stats = &query_data.self_time;
+        let (low, high) = stats.confidence_interval;
+
+        table.add_row(row![
+            query_data.label,
+            query_data.event_kind,
+            counter.format_duration(stats.mean),
+            counter.format_duration(stats.median),
+            counter.format_duration(stats.std_dev),
+            counter.format_duration(stats.min),
+            counter.format_duration(stats.max),
+            format!(
+                "{} - {}",
+                counter.format_duration(low),
+                counter.format_duration(high)
+            ),
+            query_data.occurrences,
+        ]);
+    }
+
+    table.printstd();
+
+    println!(
+        "Total time: {} (± {}) across {} profiles",
+        counter.format_duration(aggregated.total_time.mean),
+        counter.format_duration(aggregated.total_time.std_dev),
+        aggregated.num_profiles
+    );
+    println!();
+
+    // The extrema of individual durations rely on the profiles being
+    // deterministic, which rules out several threads.
+    let multi_threaded = opt.files.iter().zip(&profiles).find(|(_, data)| {
+        let mut thread_ids = data.iter().map(|event| event.thread_id);
+        let first = thread_ids.next();
+        !thread_ids.all(|thread_id| Some(thread_id) == first)
+    });
+
+    if let Some((file, _)) = multi_threaded {
+        eprintln!(
+            "warning: not printing the smallest and largest durations and variances, \
+             which are only supported for single-threaded profiles, but `{}` has \
+             events on several threads",
+            file.display()
+        );
+    } else {
+        // FIXME(eddyb) return some kind of serializable data structure from `aggregate_profiles`.
+        aggregate::aggregate_profiles(&profiles);
+    }
 
     Ok(())
 }
 
-fn diff(opt: DiffOpt) -> Result<(), Box<dyn Error + Send + Sync>> {
-    let base = process_results(&opt.base)?;
-    let change = process_results(&opt.change)?;
+fn concurrency(opt: ConcurrencyOpt) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let data = opt.filter.apply(load_profiles(&opt.file_prefix)?)?;
+    let concurrency = data.concurrency();
+
+    if opt.json {
+        write_results_json(&opt.file_prefix[0], &concurrency)?;
+        return Ok(());
+    }
 
-    let results = diff::calculate_diff(base, change);
+    let counter = &data.metadata().counter;
+    let start = data.metadata().start_time;
+    let wall_time = concurrency.wall_time.as_nanos() as f64;
+    let percent = |time: Duration| time.as_nanos() as f64 / wall_time * 100.0;
+
+    let mut table = Table::new();
+    table.set_format(*prettytable::format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+
+    if opt.timeline {
+        table.set_titles(row!("Start", "Duration", "Busy threads"));
+
+        for step in &concurrency.timeline {
+            table.add_row(row![
+                counter.format_duration(step.start.duration_since(start).unwrap_or_default()),
+                counter.format_duration(step.duration()),
+                step.busy_threads,
+            ]);
+        }
+    } else {
+        table.set_titles(row!("Busy threads", "Time", "% of wall time"));
+
+        for (busy_threads, time) in concurrency.time_by_busy_threads().into_iter().enumerate() {
+            if time == Duration::ZERO {
+                continue;
+            }
+
+            table.add_row(row![
+                busy_threads,
+                counter.format_duration(time),
+                format!("{:.3}", percent(time)),
+            ]);
+        }
+    }
+
+    table.printstd();
+
+    println!(
+        "Wall time: {}",
+        counter.format_duration(concurrency.wall_time)
+    );
+    println!("Threads: {}", concurrency.num_threads);
+    println!(
+        "Average parallelism: {:.3}",
+        concurrency.average_parallelism()
+    );
+    println!("Idle: {:.3}%", concurrency.idle_fraction() * 100.0);
+
+    let stretches = concurrency.longest_serial_stretches(opt.serial_stretches);
+    if !stretches.is_empty() {
+        let mut table = Table::new();
+        table.set_format(*prettytable::format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+        table.set_titles(row!("Serial stretch start", "Duration", "% of wall time"));
+
+        for stretch in stretches {
+            table.add_row(row![
+                counter.format_duration(stretch.start.duration_since(start).unwrap_or_default()),
+                counter.format_duration(stretch.duration()),
+                format!("{:.3}", percent(stretch.duration())),
+            ]);
+        }
+
+        table.printstd();
+    }
+
+    Ok(())
+}
+
+fn gaps(opt: GapsOpt) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let data = opt.filter.apply(load_profiles(&opt.file_prefix)?)?;
+    let gaps = data.gaps(opt.largest);
 
     if opt.json {
-        write_results_json(&opt.change, results)?;
+        write_results_json(&opt.file_prefix[0], &gaps)?;
+        return Ok(());
+    }
+
+    let counter = &data.metadata().counter;
+    let start = data.metadata().start_time;
+
+    let mut table = Table::new();
+    table.set_format(*prettytable::format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+    table.set_titles(row!(
+        "Thread",
+        "Span",
+        "Gaps",
+        "Untracked time",
+        "% of span"
+    ));
+
+    for thread in &gaps {
+        table.add_row(row![
+            thread.thread_id,
+            counter.format_duration(thread.span()),
+            thread.count,
+            counter.format_duration(thread.total),
+            format!("{:.3}", thread.untracked_fraction() * 100.0),
+        ]);
+    }
+
+    table.printstd();
+
+    if gaps.iter().any(|thread| !thread.largest.is_empty()) {
+        let mut table = Table::new();
+        table.set_format(*prettytable::format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+        table.set_titles(row!("Thread", "Gap start", "Duration", "After", "Before"));
+
+        for thread in &gaps {
+            for gap in &thread.largest {
+                table.add_row(row![
+                    thread.thread_id,
+                    counter.format_duration(gap.start.duration_since(start).unwrap_or_default()),
+                    counter.format_duration(gap.duration()),
+                    gap.preceding.label,
+                    gap.following.label,
+                ]);
+            }
+        }
+
+        table.printstd();
+    }
+
+    Ok(())
+}
+
+fn critical_path(opt: CriticalPathOpt) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let data = opt.filter.apply(load_profiles(&opt.file_prefix)?)?;
+    let path = data.critical_path();
+
+    if opt.json {
+        write_results_json(&opt.file_prefix[0], &path)?;
+        return Ok(());
+    }
+
+    let counter = &data.metadata().counter;
+    let wall_time = path.wall_time.as_nanos() as f64;
+
+    let mut table = Table::new();
+    table.set_format(*prettytable::format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+
+    if opt.segments {
+        let start = data.metadata().start_time;
+        table.set_titles(row!("Start", "Thread", "Kind", "Item", "Time"));
+
+        for segment in &path.segments {
+            let offset = segment.start.duration_since(start).unwrap_or_default();
+            table.add_row(row![
+                counter.format_duration(offset),
+                segment.thread_id,
+                segment.event_kind,
+                segment.label,
+                counter.format_duration(segment.duration()),
+            ]);
+        }
+    } else {
+        table.set_titles(row!("Item", "Kind", "Time", "% of wall time"));
+
+        for item in path.time_by_label() {
+            table.add_row(row![
+                item.label,
+                item.event_kind,
+                counter.format_duration(item.time),
+                format!("{:.3}", item.time.as_nanos() as f64 / wall_time * 100.0),
+            ]);
+        }
+    }
+
+    table.printstd();
+
+    println!("Wall time: {}", counter.format_duration(path.wall_time));
+
+    Ok(())
+}
+
+fn incr(opt: IncrOpt) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let data = opt.filter.apply(load_profiles(&opt.file_prefix)?)?;
+    let counter = data.metadata().counter.clone();
+    let report = data.perform_analysis().incremental_report();
+
+    if opt.json {
+        write_results_json(&opt.file_prefix[0], &report)?;
         return Ok(());
     }
 
+    let format_average = |average: Option<Duration>| match average {
+        Some(average) => counter.format_duration(average),
+        None => "-".to_string(),
+    };
+
+    let mut queries: Vec<_> = report
+        .queries
+        .iter()
+        .filter(|query| query.load_count > 0 || query.hashing_time > Duration::ZERO)
+        .collect();
+    queries.sort_by_key(|query| std::cmp::Reverse(query.estimated_savings()));
+
     let mut table = Table::new();
     table.set_format(*prettytable::format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
 
     table.set_titles(row!(
         "Item",
+        "Loads",
+        "Load time",
+        "Avg load time",
+        "Recomputes",
+        "Recompute time",
+        "Avg recompute time",
+        "Hashing time",
+        "% hashing",
+        "Est. savings",
+    ));
+
+    for query in queries.iter().take(opt.top) {
+        table.add_row(row![
+            query.label,
+            query.load_count,
+            counter.format_duration(query.load_time),
+            format_average(query.average_load_time()),
+            query.recompute_count,
+            counter.format_duration(query.recompute_time),
+            format_average(query.average_recompute_time()),
+            counter.format_duration(query.hashing_time),
+            format!("{:.3}", query.hashing_overhead()),
+            format_average(query.estimated_savings()),
+        ]);
+    }
+
+    table.printstd();
+
+    println!(
+        "Load time: {} ({} loads)",
+        counter.format_duration(report.load_time),
+        report.load_count
+    );
+    println!(
+        "Recompute time: {} ({} recomputes)",
+        counter.format_duration(report.recompute_time),
+        report.recompute_count
+    );
+    println!(
+        "Hashing time: {} ({:.3}% of query time)",
+        counter.format_duration(report.hashing_time),
+        report.hashing_overhead()
+    );
+    println!(
+        "Estimated savings from incremental reuse: {}",
+        counter.format_duration(report.estimated_savings)
+    );
+
+    let mut slower: Vec<_> = report.loading_slower_than_recomputing().collect();
+    if !slower.is_empty() {
+        slower.sort_by_key(|query| std::cmp::Reverse(query.load_time));
+
+        let mut table = Table::new();
+        table.set_format(*prettytable::format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+        table.set_titles(row!(
+            "Loading slower than recomputing",
+            "Loads",
+            "Avg load time",
+            "Avg recompute time",
+        ));
+
+        for query in slower {
+            table.add_row(row![
+                query.label,
+                query.load_count,
+                format_average(query.average_load_time()),
+                format_average(query.average_recompute_time()),
+            ]);
+        }
+
+        table.printstd();
+    }
+
+    Ok(())
+}
+
+fn diff(opt: DiffOpt) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let label_rules = match &opt.label_rules {
+        Some(path) => LabelRules::load(path)?,
+        None => LabelRules::new(),
+    };
+
+    let base = std::iter::once(&opt.base)
+        .chain(&opt.base_runs)
+        .map(|file| process_results(file, &label_rules))
+        .collect::<Result<Vec<_>, _>>()?;
+    let change = std::iter::once(&opt.change)
+        .chain(&opt.change_runs)
+        .map(|file| process_results(file, &label_rules))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if let Some(other) = base
+        .iter()
+        .chain(&change)
+        .find(|r| r.counter != base[0].counter)
+    {
+        eprintln!(
+            "Warning: comparing profiles recorded with different counters (`{}` and `{}`)",
+            base[0].counter.name, other.counter.name
+        );
+    }
+
+    let results = diff::calculate_group_diff(&base, &change, opt.significance_level);
+    let with_p_values = results.total_time_p_value.is_some();
+
+    let mut gate_config = match &opt.gate_config {
+        Some(path) => GateConfig::from_file(path)?,
+        None => GateConfig::default(),
+    };
+    gate_config.max_percent = opt.max_regression_percent.or(gate_config.max_percent);
+    gate_config.max_increase_ns = opt.max_regression_ns.or(gate_config.max_increase_ns);
+    gate_config.allow.extend(opt.allow.iter().cloned());
+    gate_config.exclude.extend(opt.exclude.iter().cloned());
+
+    let counter = results.counter.clone();
+    let gate_report = gate_config
+        .is_enabled()
+        .then(|| gate_config.check(&results));
+
+    if opt.json {
+        write_results_json(&opt.change, results)?;
+    } else {
+        print_diff(&opt, results, with_p_values, base.len(), change.len());
+    }
+
+    if let Some(gate_report) = gate_report {
+        if let Some(path) = &opt.gate_report {
+            let file = BufWriter::new(File::create(path)?);
+            serde_json::to_writer_pretty(file, &gate_report)?;
+        }
+
+        if !gate_report.violations.is_empty() {
+            eprintln!("Regressions above the configured thresholds:");
+            for violation in &gate_report.violations {
+                eprintln!(
+                    "  {}: {} ({:+.2}%)",
+                    violation.label.as_deref().unwrap_or("total time"),
+                    violation.change.format_with(&counter),
+                    violation.change_percent
+                );
+            }
+            std::process::exit(1);
+        }
+    }
+
+    Ok(())
+}
+
+fn print_diff(
+    opt: &DiffOpt,
+    results: diff::DiffResults,
+    with_p_values: bool,
+    base_runs: usize,
+    change_runs: usize,
+) {
+    let mut table = Table::new();
+    table.set_format(*prettytable::format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+
+    let mut titles = row!(
+        "Item",
+        "Kind",
         "Self Time",
         "Self Time Change",
         "Time",
@@ -119,7 +840,12 @@ fn diff(opt: DiffOpt) -> Result<(), Box<dyn Error + Send + Sync>> {
         "Blocked time",
         "Incremental load time",
         "Incremental hashing time",
-    ));
+    );
+    if with_p_values {
+        titles.add_cell(Cell::new("p-value"));
+        titles.add_cell(Cell::new("Significant"));
+    }
+    table.set_titles(titles);
 
     let label_max_width = (results.query_data.iter())
         .map(|q| q.label.len())
@@ -138,23 +864,51 @@ fn diff(opt: DiffOpt) -> Result<(), Box<dyn Error + Send + Sync>> {
             continue;
         }
 
-        table.add_row(row![
+        let counter = &results.counter;
+
+        let mut row = row![
             pad(&query_data.label, label_max_width),
-            format!("{:.2?}", query_data.self_time),
+            query_data.event_kind,
+            query_data.self_time.format_with(counter),
             format!("{:+.2}%", query_data.self_time_change),
-            format!("{:.2?}", query_data.time),
+            query_data.time.format_with(counter),
             format!("{:+.2}%", query_data.time_change),
             format!("{:+}", query_data.invocation_count),
             format!("{:+}", query_data.number_of_cache_hits),
-            format!("{:.2?}", query_data.blocked_time),
-            format!("{:.2?}", query_data.incremental_load_time),
-            format!("{:.2?}", query_data.incremental_hashing_time),
-        ]);
+            query_data.blocked_time.format_with(counter),
+            query_data.incremental_load_time.format_with(counter),
+            query_data.incremental_hashing_time.format_with(counter),
+        ];
+        if with_p_values {
+            row.add_cell(Cell::new(&format_p_value(query_data.self_time_p_value)));
+            row.add_cell(Cell::new(if query_data.significant == Some(true) {
+                "yes"
+            } else {
+                "no"
+            }));
+        }
+        table.add_row(row);
     }
 
     table.printstd();
 
-    println!("Total cpu time: {:?}", results.total_time);
+    if results.counter.is_wall_time() {
+        println!("Total cpu time: {:?}", results.total_time);
+    } else {
+        println!(
+            "Total {}: {}",
+            results.counter.name,
+            results.total_time.format_with(&results.counter)
+        );
+    }
+    if with_p_values {
+        println!(
+            "p-value of the total: {} ({} base and {} change profiles)",
+            format_p_value(results.total_time_p_value),
+            base_runs,
+            change_runs
+        );
+    }
 
     let mut table = Table::new();
     table.set_format(*prettytable::format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
@@ -174,18 +928,26 @@ fn diff(opt: DiffOpt) -> Result<(), Box<dyn Error + Send + Sync>> {
     }
 
     table.printstd();
-
-    Ok(())
 }
 
 fn summarize(opt: SummarizeOpt) -> Result<(), Box<dyn Error + Send + Sync>> {
-    let data = ProfilingData::new(&opt.file_prefix)?;
-
-    let mut results = data.perform_analysis();
+    let data = opt.filter.apply(load_profiles(&opt.file_prefix)?)?;
+
+    let options = AnalysisOptions {
+        by_args: opt.by_args,
+        histograms: opt.percentiles,
+        correct_overhead: opt.correct_overhead,
+        ..Default::default()
+    };
+    let mut results = data.perform_analysis_with_options(&options);
+
+    if opt.correct_overhead && results.recording_overhead.is_none() {
+        eprintln!("The profile does not contain a measurement of the recording overhead, so self-times are not corrected");
+    }
 
     //just output the results into a json file
     if opt.json {
-        write_results_json(&opt.file_prefix, &results)?;
+        write_results_json(&opt.file_prefix[0], &results)?;
         return Ok(());
     }
 
@@ -204,6 +966,21 @@ fn summarize(opt: SummarizeOpt) -> Result<(), Box<dyn Error + Send + Sync>> {
         .query_data
         .sort_by(|l, r| r.self_time.cmp(&l.self_time));
 
+    if opt.by_args {
+        print_by_args(&results, percent_above, opt.top_args);
+        return Ok(());
+    }
+
+    if opt.group_by == GroupBy::Kind {
+        print_by_kind(results, percent_above);
+        return Ok(());
+    }
+
+    if opt.percentiles {
+        print_percentiles(&results, percent_above);
+        return Ok(());
+    }
+
     let mut table = Table::new();
     table.set_format(*prettytable::format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
 
@@ -237,6 +1014,7 @@ fn summarize(opt: SummarizeOpt) -> Result<(), Box<dyn Error + Send + Sync>> {
     // to display.
     let columns = &[
         ("Item", true),
+        ("Kind", true),
         ("Self time", true),
         ("% of total time", true),
         ("Time", true),
@@ -274,6 +1052,8 @@ fn summarize(opt: SummarizeOpt) -> Result<(), Box<dyn Error + Send + Sync>> {
 
         format!("{s}{:.<pad$}", " ")
     }
+    let counter = &results.counter;
+
     for query_data in results.query_data {
         let curr_percent = (query_data.self_time.as_nanos() as f64) / total_time * 100.0;
         if curr_percent < percent_above {
@@ -286,24 +1066,25 @@ fn summarize(opt: SummarizeOpt) -> Result<(), Box<dyn Error + Send + Sync>> {
         // data to show.
         table.add_row(Row::new(filter_cells(&[
             (&pad(&query_data.label, label_max_width), true),
-            (&format!("{:.2?}", query_data.self_time), true),
+            (&query_data.event_kind, true),
+            (&counter.format_duration(query_data.self_time), true),
             (&format!("{:.3}", curr_percent), true),
-            (&format!("{:.2?}", query_data.time), true),
+            (&counter.format_duration(query_data.time), true),
             (&format!("{}", query_data.invocation_count), true),
             (
                 &format!("{}", query_data.number_of_cache_hits),
                 has_cache_hits,
             ),
             (
-                &format!("{:.2?}", query_data.blocked_time),
+                &counter.format_duration(query_data.blocked_time),
                 has_blocked_time,
             ),
             (
-                &format!("{:.2?}", query_data.incremental_load_time),
+                &counter.format_duration(query_data.incremental_load_time),
                 has_incremental_load_time,
             ),
             (
-                &format!("{:.2?}", query_data.incremental_hashing_time),
+                &counter.format_duration(query_data.incremental_hashing_time),
                 has_incremental_hashing_time,
             ),
         ])));
@@ -311,7 +1092,22 @@ fn summarize(opt: SummarizeOpt) -> Result<(), Box<dyn Error + Send + Sync>> {
 
     table.printstd();
 
-    println!("Total cpu time: {:?}", results.total_time);
+    if counter.is_wall_time() {
+        println!("Total cpu time: {:?}", results.total_time);
+    } else {
+        println!(
+            "Total {}: {}",
+            counter.name,
+            counter.format_duration(results.total_time)
+        );
+    }
+
+    if let Some(recording_overhead) = results.recording_overhead {
+        println!(
+            "Self-times corrected for a recording overhead of {} per event.",
+            counter.format_duration(recording_overhead)
+        );
+    }
 
     if percent_above != 0.0 {
         println!(
@@ -334,15 +1130,296 @@ fn summarize(opt: SummarizeOpt) -> Result<(), Box<dyn Error + Send + Sync>> {
 
     table.printstd();
 
+    if !results.integer_metrics.is_empty() {
+        let mut table = Table::new();
+        table.set_format(*prettytable::format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+
+        table.set_titles(row!("Metric", "Kind", "Count", "Sum", "Min", "Max", "Last"));
+
+        for metric in results.integer_metrics {
+            table.add_row(row![
+                metric.label,
+                metric.event_kind,
+                metric.count,
+                metric.sum,
+                metric.min,
+                metric.max,
+                metric.last,
+            ]);
+        }
+
+        table.printstd();
+    }
+
+    Ok(())
+}
+
+fn series(opt: SeriesOpt) -> Result<(), Box<dyn Error + Send + Sync>> {
+    if opt.bucket_size == 0 {
+        eprintln!("The bucket size must be greater than zero");
+        std::process::exit(1);
+    }
+
+    let data = opt.filter.apply(load_profiles(&opt.file_prefix)?)?;
+
+    let key = match opt.by {
+        GroupBy::Label => SeriesKey::Label,
+        GroupBy::Kind => SeriesKey::EventKind,
+    };
+    let mut series = data.activity_series(Duration::from_micros(opt.bucket_size), key);
+    series.truncate(opt.top);
+
+    if opt.json {
+        write_results_json(&opt.file_prefix[0], &series)?;
+    } else {
+        series.write_csv(std::io::stdout().lock())?;
+    }
+
+    Ok(())
+}
+
+fn graph(opt: GraphOpt) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let data = opt.filter.apply(load_profiles(&opt.file_prefix)?)?;
+
+    let mut graph = data.call_graph();
+    graph.truncate(opt.top);
+
+    if opt.json {
+        write_results_json(&opt.file_prefix[0], &graph)?;
+    } else {
+        graph.write_dot(std::io::stdout().lock(), &data.metadata().counter)?;
+    }
+
+    Ok(())
+}
+
+fn top(opt: TopOpt) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let data = opt.filter.apply(load_profiles(&opt.file_prefix)?)?;
+
+    let order = match opt.by {
+        TopBy::SelfTime => InvocationOrder::SelfTime,
+        TopBy::Time => InvocationOrder::Time,
+    };
+    let invocations = data.slowest_invocations(opt.count, order, opt.item.as_deref());
+
+    if opt.json {
+        write_results_json(&opt.file_prefix[0], &invocations)?;
+        return Ok(());
+    }
+
+    let counter = &data.metadata().counter;
+    let start = data.metadata().start_time;
+
+    let mut table = Table::new();
+    table.set_format(*prettytable::format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+
+    table.set_titles(row!(
+        "Item",
+        "Kind",
+        "Arguments",
+        "Thread",
+        "Start",
+        "Self time",
+        "Time",
+    ));
+
+    for invocation in &invocations {
+        table.add_row(row![
+            invocation.label,
+            invocation.event_kind,
+            invocation.args.join(", "),
+            invocation.thread_id,
+            counter.format_duration(invocation.start.duration_since(start).unwrap_or_default()),
+            counter.format_duration(invocation.self_time),
+            counter.format_duration(invocation.time),
+        ]);
+    }
+
+    table.printstd();
+
     Ok(())
 }
 
+fn format_p_value(p_value: Option<f64>) -> String {
+    match p_value {
+        Some(p_value) if p_value < 0.001 => "<0.001".to_string(),
+        Some(p_value) => format!("{:.3}", p_value),
+        None => "-".to_string(),
+    }
+}
+
+/// Prints the percentiles of the self-time and time of each item whose
+/// self-time is above `percent_above`. Expects `results.query_data` to be
+/// sorted by descending self-time.
+fn print_percentiles(results: &AnalysisResults, percent_above: f64) {
+    let histograms: FxHashMap<_, _> = results
+        .histograms
+        .iter()
+        .map(|histograms| ((&histograms.event_kind, &histograms.label), histograms))
+        .collect();
+
+    let mut table = Table::new();
+    table.set_format(*prettytable::format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+
+    table.set_titles(row!(
+        "Item",
+        "Count",
+        "Self time p50",
+        "Self time p90",
+        "Self time p99",
+        "Self time max",
+        "Time p50",
+        "Time p90",
+        "Time p99",
+        "Time max",
+    ));
+
+    let total_time = results.total_time.as_nanos() as f64;
+    let counter = &results.counter;
+    let format_percentile = |histogram: &Histogram, percentile: f64| {
+        histogram
+            .percentile(percentile)
+            .map_or_else(|| "-".to_string(), |d| counter.format_duration(d))
+    };
+
+    for query_data in &results.query_data {
+        let percent = (query_data.self_time.as_nanos() as f64) / total_time * 100.0;
+        if percent < percent_above {
+            break;
+        }
+
+        let Some(histograms) = histograms.get(&(&query_data.event_kind, &query_data.label)) else {
+            continue;
+        };
+
+        table.add_row(row![
+            query_data.label,
+            histograms.self_time.count,
+            format_percentile(&histograms.self_time, 50.0),
+            format_percentile(&histograms.self_time, 90.0),
+            format_percentile(&histograms.self_time, 99.0),
+            counter.format_duration(histograms.self_time.max),
+            format_percentile(&histograms.time, 50.0),
+            format_percentile(&histograms.time, 90.0),
+            format_percentile(&histograms.time, 99.0),
+            counter.format_duration(histograms.time.max),
+        ]);
+    }
+
+    table.printstd();
+}
+
+/// Prints the self-time of each event kind whose self-time is above
+/// `percent_above`.
+fn print_by_kind(mut results: AnalysisResults, percent_above: f64) {
+    results
+        .event_kinds
+        .sort_by_key(|event_kind| std::cmp::Reverse(event_kind.self_time));
+
+    let mut table = Table::new();
+    table.set_format(*prettytable::format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+
+    table.set_titles(row!(
+        "Kind",
+        "Self time",
+        "% of total time",
+        "Time",
+        "Item count",
+    ));
+
+    let total_time = results.total_time.as_nanos() as f64;
+    let counter = &results.counter;
+
+    for event_kind in &results.event_kinds {
+        let percent = (event_kind.self_time.as_nanos() as f64) / total_time * 100.0;
+        if percent < percent_above {
+            break;
+        }
+
+        table.add_row(row![
+            event_kind.event_kind,
+            counter.format_duration(event_kind.self_time),
+            format!("{:.3}", percent),
+            counter.format_duration(event_kind.time),
+            event_kind.invocation_count,
+        ]);
+    }
+
+    table.printstd();
+
+    if counter.is_wall_time() {
+        println!("Total cpu time: {:?}", results.total_time);
+    } else {
+        println!(
+            "Total {}: {}",
+            counter.name,
+            counter.format_duration(results.total_time)
+        );
+    }
+}
+
+/// Prints the self-time of the top argument combinations of each item whose
+/// self-time is above `percent_above`. Expects `results.query_data` to be
+/// sorted by descending self-time.
+fn print_by_args(results: &AnalysisResults, percent_above: f64, top_args: usize) {
+    let mut table = Table::new();
+    table.set_format(*prettytable::format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+
+    table.set_titles(row!(
+        "Item",
+        "Kind",
+        "Arguments",
+        "Self time",
+        "% of total time",
+        "Item count",
+        "Cache hits",
+    ));
+
+    let total_time = results.total_time.as_nanos() as f64;
+    let counter = &results.counter;
+
+    for query_data in &results.query_data {
+        let percent = (query_data.self_time.as_nanos() as f64) / total_time * 100.0;
+        if percent < percent_above {
+            break;
+        }
+
+        for args_data in
+            results.top_args_by_self_time(&query_data.event_kind, &query_data.label, top_args)
+        {
+            let data = &args_data.data;
+
+            table.add_row(row![
+                data.label,
+                data.event_kind,
+                args_data.args.join(", "),
+                counter.format_duration(data.self_time),
+                format!(
+                    "{:.3}",
+                    (data.self_time.as_nanos() as f64) / total_time * 100.0
+                ),
+                data.invocation_count,
+                data.number_of_cache_hits,
+            ]);
+        }
+    }
+
+    table.printstd();
+}
+
 fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
     let opt = Opt::parse();
 
     match opt {
         Opt::Summarize(opt) => summarize(opt),
+        Opt::Concurrency(opt) => concurrency(opt),
+        Opt::CriticalPath(opt) => critical_path(opt),
         Opt::Diff(opt) => diff(opt),
+        Opt::Gaps(opt) => gaps(opt),
+        Opt::Graph(opt) => graph(opt),
+        Opt::Incr(opt) => incr(opt),
         Opt::Aggregate(opt) => aggregate(opt),
+        Opt::Series(opt) => series(opt),
+        Opt::Top(opt) => top(opt),
     }
 }